@@ -0,0 +1,82 @@
+// the chunk meshes with a palette, see `PaletteMaterial`. the color of every vertex is looked up
+// within the palette, everything else is the same as the `StandardMaterial` of the chunks.
+#import bevy_pbr::mesh_bindings           mesh
+#import bevy_pbr::mesh_functions          as mesh_functions
+#import bevy_pbr::mesh_view_bindings      view, fog
+#import bevy_pbr::mesh_view_types         FOG_MODE_OFF
+#import bevy_pbr::pbr_functions           as pbr_functions
+#import bevy_pbr::pbr_types               as pbr_types
+#import bevy_core_pipeline::tonemapping   tone_mapping
+
+// only as large as the palette of the mesh itself.
+@group(1) @binding(0)
+var<storage, read> palette: array<vec4<f32>>;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) color_index: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) color: vec4<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.world_position = mesh_functions::mesh_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+    out.position = mesh_functions::mesh_position_world_to_clip(out.world_position);
+    out.world_normal = mesh_functions::mesh_normal_local_to_world(vertex.normal);
+    out.color = palette[vertex.color_index];
+
+    return out;
+}
+
+@fragment
+fn fragment(
+    in: VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> @location(0) vec4<f32> {
+    var pbr_input = pbr_functions::pbr_input_new();
+
+    pbr_input.material.base_color = in.color;
+
+#ifdef PALETTE_BLEND
+    pbr_input.material.flags = pbr_types::STANDARD_MATERIAL_FLAGS_ALPHA_MODE_BLEND;
+#endif
+
+#ifdef PALETTE_UNLIT
+    var output_color = pbr_functions::alpha_discard(pbr_input.material, in.color);
+#else
+#ifdef PALETTE_DOUBLE_SIDED
+    let double_sided = true;
+#else
+    let double_sided = false;
+#endif
+
+    pbr_input.frag_coord = in.position;
+    pbr_input.world_position = in.world_position;
+    pbr_input.world_normal = pbr_functions::prepare_world_normal(in.world_normal, double_sided, is_front);
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+    pbr_input.N = normalize(pbr_input.world_normal);
+    pbr_input.V = pbr_functions::calculate_view(in.world_position, pbr_input.is_orthographic);
+    pbr_input.flags = mesh.flags;
+
+    var output_color = pbr_functions::pbr(pbr_input);
+#endif
+
+    if (fog.mode != FOG_MODE_OFF) {
+        output_color = pbr_functions::apply_fog(fog, output_color, in.world_position.xyz, view.world_position.xyz);
+    }
+
+#ifdef TONEMAP_IN_SHADER
+    output_color = tone_mapping(output_color, view.color_grading);
+#endif
+
+    return output_color;
+}
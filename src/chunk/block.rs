@@ -0,0 +1,97 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{
+    chunk::VoxelFace,
+    events::discovery::ChunkDiscoveryEvent,
+    registry::ChunkRegistry,
+    voxel::{BlockType, Voxel},
+};
+
+/// The color of every face of a block type.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockAppearance {
+    // indexed in the same order as `VoxelFace` is declared in; see `BlockAppearance::face()`.
+    pub faces: [Color; 6],
+}
+
+impl BlockAppearance {
+    /// An appearance which uses the same color for every face.
+    pub fn uniform(color: Color) -> Self {
+        Self { faces: [color; 6] }
+    }
+
+    pub fn face(&self, face: &VoxelFace) -> Color {
+        self.faces[face_index(face)]
+    }
+
+    pub fn set_face(&mut self, face: &VoxelFace, color: Color) {
+        self.faces[face_index(face)] = color;
+    }
+}
+
+impl Default for BlockAppearance {
+    fn default() -> Self {
+        Self::uniform(Color::WHITE)
+    }
+}
+
+/// The appearance of every block type, this is looked up while meshing.
+///
+/// A block type with an appearance is drawn in the colors of that appearance, regardless of the
+/// color of the voxel itself; changing the color of a block type recolors all of its voxels at
+/// once. The block types without one keep the colors the voxels were generated with (such as the
+/// height bands), which is the case for every block type by default.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct BlockRegistry {
+    appearances: HashMap<BlockType, BlockAppearance>,
+}
+
+impl BlockRegistry {
+    pub fn get(&self, block: BlockType) -> BlockAppearance {
+        self.appearances.get(&block).copied().unwrap_or_default()
+    }
+
+    pub fn set(&mut self, block: BlockType, appearance: BlockAppearance) {
+        self.appearances.insert(block, appearance);
+    }
+
+    /// Goes back to the colors the voxels of a block type were generated with.
+    pub fn reset(&mut self, block: BlockType) {
+        self.appearances.remove(&block);
+    }
+
+    pub fn contains(&self, block: BlockType) -> bool {
+        self.appearances.contains_key(&block)
+    }
+
+    /// The color of the given face of a voxel; the color of its block type if it has one, and the
+    /// color of the voxel itself otherwise.
+    pub fn color(&self, voxel: &Voxel, face: &VoxelFace) -> Color {
+        match self.appearances.get(&voxel.block) {
+            Some(appearance) => appearance.face(face),
+            None => voxel.color,
+        }
+    }
+}
+
+fn face_index(face: &VoxelFace) -> usize {
+    match face {
+        VoxelFace::Front => 0,
+        VoxelFace::Back => 1,
+        VoxelFace::Left => 2,
+        VoxelFace::Right => 3,
+        VoxelFace::Up => 4,
+        VoxelFace::Down => 5,
+    }
+}
+
+/// Marks every chunk as dirty whenever the appearance of a block type changes, so the chunks get
+/// meshed again using the new colors.
+pub fn remesh_changed_blocks(
+    registry: Res<ChunkRegistry>,
+    mut discovery_writer: EventWriter<ChunkDiscoveryEvent>,
+) {
+    registry.for_each_chunk_mut(|chunk| chunk.set_dirty(true));
+
+    discovery_writer.send(ChunkDiscoveryEvent);
+}
@@ -1,9 +1,20 @@
-use std::sync::Arc;
+use std::{borrow::Cow, sync::Arc};
 
-use bevy::prelude::{Entity, Handle, Mesh, UVec3};
+use bevy::prelude::{Entity, Handle, IVec3, Mesh, UVec3};
 use enumset::{enum_set, EnumSet, EnumSetType};
+use half::f16;
 
-use super::{registry::Coordinates, voxel::Voxel};
+use super::{
+    block::BlockRegistry,
+    collider::ColliderBox,
+    lighting::MAX_LIGHT,
+    mesh::{self, ChunkMeshes, MeshLayout, MAX_INCREMENTAL_EDITS},
+    palette::PaletteMaterial,
+    registry::Coordinates,
+    surface_nets::{surface_quads, DensitySource, Neighborhood},
+    voxel::{BlockMaterial, Voxel},
+    MeshSettings, MeshTopology,
+};
 
 /// Represents the different faces of a voxel.
 #[derive(Debug, Clone, PartialEq)]
@@ -16,14 +27,62 @@ pub enum VoxelFace {
     Down,
 }
 
+impl VoxelFace {
+    /// Every face, in the order they're declared in.
+    pub const ALL: [VoxelFace; 6] = [
+        VoxelFace::Front,
+        VoxelFace::Back,
+        VoxelFace::Left,
+        VoxelFace::Right,
+        VoxelFace::Up,
+        VoxelFace::Down,
+    ];
+
+    /// The direction the face is facing in, which is the offset towards the neighbor on this side;
+    /// everything that depends on the direction of a face should go through this.
+    pub fn offset(&self) -> IVec3 {
+        match self {
+            VoxelFace::Front => IVec3::Z,
+            VoxelFace::Back => IVec3::NEG_Z,
+            VoxelFace::Left => IVec3::NEG_X,
+            VoxelFace::Right => IVec3::X,
+            VoxelFace::Up => IVec3::Y,
+            VoxelFace::Down => IVec3::NEG_Y,
+        }
+    }
+
+    /// The position of the face within [`ALL`](Self::ALL).
+    pub fn index(&self) -> usize {
+        match self {
+            VoxelFace::Front => 0,
+            VoxelFace::Back => 1,
+            VoxelFace::Left => 2,
+            VoxelFace::Right => 3,
+            VoxelFace::Up => 4,
+            VoxelFace::Down => 5,
+        }
+    }
+}
+
 /// Represents the flags that can be associated with a chunk.
 #[derive(EnumSetType, Debug)]
 pub enum ChunkFlags {
     Generated,
     Dirty,
+    // whether the chunk is currently shown. unloading a chunk only hides its entity and removes
+    // this flag, the entity (and its mesh) is kept around to draw it again later on.
     Drawn,
     Busy,
     Meshed,
+    // the chunk doesn't contain any visible voxels, so there's nothing to mesh or draw. this is
+    // kept up to date whenever the voxels of the chunk change.
+    Empty,
+    // the chunk was meshed as a smooth surface while some of its neighbors weren't generated
+    // (yet), these are treated as air. the chunk gets meshed again once one of them is generated.
+    MissingNeighbors,
+    // a voxel of the chunk was changed after it got generated (e.g. by the player), so it can't
+    // just be generated again; it has to be kept (or saved) as is.
+    Modified,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -33,6 +92,221 @@ pub struct ChunkDimensions {
     pub depth: u32,
 }
 
+impl ChunkDimensions {
+    /// The amount of voxels within a chunk of these dimensions.
+    #[inline]
+    pub fn volume(&self) -> usize {
+        (self.width * self.height * self.depth) as usize
+    }
+
+    /// Gets the index of the voxel at the given (chunk-local) coordinates within the voxels of a
+    /// chunk. the voxels are laid out along the x axis first, then the y axis, then the z axis.
+    #[inline]
+    pub fn local_to_index(&self, UVec3 { x, y, z }: UVec3) -> usize {
+        (x + y * self.width + z * self.width * self.height) as usize
+    }
+
+    /// The inverse of [`local_to_index()`](Self::local_to_index).
+    #[inline]
+    pub fn index_to_local(&self, index: usize) -> UVec3 {
+        let index = index as u32;
+
+        UVec3::new(
+            index % self.width,
+            (index / self.width) % self.height,
+            index / (self.width * self.height),
+        )
+    }
+
+    /// The amount of voxels on the given face of a chunk of these dimensions.
+    pub fn face_area(&self, face: &VoxelFace) -> u32 {
+        match face.offset() {
+            IVec3 { x: 0, y: 0, .. } => self.width * self.height,
+            IVec3 { x: 0, .. } => self.width * self.depth,
+            _ => self.height * self.depth,
+        }
+    }
+
+    /// Iterates over the (chunk-local) coordinates of the voxels on the given face of a chunk of
+    /// these dimensions, which is the outermost layer of voxels on that side.
+    pub fn face_positions(&self, face: &VoxelFace) -> impl Iterator<Item = UVec3> {
+        let size = UVec3::new(self.width, self.height, self.depth);
+        let offset = face.offset();
+
+        // the layer is a single voxel thick along the axis the face is facing in.
+        let min = UVec3::select(offset.cmpgt(IVec3::ZERO), size - 1, UVec3::ZERO);
+        let max = UVec3::select(offset.cmplt(IVec3::ZERO), UVec3::ONE, size);
+
+        (min.z..max.z).flat_map(move |z| {
+            (min.y..max.y).flat_map(move |y| (min.x..max.x).map(move |x| UVec3::new(x, y, z)))
+        })
+    }
+}
+
+/// A mesh of a chunk that's rendered with a different material than the chunk itself.
+///
+/// Submeshes are drawn by a child entity of the chunk entity, so these are shown and hidden
+/// together with the chunk.
+#[derive(Debug, Clone)]
+pub struct SubMesh {
+    pub material: BlockMaterial,
+    // this is `None` if the chunk no longer contains any voxels of this material.
+    pub mesh: Option<Handle<Mesh>>,
+    // same as `Chunk::palette`, for this mesh.
+    pub palette: Option<Handle<PaletteMaterial>>,
+    pub entity: Option<Entity>,
+}
+
+/// How the voxels of a chunk are stored.
+///
+/// Most chunks are either entirely air or (deep below the surface) entirely solid, so there's no
+/// point in keeping a voxel around for every single position within these. A uniform chunk is
+/// turned into a dense one as soon as a different voxel is written into it.
+#[derive(Debug, Clone)]
+pub enum VoxelStorage {
+    Uniform(Voxel),
+    // this is an Arc<T> to avoid cloning; as we pass this into a new thread.
+    Dense(Arc<Vec<Voxel>>),
+}
+
+impl VoxelStorage {
+    /// Gets the voxel at the given index, `volume` being the amount of voxels within the chunk.
+    pub fn get(&self, index: usize, volume: usize) -> Option<&Voxel> {
+        match self {
+            VoxelStorage::Uniform(voxel) => (index < volume).then_some(voxel),
+            VoxelStorage::Dense(voxels) => voxels.get(index),
+        }
+    }
+
+    /// Every single voxel, using the layout of [`ChunkDimensions::local_to_index()`]. a uniform
+    /// chunk has to be expanded for this.
+    pub fn to_dense(&self, volume: usize) -> Cow<'_, Vec<Voxel>> {
+        match self {
+            VoxelStorage::Uniform(voxel) => Cow::Owned(vec![*voxel; volume]),
+            VoxelStorage::Dense(voxels) => Cow::Borrowed(voxels),
+        }
+    }
+
+    /// The voxel every position is filled with, if the chunk is uniform.
+    pub fn uniform(&self) -> Option<Voxel> {
+        match self {
+            VoxelStorage::Uniform(voxel) => Some(*voxel),
+            VoxelStorage::Dense(_) => None,
+        }
+    }
+}
+
+/// Everything a chunk is meshed from, taken through [`Chunk::snapshot()`].
+///
+/// The buffers are shared with the chunk rather than copied, so taking a snapshot is cheap. It can
+/// be moved into a meshing task, while the chunk itself stays in the registry.
+#[derive(Debug, Clone)]
+pub struct ChunkSnapshot {
+    pub voxels: VoxelStorage,
+    pub light: Arc<Vec<u8>>,
+    pub density: Arc<Vec<f16>>,
+    pub occupancy: Arc<Vec<u32>>,
+    pub dimensions: ChunkDimensions,
+    pub lod: u32,
+    // the voxels that were edited since the chunk was last meshed, along with the layout of that
+    // mesh; see `Chunk::mark_edited()`.
+    pub edits: Vec<IVec3>,
+    pub layout: Option<Arc<MeshLayout>>,
+    // the revision of the chunk this was taken at, see `Chunk::finish_meshing()`.
+    pub revision: u64,
+    // the chunks around this one, which only a smooth surface needs; see `Neighborhood::gather()`.
+    pub neighbors: Neighborhood,
+}
+
+impl ChunkSnapshot {
+    /// Every voxel of the chunk; a uniform chunk is only expanded here, off the main thread.
+    pub fn voxels(&self) -> Cow<'_, Vec<Voxel>> {
+        self.voxels.to_dense(self.dimensions.volume())
+    }
+
+    /// Meshes the chunk at its level of detail; this is the one place the voxels, light and
+    /// occupancy of a chunk are handed to [`mesh::mesh()`](super::mesh::mesh).
+    ///
+    /// A chunk that only had a couple of voxels edited since it was last meshed is meshed
+    /// incrementally, by reusing the layout of its previous mesh; see [`MeshLayout::remesh()`].
+    /// Only the chunks that got edited keep their layout around, as these are the ones likely to
+    /// be edited again.
+    pub fn mesh(&self, settings: MeshSettings, blocks: &BlockRegistry) -> Option<ChunkMeshes> {
+        let voxels = self.voxels();
+
+        // a smooth surface reaches into the neighbors, so it can't be meshed incrementally; the
+        // voxels that aren't part of it are still meshed as faces.
+        if settings.topology == MeshTopology::Smooth {
+            let mut neighborhood = self.neighbors.clone();
+            neighborhood.set(
+                IVec3::ZERO,
+                Some(DensitySource {
+                    voxels: self.voxels.clone(),
+                    density: self.density.clone(),
+                }),
+            );
+
+            let mut layout = mesh::mesh_layout(
+                &voxels,
+                &self.light,
+                &self.occupancy,
+                self.lod,
+                &settings,
+                blocks,
+                &self.dimensions,
+            );
+
+            layout.push_surface(
+                &surface_quads(&neighborhood, &self.dimensions, self.lod),
+                &self.light,
+                blocks,
+                &self.dimensions,
+            );
+
+            return layout.into_meshes(&settings);
+        }
+        let keep_layout = self.lod == 0 && (self.layout.is_some() || !self.edits.is_empty());
+
+        let layout = match &self.layout {
+            Some(layout) if !self.edits.is_empty() && layout.is_reusable(self.lod, &settings) => {
+                layout.remesh(
+                    &voxels,
+                    &self.light,
+                    &self.edits,
+                    &settings,
+                    blocks,
+                    &self.dimensions,
+                )
+            }
+            _ if keep_layout => mesh::mesh_layout(
+                &voxels,
+                &self.light,
+                &self.occupancy,
+                self.lod,
+                &settings,
+                blocks,
+                &self.dimensions,
+            ),
+            _ => {
+                return mesh::mesh(
+                    &voxels,
+                    &self.light,
+                    &self.occupancy,
+                    self.lod,
+                    settings,
+                    blocks,
+                    &self.dimensions,
+                )
+            }
+        };
+
+        let mut meshes = layout.build(&settings)?;
+        meshes.layout = Some(Arc::new(layout));
+
+        Some(meshes)
+    }
+}
+
 /// Represents a chunk of voxels in a 3D space.
 ///
 /// A `Chunk` is a fundamental unit of a 3D voxel space. It contains voxel data, mesh information,
@@ -58,15 +332,49 @@ pub struct ChunkDimensions {
 ///
 /// # Fields
 ///
-/// - `voxels`: An `Arc` (atomic reference-counted) vector of `Voxel` instances. This field is
-///   used to store the voxel data for the chunk efficiently, as it can be shared among threads
+/// - `voxels`: The voxel data of the chunk, see [`VoxelStorage`]. A chunk that consists of a
+///   single kind of voxel (such as air) only stores that voxel, every other chunk stores an `Arc`
+///   (atomic reference-counted) vector of `Voxel` instances, which can be shared among threads
 ///   without cloning the data.
 ///
+/// - `light`: An `Arc` vector containing the light level of every voxel, using the same layout as
+///   `voxels`. See [`propagate_light()`](../lighting/fn.propagate_light.html). This is empty until
+///   the light is propagated (and for uniform chunks), in which case everything is fully lit.
+///
+/// - `density`: An `Arc` vector containing the terrain density of every voxel, using the same
+///   layout as `voxels`; see [`terrain_density()`](../generation/fn.terrain_density.html). This is
+///   what a smooth surface can be built from, rather than cubes. Only generated chunks have one,
+///   every other voxel falls back to [`fallback_density()`]; see `density_at()`.
+///
+/// - `occupancy`: The amount of visible voxels within every y layer of the chunk. This is kept up
+///   to date by `set_voxel()` and `set_voxels()`, and is used by the mesher to skip empty layers
+///   altogether.
+///
+/// - `solid`: The amount of solid voxels within the chunk, kept up to date together with
+///   `occupancy`. Used to tell whether a chunk blocks the view entirely, see `is_full()`.
+///
+/// - `solid_faces`: The amount of solid voxels on every face of the chunk, indexed by
+///   `VoxelFace::index()`. A face that's entirely solid hides whatever is behind it on that side,
+///   see `is_face_solid()`. Kept up to date together with `solid`.
+///
 /// - `dimensions`: A `ChunkDimensions` struct that defines the size and shape of the chunk. This
 ///   is created using the provided (width, height, depth)
 ///
 /// - `mesh`: An optional `Handle<Mesh>` representing the mesh associated with this chunk. This
-///   gets re-used if the chunk is not dirty, but has to get re-rendered.
+///   gets re-used if the chunk is not dirty, but has to get re-rendered. This is `None` for
+///   chunks that have been meshed, but didn't produce any opaque geometry.
+///
+/// - `palette`: The material the `mesh` is drawn with if its colors got compressed, see
+///   `MeshSettings::palette`. The mesh is drawn with a regular `StandardMaterial` otherwise.
+///
+/// - `submeshes`: The meshes of the voxels that are rendered with a different material than the
+///   `mesh`, such as water; see [`SubMesh`]. There's only an entry for the materials the chunk has
+///   contained at some point.
+///
+/// - `colliders`: The collision boxes of the chunk, see
+///   [`greedy_boxes()`](../collider/fn.greedy_boxes.html). These are rebuilt together with the
+///   mesh, so they're always in sync with what's being rendered. Empty unless
+///   `MeshSettings::colliders` is enabled.
 ///
 /// - `flags`: An `EnumSet<ChunkFlags>` that contains flags to control various behaviors and
 ///   properties of the chunk.
@@ -81,6 +389,12 @@ pub struct ChunkDimensions {
 ///   the rendering detail of the chunk, with lower values indicating higher (or lower, can't
 ///   remember) detail.
 ///
+/// - `generated_lod`: The level of detail the voxels of the chunk were generated at. Distant chunks
+///   are generated at a lower resolution, these have to be generated again once they get closer.
+///
+/// - `drawn_lod`: The level of detail of the mesh that's currently drawn, if any. Once the chunk
+///   gets drawn at a different one, the previous mesh is faded out; see `draw_chunks`.
+///
 /// # Thread Safety
 ///
 /// The use of `Arc` for the `voxels` field ensures that the voxel data can be safely shared among
@@ -103,47 +417,126 @@ pub struct ChunkDimensions {
 /// - [`Coordinates`](struct.Coordinates.html): Represents 3D coordinates in the world space.
 #[derive(Debug, Clone)]
 pub struct Chunk {
-    // this is an Arc<T> to avoid cloning; as we pass this into a new thread.
-    pub voxels: Arc<Vec<Voxel>>,
+    pub voxels: VoxelStorage,
+    // same as the voxels; this gets passed into the meshing thread.
+    pub light: Arc<Vec<u8>>,
+    // same as the voxels, or empty if the chunk wasn't generated as is; see `density_at()`.
+    pub density: Arc<Vec<f16>>,
+    // same as the voxels; this gets passed into the meshing thread.
+    pub occupancy: Arc<Vec<u32>>,
+    // the amount of solid voxels within the chunk, kept up to date the same way as `occupancy`.
+    pub solid: u32,
+    // the amount of solid voxels on every face of the chunk, indexed by `VoxelFace::index()`.
+    pub solid_faces: [u32; 6],
     pub dimensions: ChunkDimensions,
     pub mesh: Option<Handle<Mesh>>,
+    pub palette: Option<Handle<PaletteMaterial>>,
+    pub submeshes: Vec<SubMesh>,
+    pub colliders: Arc<Vec<ColliderBox>>,
     pub flags: EnumSet<ChunkFlags>,
     // keep track of the current entity to avoid spawning new entities for every respawn
     // this is used to render the entity, by inserting the material components through bevy.
     pub entity: Option<Entity>,
     pub world_position: Coordinates,
     pub lod: u32,
+    // the level of detail the voxels were generated at; see `generate_voxels()`.
+    pub generated_lod: u32,
+    // the level of detail of the mesh the entity is drawing; see `draw_chunks()`.
+    pub drawn_lod: Option<u32>,
+    // the voxels (in local coordinates) that were edited since the chunk was last meshed, see
+    // `mark_edited()`. this is empty for a dirty chunk that has to be meshed entirely.
+    pub edits: Vec<IVec3>,
+    // the layout of the current mesh, which is only kept around for the chunks that got edited;
+    // see `ChunkSnapshot::mesh()`.
+    pub layout: Option<Arc<MeshLayout>>,
+    // goes up every time the chunk is marked as dirty, so a mesh can tell whether the chunk
+    // changed while it was being built; see `finish_meshing()`.
+    pub revision: u64,
 }
 
 impl Chunk {
     pub fn new(width: u32, height: u32, depth: u32, world_position: Coordinates) -> Self {
-        let num_voxels = width * height * depth;
-        let voxels = vec![Voxel::default(); num_voxels as usize];
-
-        Self {
-            voxels: Arc::new(voxels),
-            dimensions: ChunkDimensions {
-                width,
-                height,
-                depth,
-            },
+        let dimensions = ChunkDimensions {
+            width,
+            height,
+            depth,
+        };
+
+        let mut chunk = Self {
+            voxels: VoxelStorage::Uniform(Voxel::default()),
+            light: Arc::new(Vec::new()),
+            density: Arc::new(Vec::new()),
+            occupancy: Arc::new(Vec::new()),
+            solid: 0,
+            solid_faces: [0; 6],
+            dimensions,
             world_position,
             mesh: None,
+            palette: None,
+            submeshes: Vec::new(),
+            colliders: Arc::new(Vec::new()),
             lod: 0,
+            generated_lod: 0,
+            drawn_lod: None,
+            edits: Vec::new(),
+            layout: None,
+            revision: 0,
             entity: None,
             flags: enum_set!(),
-        }
+        };
+
+        chunk.set_uniform(Voxel::default());
+        chunk
+    }
+
+    /// Creates a chunk using an existing buffer of voxels, such as one from the
+    /// [`VoxelBufferPool`](super::pool::VoxelBufferPool). the buffer has to contain exactly
+    /// `width * height * depth` voxels.
+    pub fn with_voxels(
+        width: u32,
+        height: u32,
+        depth: u32,
+        world_position: Coordinates,
+        voxels: Vec<Voxel>,
+    ) -> Self {
+        debug_assert_eq!(voxels.len(), (width * height * depth) as usize);
+
+        let mut chunk = Self::new(width, height, depth, world_position);
+
+        chunk.replace_voxels(voxels);
+        chunk
     }
 
     pub fn get_voxel(&self, coordinates: impl Into<UVec3>) -> Option<&Voxel> {
         let UVec3 { x, y, z } = coordinates.into();
         let index = self.get_index([x, y, z]);
 
-        return self.voxels.get(index as usize);
+        return self.voxels.get(index, self.dimensions.volume());
+    }
+
+    /// Every voxel of the chunk, see [`VoxelStorage::to_dense()`].
+    pub fn get_voxels(&self) -> Cow<'_, Vec<Voxel>> {
+        self.voxels.to_dense(self.dimensions.volume())
     }
 
-    pub fn get_voxels<'a>(&self) -> &Vec<Voxel> {
-        &self.voxels
+    /// Iterates over every voxel of the chunk, alongside its world position.
+    pub fn iter_voxels(&self) -> impl Iterator<Item = (Coordinates, &Voxel)> + '_ {
+        let dimensions = self.dimensions;
+        let world_position = self.world_position;
+
+        (0..dimensions.volume()).filter_map(move |index| {
+            let voxel = self.voxels.get(index, dimensions.volume())?;
+
+            Some((
+                world_position + dimensions.index_to_local(index).as_ivec3(),
+                voxel,
+            ))
+        })
+    }
+
+    /// Iterates over the solid voxels of the chunk, alongside their world position.
+    pub fn iter_solid(&self) -> impl Iterator<Item = (Coordinates, &Voxel)> + '_ {
+        self.iter_voxels().filter(|(_, voxel)| voxel.is_solid())
     }
 
     pub fn set_voxel(&mut self, coordinates: impl Into<UVec3>, voxel: Voxel) {
@@ -156,38 +549,316 @@ impl Chunk {
 
         if x < width && y < height && z < depth {
             let index = self.get_index([x, y, z]);
-            let mut_data = Arc::get_mut(&mut self.voxels);
 
-            if let Some(value) = mut_data {
-                value[index as usize] = voxel;
+            if let Some(uniform) = self.voxels.uniform() {
+                if uniform == voxel {
+                    return;
+                }
+
+                self.split_uniform(uniform);
+            }
+
+            let VoxelStorage::Dense(voxels) = &mut self.voxels else {
+                unreachable!("the chunk was just made dense");
+            };
+
+            // the voxels are cloned if a task is still holding on to them (such as a mesh task),
+            // otherwise the change would get lost.
+            let value = Arc::make_mut(voxels);
+
+            let previous = std::mem::replace(&mut value[index], voxel);
+
+            // the noise has nothing to do with an edited voxel, it's either entirely within the
+            // terrain or entirely outside of it.
+            if previous.is_solid() != voxel.is_solid() && !self.density.is_empty() {
+                Arc::make_mut(&mut self.density)[index] = fallback_density(&voxel);
+            }
+
+            let occupancy = Arc::make_mut(&mut self.occupancy);
+
+            match (previous.is_visible(), voxel.is_visible()) {
+                (false, true) => occupancy[y as usize] += 1,
+                (true, false) => occupancy[y as usize] -= 1,
+                _ => {}
+            }
+
+            match (previous.is_solid(), voxel.is_solid()) {
+                (false, true) => self.solid += 1,
+                (true, false) => self.solid -= 1,
+                _ => {}
+            }
+
+            // only the faces the voxel lies on are affected, a voxel within the chunk isn't on
+            // any of them.
+            if previous.is_solid() != voxel.is_solid() {
+                for face in VoxelFace::ALL {
+                    if mesh::get_neighbor([x, y, z], &face, &self.dimensions).is_some() {
+                        continue;
+                    }
+
+                    match voxel.is_solid() {
+                        true => self.solid_faces[face.index()] += 1,
+                        false => self.solid_faces[face.index()] -= 1,
+                    }
+                }
+            }
+
+            self.update_empty();
+
+            // the voxels written by the generator go through `set_voxels()`, anything after that
+            // is an edit.
+            if self.is_generated() {
+                self.set_modified(true);
             }
         }
     }
 
     pub fn set_voxels(&mut self, voxels: impl Into<Vec<Voxel>>) {
-        self.voxels = Arc::new(voxels.into());
+        self.replace_voxels(voxels);
     }
 
-    fn get_index(&self, coordinates: impl Into<UVec3>) -> u32 {
-        let UVec3 { x, y, z } = coordinates.into();
-        let ChunkDimensions { width, height, .. } = self.dimensions;
+    /// Replaces the voxels of the chunk, returning the previous buffer so it can be reused. the
+    /// previous buffer can't be returned while a mesh task is still holding on to it, in which
+    /// case it's dropped as soon as that task is done with it.
+    pub fn replace_voxels(&mut self, voxels: impl Into<Vec<Voxel>>) -> Option<Vec<Voxel>> {
+        let voxels = voxels.into();
+
+        self.occupancy = Arc::new(count_occupancy(&voxels, &self.dimensions));
+        self.solid = count_solid(&voxels);
+        self.solid_faces = count_solid_faces(&voxels, &self.dimensions);
+        // the density belongs to the previous voxels, see `set_density()`.
+        self.density = Arc::new(Vec::new());
+        self.update_empty();
+
+        let previous = std::mem::replace(&mut self.voxels, VoxelStorage::Dense(Arc::new(voxels)));
+
+        Self::take_buffer(previous)
+    }
+
+    /// Fills the entire chunk with a single voxel, without allocating a voxel for every position;
+    /// see [`VoxelStorage`]. just like [`replace_voxels()`](Self::replace_voxels), the previous
+    /// buffer is returned so it can be reused.
+    pub fn set_uniform(&mut self, voxel: Voxel) -> Option<Vec<Voxel>> {
+        let ChunkDimensions {
+            width,
+            height,
+            depth,
+        } = self.dimensions;
+
+        let layer = match voxel.is_visible() {
+            true => width * depth,
+            false => 0,
+        };
+
+        self.occupancy = Arc::new(vec![layer; height as usize]);
+        self.solid = match voxel.is_solid() {
+            true => self.dimensions.volume() as u32,
+            false => 0,
+        };
+        self.solid_faces = VoxelFace::ALL.map(|face| match voxel.is_solid() {
+            true => self.dimensions.face_area(&face),
+            false => 0,
+        });
+        // there's nothing within the chunk to block the sky or to emit light, besides the voxel
+        // itself.
+        self.light = Arc::new(Vec::new());
+        self.density = Arc::new(Vec::new());
+        self.update_empty();
+
+        let previous = std::mem::replace(&mut self.voxels, VoxelStorage::Uniform(voxel));
+
+        Self::take_buffer(previous)
+    }
+
+    // the first voxel that differs from the rest turns a uniform chunk into a dense one.
+    fn split_uniform(&mut self, uniform: Voxel) {
+        let volume = self.dimensions.volume();
+
+        self.voxels = VoxelStorage::Dense(Arc::new(vec![uniform; volume]));
+
+        // this is the light level `propagate_light()` would've given every voxel, the light isn't
+        // propagated again after an edit.
+        if self.light.is_empty() {
+            let light = match uniform.is_solid() {
+                true => uniform.emission.min(MAX_LIGHT),
+                false => MAX_LIGHT,
+            };
+
+            self.light = Arc::new(vec![light; volume]);
+        }
+    }
+
+    // the previous buffer can't be taken while a mesh task is still holding on to it.
+    fn take_buffer(previous: VoxelStorage) -> Option<Vec<Voxel>> {
+        match previous {
+            VoxelStorage::Dense(voxels) => Arc::try_unwrap(voxels).ok(),
+            VoxelStorage::Uniform(_) => None,
+        }
+    }
+
+    fn update_empty(&mut self) {
+        let empty = self.occupancy.iter().all(|count| *count == 0);
+        self.set_flag(ChunkFlags::Empty, empty);
+    }
 
-        x + y * width + z * width * height
+    /// Whether every single voxel of the chunk is solid, meaning nothing behind it can be seen
+    /// through it.
+    pub fn is_full(&self) -> bool {
+        self.solid as usize == self.dimensions.volume()
+    }
+
+    /// Whether every voxel on the given face of the chunk is solid, meaning nothing can be seen
+    /// through the chunk from that side; the neighbor on that side can't see into it either.
+    ///
+    /// This only looks at the outermost layer of voxels, so it's a lot more likely to be true than
+    /// [`is_full()`](Self::is_full) is, such as for the bottom of a chunk on the surface.
+    pub fn is_face_solid(&self, face: &VoxelFace) -> bool {
+        self.solid_faces[face.index()] == self.dimensions.face_area(face)
+    }
+
+    /// Whether the given y layer of the chunk doesn't contain any visible voxels.
+    pub fn is_layer_empty(&self, y: u32) -> bool {
+        self.occupancy
+            .get(y as usize)
+            .is_none_or(|count| *count == 0)
+    }
+
+    pub fn get_light(&self) -> &Vec<u8> {
+        &self.light
+    }
+
+    pub fn set_light(&mut self, light: impl Into<Vec<u8>>) {
+        self.light = Arc::new(light.into());
+    }
+
+    /// Sets the density of every voxel, using the same layout as the voxels. This has to come
+    /// after the voxels themselves are set, which forget the previous density.
+    pub fn set_density(&mut self, density: impl Into<Vec<f16>>) {
+        self.density = Arc::new(density.into());
+    }
+
+    /// The terrain density of the voxel at the given (chunk-local) coordinates; positive within
+    /// the terrain, negative outside of it and zero right at its surface.
+    pub fn density_at(&self, coordinates: impl Into<UVec3>) -> Option<f16> {
+        let coordinates = coordinates.into();
+        let voxel = self.get_voxel(coordinates)?;
+
+        Some(match self.density.get(self.get_index(coordinates)) {
+            Some(density) => *density,
+            None => fallback_density(voxel),
+        })
+    }
+
+    fn get_index(&self, coordinates: impl Into<UVec3>) -> usize {
+        self.dimensions.local_to_index(coordinates.into())
     }
 
     pub fn get_dimensions<'a>(&'a self) -> &'a ChunkDimensions {
         return &self.dimensions;
     }
 
-    pub fn set_mesh(&mut self, mesh: Handle<Mesh>) {
-        self.mesh = Some(mesh);
+    /// Takes a snapshot of everything the chunk is meshed from, see [`ChunkSnapshot`].
+    pub fn snapshot(&self) -> ChunkSnapshot {
+        ChunkSnapshot {
+            voxels: self.voxels.clone(),
+            light: self.light.clone(),
+            density: self.density.clone(),
+            occupancy: self.occupancy.clone(),
+            dimensions: self.dimensions,
+            lod: self.lod,
+            edits: self.edits.clone(),
+            layout: self.layout.clone(),
+            revision: self.revision,
+            neighbors: Neighborhood::default(),
+        }
+    }
+
+    pub fn set_mesh(&mut self, mesh: Option<Handle<Mesh>>) {
+        self.mesh = mesh;
         self.flags.insert(ChunkFlags::Meshed);
     }
 
+    /// Marks the chunk as meshed without a mesh, for chunks that don't produce any geometry.
+    pub fn clear_mesh(&mut self) {
+        self.mesh = None;
+        self.palette = None;
+        self.layout = None;
+        self.flags.insert(ChunkFlags::Meshed);
+
+        for submesh in &mut self.submeshes {
+            submesh.mesh = None;
+            submesh.palette = None;
+        }
+    }
+
     pub fn get_mesh(&self) -> Option<Handle<Mesh>> {
         self.mesh.as_ref().map(|mesh| mesh.clone())
     }
 
+    pub fn set_palette(&mut self, palette: Option<Handle<PaletteMaterial>>) {
+        self.palette = palette;
+    }
+
+    pub fn get_palette(&self) -> Option<Handle<PaletteMaterial>> {
+        self.palette.clone()
+    }
+
+    pub fn set_layout(&mut self, layout: Option<Arc<MeshLayout>>) {
+        self.layout = layout;
+    }
+
+    /// Replaces the meshes of the submeshes. The submeshes of the materials that aren't part of
+    /// `meshes` lose their mesh, but keep their entity so it can be reused later on.
+    pub fn set_submeshes(
+        &mut self,
+        meshes: Vec<(BlockMaterial, Handle<Mesh>, Option<Handle<PaletteMaterial>>)>,
+    ) {
+        for submesh in &mut self.submeshes {
+            submesh.mesh = None;
+            submesh.palette = None;
+        }
+
+        for (material, mesh, palette) in meshes {
+            match self.get_submesh_mut(material) {
+                Some(submesh) => {
+                    submesh.mesh = Some(mesh);
+                    submesh.palette = palette;
+                }
+                None => self.submeshes.push(SubMesh {
+                    material,
+                    mesh: Some(mesh),
+                    palette,
+                    entity: None,
+                }),
+            }
+        }
+    }
+
+    pub fn get_submesh(&self, material: BlockMaterial) -> Option<&SubMesh> {
+        self.submeshes
+            .iter()
+            .find(|submesh| submesh.material == material)
+    }
+
+    pub fn get_submesh_mut(&mut self, material: BlockMaterial) -> Option<&mut SubMesh> {
+        self.submeshes
+            .iter_mut()
+            .find(|submesh| submesh.material == material)
+    }
+
+    /// The entities of the submeshes that have been drawn before.
+    pub fn submesh_entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.submeshes.iter().filter_map(|submesh| submesh.entity)
+    }
+
+    pub fn get_colliders(&self) -> &Vec<ColliderBox> {
+        &self.colliders
+    }
+
+    pub fn set_colliders(&mut self, colliders: impl Into<Vec<ColliderBox>>) {
+        self.colliders = Arc::new(colliders.into());
+    }
+
     pub fn get_entity(&self) -> Option<Entity> {
         return self.entity;
     }
@@ -196,6 +867,19 @@ impl Chunk {
         self.entity = Some(entity);
     }
 
+    /// Forgets the entity of the chunk (and those of its submeshes), returning the entity so it
+    /// can be despawned; the submeshes are its children, these go along with it. the chunk is no
+    /// longer drawn, so it gets drawn again (with a new entity) through discovery.
+    pub fn clear_entity(&mut self) -> Option<Entity> {
+        for submesh in &mut self.submeshes {
+            submesh.entity = None;
+        }
+
+        self.set_drawn(false);
+        self.drawn_lod = None;
+        self.entity.take()
+    }
+
     pub fn is_generated(&self) -> bool {
         self.flags.contains(ChunkFlags::Generated)
     }
@@ -212,6 +896,14 @@ impl Chunk {
         self.flags.contains(ChunkFlags::Drawn)
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.flags.contains(ChunkFlags::Empty)
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.flags.contains(ChunkFlags::Modified)
+    }
+
     pub fn set_flag(&mut self, flag: ChunkFlags, value: bool) {
         if value {
             self.flags |= flag;
@@ -225,7 +917,51 @@ impl Chunk {
     }
 
     pub fn set_dirty(&mut self, dirty: bool) {
+        // either the chunk is meshed entirely now, or it just got meshed; the edits are covered by
+        // that mesh either way.
+        self.edits.clear();
         self.set_flag(ChunkFlags::Dirty, dirty);
+
+        if dirty {
+            self.revision += 1;
+        }
+    }
+
+    /// Clears the dirty flag once the chunk got meshed from a snapshot taken at the given
+    /// revision, which covered the given amount of edits; see [`ChunkSnapshot`].
+    ///
+    /// The chunk can be edited (or marked as dirty) while the mesh is being built, in which case
+    /// it stays dirty. Only the edits the mesh covers are dropped, the newer ones are meshed the
+    /// next time around.
+    pub fn finish_meshing(&mut self, revision: u64, edits: usize) {
+        if self.revision == revision {
+            self.set_dirty(false);
+            return;
+        }
+
+        // edits are only ever appended, unless the chunk has to be meshed entirely anyway.
+        let covered = edits.min(self.edits.len());
+        self.edits.drain(..covered);
+    }
+
+    /// Marks the chunk as dirty because the voxel at the given local position was edited, which
+    /// can be just outside of the chunk for the voxels on the edge of a neighbor. Only the voxels
+    /// around the edits are meshed again, rather than the entire chunk; see
+    /// [`ChunkSnapshot::mesh()`].
+    ///
+    /// A chunk that's already waiting to be meshed entirely stays that way, as does a chunk with
+    /// more than [`MAX_INCREMENTAL_EDITS`] edits.
+    pub fn mark_edited(&mut self, position: IVec3) {
+        let full = self.is_dirty() && self.edits.is_empty();
+
+        if full || self.edits.len() >= MAX_INCREMENTAL_EDITS {
+            self.set_dirty(true);
+            return;
+        }
+
+        self.edits.push(position);
+        self.flags.insert(ChunkFlags::Dirty);
+        self.revision += 1;
     }
 
     pub fn set_busy(&mut self, busy: bool) {
@@ -236,6 +972,10 @@ impl Chunk {
         self.set_flag(ChunkFlags::Drawn, drawn);
     }
 
+    pub fn set_modified(&mut self, modified: bool) {
+        self.set_flag(ChunkFlags::Modified, modified);
+    }
+
     pub fn apply_mask(&mut self, flags: EnumSet<ChunkFlags>) {
         self.flags ^= flags;
     }
@@ -251,4 +991,240 @@ impl Chunk {
     pub fn get_lod(&mut self) -> u32 {
         return self.lod;
     }
+
+    pub fn set_generated_lod(&mut self, lod: u32) {
+        self.generated_lod = lod;
+    }
+
+    pub fn get_generated_lod(&self) -> u32 {
+        return self.generated_lod;
+    }
+
+    pub fn set_drawn_lod(&mut self, lod: Option<u32>) {
+        self.drawn_lod = lod;
+    }
+
+    pub fn get_drawn_lod(&self) -> Option<u32> {
+        self.drawn_lod
+    }
+}
+
+/// Counts the amount of visible voxels within every y layer of a chunk.
+pub fn count_occupancy(voxels: &[Voxel], dimensions: &ChunkDimensions) -> Vec<u32> {
+    let mut occupancy = vec![0; dimensions.height as usize];
+
+    for (index, voxel) in voxels.iter().enumerate() {
+        if voxel.is_visible() {
+            occupancy[dimensions.index_to_local(index).y as usize] += 1;
+        }
+    }
+
+    occupancy
+}
+
+/// Counts the amount of solid voxels.
+pub fn count_solid(voxels: &[Voxel]) -> u32 {
+    voxels.iter().filter(|voxel| voxel.is_solid()).count() as u32
+}
+
+/// The density of a voxel that doesn't have one of its own, such as an edited voxel or the voxels
+/// of a uniform chunk. These are either entirely within the terrain or entirely outside of it.
+pub fn fallback_density(voxel: &Voxel) -> f16 {
+    match voxel.is_solid() {
+        true => f16::ONE,
+        false => f16::NEG_ONE,
+    }
+}
+
+/// Counts the amount of solid voxels on every face of a chunk, indexed by `VoxelFace::index()`.
+pub fn count_solid_faces(voxels: &[Voxel], dimensions: &ChunkDimensions) -> [u32; 6] {
+    VoxelFace::ALL.map(|face| {
+        dimensions
+            .face_positions(&face)
+            .filter(|position| voxels[dimensions.local_to_index(*position)].is_solid())
+            .count() as u32
+    })
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::{Color, IVec3, UVec3};
+    use half::f16;
+
+    use super::{Chunk, ChunkDimensions, VoxelFace};
+    use crate::chunk::{registry::Coordinates, voxel::Voxel};
+
+    #[test]
+    fn test_index_round_trip() {
+        let dimensions = ChunkDimensions {
+            width: 3,
+            height: 5,
+            depth: 7,
+        };
+
+        for index in 0..dimensions.volume() {
+            let local = dimensions.index_to_local(index);
+
+            assert!(local.x < 3 && local.y < 5 && local.z < 7);
+            assert_eq!(dimensions.local_to_index(local), index);
+        }
+
+        assert_eq!(
+            dimensions.local_to_index(UVec3::new(2, 4, 6)),
+            3 * 5 * 7 - 1
+        );
+    }
+
+    #[test]
+    fn test_iter_solid() {
+        let mut chunk = Chunk::new(4, 4, 4, Coordinates::new(-4, 8, 12));
+
+        chunk.set_voxel([1, 2, 3], Voxel::new_solid(Color::GRAY, f16::ONE));
+        chunk.set_voxel([3, 0, 0], Voxel::new_solid(Color::GRAY, f16::ONE));
+
+        let solid = chunk
+            .iter_solid()
+            .map(|(position, _)| position)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            solid,
+            vec![Coordinates::new(-1, 8, 12), Coordinates::new(-3, 10, 15)]
+        );
+        assert_eq!(chunk.iter_voxels().count(), 4 * 4 * 4);
+    }
+
+    #[test]
+    fn test_modified() {
+        let mut chunk = Chunk::new(4, 4, 4, Coordinates::new(0, 0, 0));
+
+        // filling in a chunk that hasn't been generated yet doesn't count as an edit.
+        chunk.set_voxel([0, 0, 0], Voxel::new_solid(Color::GRAY, f16::ONE));
+        assert!(!chunk.is_modified());
+
+        chunk.set_generated(true);
+        chunk.set_voxel([0, 0, 0], Voxel::default());
+        assert!(chunk.is_modified());
+    }
+
+    #[test]
+    fn test_edited_while_meshing() {
+        let mut chunk = Chunk::new(4, 4, 4, Coordinates::new(0, 0, 0));
+
+        chunk.set_dirty(false);
+        chunk.mark_edited(IVec3::new(0, 0, 0));
+        chunk.mark_edited(IVec3::new(1, 0, 0));
+
+        // another edit lands while the chunk is being meshed, which the mesh doesn't cover.
+        let snapshot = chunk.snapshot();
+        chunk.mark_edited(IVec3::new(2, 0, 0));
+
+        chunk.finish_meshing(snapshot.revision, snapshot.edits.len());
+        assert!(chunk.is_dirty());
+        assert_eq!(chunk.edits, vec![IVec3::new(2, 0, 0)]);
+
+        // nothing changed while meshing this time around.
+        let snapshot = chunk.snapshot();
+        chunk.finish_meshing(snapshot.revision, snapshot.edits.len());
+        assert!(!chunk.is_dirty());
+        assert!(chunk.edits.is_empty());
+
+        // a chunk that has to be meshed entirely doesn't have any edits to keep.
+        chunk.mark_edited(IVec3::new(3, 0, 0));
+        let snapshot = chunk.snapshot();
+        chunk.set_dirty(true);
+
+        chunk.finish_meshing(snapshot.revision, snapshot.edits.len());
+        assert!(chunk.is_dirty());
+        assert!(chunk.edits.is_empty());
+    }
+
+    #[test]
+    fn test_face_offsets() {
+        let offsets = VoxelFace::ALL.map(|face| face.offset());
+
+        // every face points along a different axis, or the other way along the same one.
+        for axis in [IVec3::X, IVec3::Y, IVec3::Z] {
+            assert_eq!(offsets.iter().filter(|offset| **offset == axis).count(), 1);
+            assert_eq!(offsets.iter().filter(|offset| **offset == -axis).count(), 1);
+        }
+
+        assert_eq!(VoxelFace::Front.offset(), -VoxelFace::Back.offset());
+        assert_eq!(VoxelFace::Right.offset(), -VoxelFace::Left.offset());
+        assert_eq!(VoxelFace::Up.offset(), -VoxelFace::Down.offset());
+    }
+
+    #[test]
+    fn test_uniform_split() {
+        let mut chunk = Chunk::new(4, 4, 4, Coordinates::new(0, 0, 0));
+        let solid = Voxel::new_solid(Color::GRAY, f16::ONE);
+
+        chunk.set_uniform(solid);
+        assert!(chunk.is_full());
+        assert_eq!(chunk.get_voxel([3, 3, 3]), Some(&solid));
+        assert_eq!(chunk.get_voxel([0, 0, 4]), None);
+
+        // writing the same voxel again keeps the chunk uniform, a different one splits it.
+        chunk.set_voxel([1, 2, 3], solid);
+        assert_eq!(chunk.voxels.uniform(), Some(solid));
+
+        chunk.set_voxel([1, 2, 3], Voxel::default());
+        assert_eq!(chunk.voxels.uniform(), None);
+        assert_eq!(chunk.get_voxel([1, 2, 3]), Some(&Voxel::default()));
+        assert_eq!(chunk.get_voxel([2, 2, 3]), Some(&solid));
+        assert_eq!(chunk.iter_solid().count(), 4 * 4 * 4 - 1);
+        assert!(!chunk.is_full() && !chunk.is_layer_empty(2));
+
+        // the buffer it got split into can be reused once the chunk is uniform again.
+        assert_eq!(
+            chunk
+                .set_uniform(Voxel::default())
+                .map(|voxels| voxels.len()),
+            Some(64)
+        );
+        assert!(chunk.is_empty());
+    }
+
+    #[test]
+    fn test_solid_faces() {
+        let mut chunk = Chunk::new(3, 4, 5, Coordinates::new(0, 0, 0));
+        let solid = Voxel::new_solid(Color::GRAY, f16::ONE);
+
+        // a solid floor, which covers the bottom face but only part of the sides.
+        let voxels = (0..chunk.dimensions.volume())
+            .map(|index| match chunk.dimensions.index_to_local(index).y {
+                0 => solid,
+                _ => Voxel::default(),
+            })
+            .collect::<Vec<_>>();
+
+        chunk.set_voxels(voxels);
+
+        assert!(chunk.is_face_solid(&VoxelFace::Down));
+        assert_eq!(chunk.solid_faces[VoxelFace::Left.index()], 5);
+        assert_eq!(chunk.solid_faces[VoxelFace::Front.index()], 3);
+        assert!(!chunk.is_face_solid(&VoxelFace::Up) && !chunk.is_full());
+
+        // a hole in the corner opens up the three faces it lies on.
+        chunk.set_voxel([2, 0, 4], Voxel::default());
+
+        assert!(!chunk.is_face_solid(&VoxelFace::Down));
+        assert_eq!(chunk.solid_faces[VoxelFace::Right.index()], 4);
+        assert_eq!(chunk.solid_faces[VoxelFace::Front.index()], 2);
+        assert_eq!(chunk.solid_faces[VoxelFace::Left.index()], 5);
+
+        chunk.set_voxel([2, 0, 4], solid);
+        assert!(chunk.is_face_solid(&VoxelFace::Down));
+
+        // the index of every face matches its position within `ALL`.
+        for (index, face) in VoxelFace::ALL.iter().enumerate() {
+            assert_eq!(face.index(), index);
+        }
+
+        chunk.set_uniform(solid);
+        assert!(VoxelFace::ALL.iter().all(|face| chunk.is_face_solid(face)));
+
+        chunk.set_uniform(Voxel::default());
+        assert_eq!(chunk.solid_faces, [0; 6]);
+    }
 }
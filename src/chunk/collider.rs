@@ -0,0 +1,136 @@
+use bevy::prelude::UVec3;
+
+use super::{chunk::ChunkDimensions, voxel::Voxel};
+
+/// An axis-aligned box in the local voxel space of a chunk, represented as `(min, max)`.
+///
+/// `min` is inclusive and `max` is exclusive, meaning a single voxel at `(0, 0, 0)` is represented
+/// as `((0, 0, 0), (1, 1, 1))`. To get the box in world space, these have to be scaled by the size
+/// of the voxels and offset by the world position of the chunk.
+pub type ColliderBox = (UVec3, UVec3);
+
+/// Builds a simplified collision shape for the solid voxels of a chunk.
+///
+/// The solid voxels are greedily merged into as few boxes as possible: every box is first grown
+/// along the x axis, then along the y axis and lastly along the z axis, for as long as all of the
+/// voxels it would cover are solid and not part of another box yet. This is not guaranteed to
+/// produce the smallest possible amount of boxes, but it gets pretty close for terrain, and it's a
+/// lot cheaper than handing every single voxel to a physics engine.
+///
+/// This always operates on the voxels at their base resolution, regardless of the level of detail
+/// the chunk is rendered at; we don't want the player to fall through the terrain just because a
+/// chunk is far away.
+pub fn greedy_boxes(voxels: &[Voxel], dimensions: &ChunkDimensions) -> Vec<ColliderBox> {
+    let ChunkDimensions {
+        width,
+        height,
+        depth,
+    } = *dimensions;
+
+    let index_of = |x: u32, y: u32, z: u32| dimensions.local_to_index(UVec3::new(x, y, z));
+
+    let mut visited = vec![false; voxels.len()];
+    let mut boxes = Vec::new();
+
+    let is_free = |visited: &[bool], x: u32, y: u32, z: u32| {
+        let index = index_of(x, y, z);
+
+        !visited[index] && voxels.get(index).is_some_and(|voxel| voxel.is_solid())
+    };
+
+    for z in 0..depth {
+        for y in 0..height {
+            for x in 0..width {
+                if !is_free(&visited, x, y, z) {
+                    continue;
+                }
+
+                let mut max_x = x + 1;
+
+                while max_x < width && is_free(&visited, max_x, y, z) {
+                    max_x += 1;
+                }
+
+                let mut max_y = y + 1;
+
+                while max_y < height && (x..max_x).all(|x| is_free(&visited, x, max_y, z)) {
+                    max_y += 1;
+                }
+
+                let mut max_z = z + 1;
+
+                while max_z < depth
+                    && (y..max_y).all(|y| (x..max_x).all(|x| is_free(&visited, x, y, max_z)))
+                {
+                    max_z += 1;
+                }
+
+                for box_z in z..max_z {
+                    for box_y in y..max_y {
+                        for box_x in x..max_x {
+                            visited[index_of(box_x, box_y, box_z)] = true;
+                        }
+                    }
+                }
+
+                boxes.push((UVec3::new(x, y, z), UVec3::new(max_x, max_y, max_z)));
+            }
+        }
+    }
+
+    boxes
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::{Color, UVec3};
+    use half::f16;
+
+    use super::greedy_boxes;
+    use crate::chunk::{chunk::ChunkDimensions, voxel::Voxel};
+
+    fn solid() -> Voxel {
+        Voxel::new_solid(Color::GRAY, f16::ONE)
+    }
+
+    const DIMENSIONS: ChunkDimensions = ChunkDimensions {
+        width: 4,
+        height: 4,
+        depth: 4,
+    };
+
+    #[test]
+    fn test_solid_chunk_is_single_box() {
+        let voxels = vec![solid(); 64];
+
+        assert_eq!(
+            greedy_boxes(&voxels, &DIMENSIONS),
+            vec![(UVec3::ZERO, UVec3::splat(4))]
+        );
+    }
+
+    #[test]
+    fn test_boxes_cover_solid_voxels() {
+        let mut voxels = vec![Voxel::default(); 64];
+
+        // a floor, and a single pillar standing on top of it.
+        for z in 0..4 {
+            for x in 0..4 {
+                voxels[x + z * 16] = solid();
+            }
+        }
+
+        for y in 1..4 {
+            voxels[2 + y * 4 + 2 * 16] = solid();
+        }
+
+        let boxes = greedy_boxes(&voxels, &DIMENSIONS);
+        let volume: u32 = boxes
+            .iter()
+            .map(|(min, max)| (*max - *min).to_array().iter().product::<u32>())
+            .sum();
+
+        assert_eq!(boxes.len(), 2);
+        assert_eq!(volume, 16 + 3);
+    }
+}
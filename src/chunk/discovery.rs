@@ -1,8 +1,13 @@
-use bevy::{prelude::*, render::primitives::Frustum};
+use bevy::{prelude::*, render::view::NoFrustumCulling};
 
 use crate::{
-    chunk::{registry::ChunkRegistry, ChunkEntity, DiscoverySettings},
-    util::frustum::{create_frustum_points, is_in_frustum_batch_unsized},
+    chunk::{
+        chunk::ChunkFlags,
+        origin::FloatingOrigin,
+        registry::{ChunkCoordinates, ChunkRegistry, Coordinates},
+        ChunkEntity, ChunkStats, DiscoverySettings, DiscoveryShape,
+    },
+    input::camera::PlayerController,
 };
 
 use super::events::discovery::BusyLocations;
@@ -14,113 +19,392 @@ use super::events::discovery::BusyLocations;
 /// "dirty" to be re-rendered once they come back within the discovery radius, preventing them from
 /// appearing as blank chunks.
 ///
+/// Chunks that are within the discovery radius but outside of the view frustum are not touched at
+/// all; bevy already skips these while rendering, using the bounding boxes of the chunk entities
+/// (see `apply_frustum_culling`). This way turning the camera around doesn't cost anything.
+///
 /// # Parameters
 ///
-/// - `commands`: A mutable reference to the ECS commands buffer, used for removing components from entities.
-/// - `registry`: A mutable reference to the `ChunkRegistry`, which manages the storage and retrieval of chunks.
+/// - `commands`: A mutable reference to the ECS commands buffer, used for updating the visibility of entities.
+/// - `registry`: The `ChunkRegistry`, which manages the storage and retrieval of chunks.
 /// - `loaded_chunks`: A query that retrieves loaded chunk entities along with their positions.
 /// - `transform`: A query that retrieves the transformation data of the camera entity.
 /// - `discovery_settings`: A resource containing settings related to chunk discovery and unloading.
+/// - `stats`: The `ChunkStats` resource, which keeps track of how many chunks were hidden by
+///   occlusion culling.
 ///
 /// # Details
 ///
 /// Chunks are managed as entities with associated positions. The camera's current translation is used
 /// to calculate its position in chunk space. Each loaded chunk's position is also translated to chunk
 /// space. The distance between each chunk's position and the camera's position in chunk space is
-/// calculated to determine whether the chunk is outside the discovery radius. If so, the chunk is marked
-/// as dirty and its rendering material is removed, causing it to be despawned.
+/// calculated to determine whether the chunk is outside the discovery radius. If so, the chunk is
+/// no longer marked as drawn and its entity is hidden; the mesh components are kept in place, so
+/// drawing it again once it comes back within the radius is cheap. Otherwise, its visibility is
+/// updated according to whether it's occluded.
 ///
 pub fn unload_distant_chunks(
     mut commands: Commands,
-    mut registry: ResMut<ChunkRegistry>,
+    registry: Res<ChunkRegistry>,
     busy: Res<BusyLocations>,
-    loaded_chunks: Query<(Entity, &ChunkEntity)>,
-    transform: Query<(&Transform, &Frustum)>,
+    loaded_chunks: Query<(Entity, &ChunkEntity, &Visibility)>,
+    transform: Query<&Transform, With<PlayerController>>,
     discovery_settings: Res<DiscoverySettings>,
+    mut stats: ResMut<ChunkStats>,
+    origin: Res<FloatingOrigin>,
 ) {
-    let (transform, frustum) = transform.single();
-    let translation = transform.translation;
+    let Ok(transform) = transform.get_single() else {
+        return;
+    };
+
+    // the discovery radius is measured in chunks rather than in voxels, so we have to measure
+    // the distance between the chunk and the camera in chunks as well.
+    let camera_position = origin.to_chunk_space(transform.translation);
+    // the chunk the camera is in, the discovery area is centered around this.
+    let camera_chunk = origin.to_chunk_coords(transform.translation);
 
-    for (entity, ChunkEntity { position }) in loaded_chunks.iter() {
-        let IVec3 {
-            x: pos_x,
-            y: pos_y,
-            z: pos_z,
-        } = position;
+    let mut kept = 0;
+    let mut occluded_count = 0;
 
+    for (entity, ChunkEntity { position }, visibility) in loaded_chunks.iter() {
         if busy.0.contains(position) {
             continue;
         }
 
-        let size = ChunkRegistry::CHUNK_SIZE;
-        let height = ChunkRegistry::CHUNK_HEIGHT;
-
-        // these values have to be divided by `size` to get the chunked-distance; we need this
-        // distance as the discovery_settings.discovery_radius is measured in chunks; not in
-        // blocks.
-        let dist_x: f32 = (pos_x / size as i32) as f32;
-        let dist_y: f32 = (pos_y / height as i32) as f32;
-        let dist_z: f32 = (pos_z / size as i32) as f32;
+        // this has to happen before we're holding on to the chunk itself; the occlusion test
+        // reads the chunks in between, which could be in the same shard as this chunk.
+        let occluded =
+            discovery_settings.occlusion_cull && is_occluded(&registry, camera_position, *position);
 
-        // same thing goes for these as for the dist_x and dist_z variables above.
-        let trans_x = translation.x / size as f32;
-        let trans_y = translation.y / size as f32;
-        let trans_z = translation.z / size as f32;
+        let Some(mut chunk) = registry.get_chunk_at_mut(*position) else {
+            continue;
+        };
 
-        // calculate the difference between the chunk's position and the camera's position
-        let diff_x = (dist_x - trans_x).abs();
-        let diff_y = (dist_y - trans_y).abs();
-        let diff_z = (dist_z - trans_z).abs();
+        // the chunk has already been unloaded, it'll be drawn again through discovery.
+        if !chunk.is_drawn() {
+            continue;
+        }
 
-        let points =
-            create_frustum_points((*pos_x, *pos_y, *pos_z).into(), (size, height, size).into());
+        let chunk_position = position.to_chunk_coords().as_vec3();
 
-        let mut chunk = registry.get_chunk_at_mut([*pos_x, *pos_y, *pos_z]);
+        // calculate the difference between the chunk's position and the camera's position
+        let Vec3 {
+            x: diff_x,
+            y: diff_y,
+            z: diff_z,
+        } = (chunk_position - camera_position).abs();
 
         if discovery_settings.lod {
-            // this will require some more playing around to get the values right, LOD should probably
-            // be calculated in a much different way. but we'll just use this until we get the entire
-            // LOD system to work properly.
-            if let Some(chunk) = &mut chunk {
-                // get the difference that's the least. we'll base our LOD off of this.
-                // we use minimum instead of the maximum, to ensure even if the chunks are far away in
-                // terms of a single axis, but close in all of the others, it will be rendered in a
-                // higher quality rather than lower quality.
-                let min_diff = diff_x.min(diff_y).min(diff_z);
-                // we apply a scale to the difference, without this scale the LOD effect won't do too
-                // much.
-                let scaled_diff = min_diff * 3.0;
-
-                // round the LOD to be a u32
-                let rounded_lod = ((scaled_diff.round() - 1.0) as u32).max(0);
+            let rounded_lod = lod_for_distance(
+                Vec3::new(diff_x, diff_y, diff_z),
+                discovery_settings.lod_bias,
+            );
+
+            // the chunk was generated at a lower resolution than we need now that we're closer,
+            // so it has to be generated all over again. the old mesh stays in place until the new
+            // one is ready. modified chunks are kept as is, as regenerating them undoes the edits.
+            if rounded_lod < chunk.get_generated_lod() && !chunk.is_modified() {
+                chunk.set_generated(false);
+                chunk.set_flag(ChunkFlags::Meshed, false);
+            }
 
+            // the level of detail is only applied while meshing, so the chunk has to be meshed
+            // again whenever it changes.
+            if rounded_lod != chunk.get_lod() {
                 chunk.set_lod(rounded_lod);
+                chunk.set_dirty(true);
             }
         }
 
-        if diff_x - 1.0 > discovery_settings.discovery_radius.into()
-            || diff_z - 1.0 > discovery_settings.discovery_radius.into()
-            || diff_y - 1.0 > discovery_settings.discovery_radius_height.into()
-            // also unload the chunks if they are out of vision
-            || is_in_frustum_batch_unsized(points, frustum.half_spaces)
-                .iter()
-                .filter(|result| **result)
-                .next()
-                .is_none()
-        {
-            if let Some(chunk) = chunk {
-                chunk.set_drawn(false);
-                chunk.set_busy(false);
+        if is_out_of_range(
+            camera_chunk,
+            position.to_chunk_coords(),
+            &discovery_settings,
+        ) {
+            chunk.set_drawn(false);
+            chunk.set_busy(false);
+
+            // the submeshes are children of the chunk entity, so these are hidden along with it.
+            commands.entity(entity).insert(Visibility::Hidden);
+
+            continue;
+        }
+
+        let target = match occluded {
+            false => {
+                kept += 1;
+                Visibility::Inherited
             }
+            true => {
+                occluded_count += 1;
+                Visibility::Hidden
+            }
+        };
+
+        // only touch the visibility if it actually changed, otherwise we'd trigger change
+        // detection on every single chunk, every single frame.
+        if *visibility != target {
+            commands.entity(entity).insert(target);
+        }
+    }
+
+    stats.kept = kept;
+    stats.occluded = occluded_count;
+}
+
+// the distance (in chunks) between the points that are checked along the line of sight; lower
+// values are more accurate, but have to look up more chunks.
+const OCCLUSION_STEP: f32 = 0.5;
+
+/// Whether the line of sight between the camera and the center of a chunk is blocked by another
+/// chunk, that's entirely solid.
+///
+/// This is rather coarse: a chunk is only considered to be blocking if every single one of its
+/// voxels is solid, and only the center of the target chunk is checked. The camera position is
+/// in chunk space, see `ChunkRegistry::world_to_chunk_space()`.
+pub fn is_occluded(registry: &ChunkRegistry, camera: Vec3, target: Coordinates) -> bool {
+    let camera_chunk = camera.floor().as_ivec3();
+    let target_chunk = target.to_chunk_coords();
+    let target_center = target_chunk.as_vec3() + Vec3::splat(0.5);
+
+    let steps = (camera.distance(target_center) / OCCLUSION_STEP).ceil() as u32;
+
+    (1..steps)
+        .map(|step| {
+            camera
+                .lerp(target_center, step as f32 / steps as f32)
+                .floor()
+                .as_ivec3()
+        })
+        .filter(|chunk| *chunk != camera_chunk && *chunk != target_chunk)
+        .any(|chunk| {
+            registry
+                .get_chunk_at(chunk * ChunkRegistry::chunk_dimensions())
+                .is_some_and(|chunk| chunk.is_full())
+        })
+}
+
+/// Enables (or disables) bevy's frustum culling of the chunk entities whenever
+/// `DiscoverySettings::frustum_cull` changes; entities that get spawned while it's disabled
+/// receive `NoFrustumCulling` in `draw_chunks`.
+///
+/// The culling itself is done by bevy, using the bounding box of every entity; these are kept up
+/// to date whenever a chunk gets meshed again, see `process_chunk_meshing`.
+pub fn apply_frustum_culling(
+    mut commands: Commands,
+    registry: Res<ChunkRegistry>,
+    settings: Res<DiscoverySettings>,
+    mut previous: Local<Option<bool>>,
+) {
+    // every chunk entity gets touched, so only once the setting actually flips; see
+    // `inspector_ui`.
+    if *previous == Some(settings.frustum_cull) {
+        return;
+    }
+
+    *previous = Some(settings.frustum_cull);
+
+    let mut entities = Vec::new();
+
+    registry.for_each_chunk_mut(|chunk| {
+        entities.extend(chunk.entity.into_iter().chain(chunk.submesh_entities()));
+    });
+
+    for entity in entities {
+        let mut entity = commands.entity(entity);
+
+        match settings.frustum_cull {
+            true => entity.remove::<NoFrustumCulling>(),
+            false => entity.insert(NoFrustumCulling),
+        };
+    }
+}
+
+/// Counts the chunk entities that we want to show, but that bevy's own frustum culling skipped.
+///
+/// The visibility is computed in `PostUpdate`, so this always reports the previous frame.
+pub fn count_view_culled(
+    chunks: Query<&ComputedVisibility, With<ChunkEntity>>,
+    mut stats: ResMut<ChunkStats>,
+) {
+    stats.view_culled = chunks
+        .iter()
+        .filter(|visibility| {
+            visibility.is_visible_in_hierarchy() && !visibility.is_visible_in_view()
+        })
+        .count();
+}
+
+// the distance (in chunks) a chunk can be outside of the discovery area before it gets unloaded.
+// this way chunks right on the edge don't get unloaded and discovered over and over again.
+const UNLOAD_MARGIN: f32 = 1.0;
+
+/// Whether a chunk belongs to the discovery area around the chunk the camera is in, both are
+/// given in chunk coordinates (see `ChunkCoordinates::to_chunk_coords()`).
+///
+/// This decides which chunks get discovered; everything that decides whether a chunk should
+/// still be loaded goes through `is_out_of_range()`, which is built on top of this. This way
+/// both always agree on the shape of the area, see `DiscoveryShape`. Chunks outside of the
+/// `WorldBounds` never belong to it.
+pub fn is_within_discovery(
+    center: Coordinates,
+    chunk: Coordinates,
+    settings: &DiscoverySettings,
+) -> bool {
+    is_within_bounds(chunk, settings) && is_within_shape((chunk - center).as_vec3(), settings, 0.0)
+}
+
+/// Whether a chunk is far enough outside of the discovery area to be unloaded; both are given in
+/// chunk coordinates, just like `is_within_discovery()`.
+pub fn is_out_of_range(
+    center: Coordinates,
+    chunk: Coordinates,
+    settings: &DiscoverySettings,
+) -> bool {
+    // the bounds don't get a margin, these can only change through the settings.
+    !is_within_bounds(chunk, settings)
+        || !is_within_shape((chunk - center).as_vec3(), settings, UNLOAD_MARGIN)
+}
+
+fn is_within_bounds(chunk: Coordinates, settings: &DiscoverySettings) -> bool {
+    settings.bounds.is_none_or(|bounds| bounds.contains(chunk))
+}
+
+fn is_within_shape(offset: Vec3, settings: &DiscoverySettings, margin: f32) -> bool {
+    let radius = settings.discovery_radius as f32 + margin;
+    let radius_height = settings.discovery_radius_height as f32 + margin;
+    let Vec3 { x, y, z } = offset.abs();
+
+    match settings.shape {
+        DiscoveryShape::Box => x <= radius && z <= radius && y <= radius_height,
+        DiscoveryShape::Cylinder => x * x + z * z < radius * radius && y <= radius_height,
+        DiscoveryShape::Sphere => {
+            // a radius of 0 still covers the chunk the camera is in, rather than dividing by 0.
+            let (radius, radius_height) = (radius.max(0.5), radius_height.max(0.5));
+
+            (x * x + z * z) / (radius * radius) + (y * y) / (radius_height * radius_height) < 1.0
+        }
+    }
+}
+
+/// Calculates the level of detail of a chunk, based on its distance to the camera (in chunks) on
+/// every axis.
+///
+/// This will require some more playing around to get the values right, LOD should probably be
+/// calculated in a much different way. but we'll just use this until we get the entire LOD system
+/// to work properly.
+pub fn lod_for_distance(difference: Vec3, bias: f32) -> u32 {
+    let Vec3 { x, y, z } = difference.abs();
+
+    // get the difference that's the least. we'll base our LOD off of this.
+    // we use minimum instead of the maximum, to ensure even if the chunks are far away in
+    // terms of a single axis, but close in all of the others, it will be rendered in a
+    // higher quality rather than lower quality.
+    let min_diff = x.min(y).min(z);
+    // we apply a scale to the difference, without this scale the LOD effect won't do too
+    // much.
+    let scaled_diff = min_diff * bias;
+
+    // round the LOD to be a u32, the closest chunks would end up below 0 otherwise.
+    (scaled_diff.round() - 1.0).max(0.0) as u32
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::{Color, IVec3, Vec3};
+    use half::f16;
+
+    use super::{is_occluded, is_out_of_range, is_within_discovery};
+    use crate::chunk::{
+        chunk::Chunk,
+        registry::{ChunkRegistry, Coordinates},
+        voxel::Voxel,
+        DiscoverySettings, DiscoveryShape,
+    };
+
+    #[test]
+    fn test_is_occluded() {
+        let registry = ChunkRegistry::new();
+        let dimensions = ChunkRegistry::chunk_dimensions();
+        let (width, height, depth) = (
+            dimensions.x as u32,
+            dimensions.y as u32,
+            dimensions.z as u32,
+        );
+
+        // a wall of a single, entirely solid chunk right next to the camera.
+        let wall = IVec3::new(1, 0, 0) * dimensions;
+        let voxels =
+            vec![Voxel::new_solid(Color::GRAY, f16::ONE); (width * height * depth) as usize];
+
+        registry.push_chunk_at(wall, Chunk::with_voxels(width, height, depth, wall, voxels));
+
+        let camera = Vec3::splat(0.5);
+
+        assert!(is_occluded(
+            &registry,
+            camera,
+            IVec3::new(3, 0, 0) * dimensions
+        ));
+        assert!(!is_occluded(
+            &registry,
+            camera,
+            IVec3::new(0, 0, 3) * dimensions
+        ));
+        // the wall itself isn't hidden behind anything.
+        assert!(!is_occluded(&registry, camera, wall));
+    }
+
+    #[test]
+    fn test_discovery_shapes() {
+        let center = Coordinates::new(5, -2, 7);
+
+        for shape in DiscoveryShape::ALL {
+            let settings = DiscoverySettings {
+                discovery_radius: 4,
+                discovery_radius_height: 2,
+                shape,
+                ..Default::default()
+            };
+
+            for x in -6..=6 {
+                for y in -4..=4 {
+                    for z in -6..=6 {
+                        let chunk = center + Coordinates::new(x, y, z);
+
+                        // every discovered chunk has to stay loaded, otherwise it would get
+                        // unloaded right after being discovered.
+                        if is_within_discovery(center, chunk, &settings) {
+                            assert!(!is_out_of_range(center, chunk, &settings), "{shape:?}");
+                        }
+                    }
+                }
+            }
+
+            assert!(is_within_discovery(center, center, &settings));
+            assert!(!is_within_discovery(
+                center,
+                center + Coordinates::new(0, 3, 0),
+                &settings
+            ));
+            assert!(is_out_of_range(
+                center,
+                center + Coordinates::new(6, 0, 0),
+                &settings
+            ));
+
+            // a single layer of chunks, the one the camera is in.
+            let flat = DiscoverySettings {
+                discovery_radius_height: 0,
+                ..settings
+            };
 
-            commands
-                .entity(entity)
-                .insert(SceneBundle {
-                    visibility: Visibility::Hidden,
-                    ..Default::default()
-                })
-                .remove::<ChunkEntity>()
-                .remove::<PbrBundle>();
+            assert!(is_within_discovery(center, center, &flat), "{shape:?}");
+            assert!(!is_within_discovery(
+                center,
+                center + Coordinates::new(0, 1, 0),
+                &flat
+            ));
         }
     }
 }
@@ -0,0 +1,234 @@
+use std::collections::VecDeque;
+
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::input::bindings::{InputAction, InputBindings};
+
+use super::{
+    events::discovery::ChunkDiscoveryEvent,
+    registry::{ChunkCoordinates, ChunkRegistry, Coordinates},
+    voxel::Voxel,
+};
+
+// the maximum amount of undo steps that are kept around, the oldest ones are dropped first.
+pub const MAX_UNDO_STEPS: usize = 64;
+
+/// Requests a set of voxels to be changed, using their world position.
+///
+/// Every event is a single undo step, so editing tools (such as brushes) should send all of the
+/// voxels they change at once, rather than sending an event per voxel.
+#[derive(Event, Clone)]
+pub struct VoxelEditEvent {
+    pub voxels: Vec<(Coordinates, Voxel)>,
+}
+
+/// A single voxel that was changed, along with what it was before.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoxelEdit {
+    pub position: Coordinates,
+    pub previous: Voxel,
+    pub voxel: Voxel,
+}
+
+/// The undo and redo stacks of the voxel edits.
+#[derive(Resource, Default, Debug)]
+pub struct EditHistory {
+    undo: VecDeque<Vec<VoxelEdit>>,
+    redo: Vec<Vec<VoxelEdit>>,
+}
+
+impl EditHistory {
+    /// Applies a set of edits as a single undo step. anything that could be redone is discarded.
+    ///
+    /// Voxels within chunks that don't exist are skipped, these aren't recorded either. The
+    /// chunks that were changed are returned, these have already been marked as dirty.
+    pub fn apply(
+        &mut self,
+        registry: &ChunkRegistry,
+        voxels: impl IntoIterator<Item = (Coordinates, Voxel)>,
+    ) -> HashSet<Coordinates> {
+        let mut changed = HashSet::new();
+
+        let edits = voxels
+            .into_iter()
+            .filter_map(|(position, voxel)| {
+                let previous = set_voxel(registry, position, voxel, &mut changed)?;
+
+                Some(VoxelEdit {
+                    position,
+                    previous,
+                    voxel,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if !edits.is_empty() {
+            self.redo.clear();
+            self.undo.push_back(edits);
+
+            if self.undo.len() > MAX_UNDO_STEPS {
+                self.undo.pop_front();
+            }
+        }
+
+        changed
+    }
+
+    /// Reverts the last undo step, returning the chunks that were changed.
+    pub fn undo(&mut self, registry: &ChunkRegistry) -> HashSet<Coordinates> {
+        let mut changed = HashSet::new();
+
+        if let Some(edits) = self.undo.pop_back() {
+            // reverted in the opposite order, in case the same voxel was changed more than once.
+            for edit in edits.iter().rev() {
+                set_voxel(registry, edit.position, edit.previous, &mut changed);
+            }
+
+            self.redo.push(edits);
+        }
+
+        changed
+    }
+
+    /// Applies the last undone step again, returning the chunks that were changed.
+    pub fn redo(&mut self, registry: &ChunkRegistry) -> HashSet<Coordinates> {
+        let mut changed = HashSet::new();
+
+        if let Some(edits) = self.redo.pop() {
+            for edit in &edits {
+                set_voxel(registry, edit.position, edit.voxel, &mut changed);
+            }
+
+            self.undo.push_back(edits);
+        }
+
+        changed
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+// sets a voxel by its world position, returning the voxel that was there before. the chunk (and
+// the neighbors the voxel is next to) are marked as edited and added to `changed`, as the voxel
+// could have been hiding (or exposing) the faces of the voxels around it.
+fn set_voxel(
+    registry: &ChunkRegistry,
+    position: Coordinates,
+    voxel: Voxel,
+    changed: &mut HashSet<Coordinates>,
+) -> Option<Voxel> {
+    let chunk_position = position.to_world_origin();
+    let local = position.chunk_local_offset();
+
+    let mut chunk = registry.get_chunk_at_mut(chunk_position)?;
+    let previous = *chunk.get_voxel(local)?;
+
+    chunk.set_voxel(local, voxel);
+    drop(chunk);
+
+    changed.extend(registry.mark_edited_with_neighbors(position));
+
+    Some(previous)
+}
+
+/// Applies the `VoxelEditEvent`s, and undoes or redoes them on `InputAction::Undo` and
+/// `InputAction::Redo` (`Ctrl + Z` and `Ctrl + Y` by default).
+pub fn handle_voxel_edits(
+    mut reader: EventReader<VoxelEditEvent>,
+    mut history: ResMut<EditHistory>,
+    registry: Res<ChunkRegistry>,
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut discovery_writer: EventWriter<ChunkDiscoveryEvent>,
+) {
+    let mut changed = HashSet::new();
+
+    for VoxelEditEvent { voxels } in reader.iter() {
+        changed.extend(history.apply(&registry, voxels.iter().copied()));
+    }
+
+    if bindings.just_pressed(InputAction::Undo, &keys) {
+        changed.extend(history.undo(&registry));
+    }
+
+    if bindings.just_pressed(InputAction::Redo, &keys) {
+        changed.extend(history.redo(&registry));
+    }
+
+    // the dirty chunks get meshed again through discovery.
+    if !changed.is_empty() {
+        discovery_writer.send(ChunkDiscoveryEvent);
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::Color;
+    use half::f16;
+
+    use super::EditHistory;
+    use crate::chunk::{
+        chunk::Chunk,
+        registry::{ChunkRegistry, Coordinates},
+        voxel::Voxel,
+    };
+
+    #[test]
+    fn test_undo_redo() {
+        let registry = ChunkRegistry::new();
+        let size = ChunkRegistry::CHUNK_SIZE;
+
+        // two neighboring chunks, to make sure an undo step can span multiple chunks.
+        for x in [0, size] {
+            let position = Coordinates::new(x, 0, 0);
+            let chunk = Chunk::new(
+                size as u32,
+                ChunkRegistry::CHUNK_HEIGHT as u32,
+                size as u32,
+                position,
+            );
+
+            registry.push_chunk_at(position, chunk);
+        }
+
+        let solid = Voxel::new_solid(Color::GRAY, f16::ONE);
+        let positions = [
+            Coordinates::new(size - 1, 1, 1),
+            Coordinates::new(size, 1, 1),
+        ];
+
+        let voxel_at = |position: Coordinates| {
+            let chunk = registry
+                .get_chunk_at(Coordinates::new(position.x - position.x % size, 0, 0))
+                .unwrap();
+
+            *chunk.get_voxel([(position.x % size) as u32, 1, 1]).unwrap()
+        };
+
+        let mut history = EditHistory::default();
+        let changed = history.apply(&registry, positions.map(|position| (position, solid)));
+
+        // both chunks were edited, they're each other's only neighbors.
+        assert_eq!(changed.len(), 2);
+        assert!(positions
+            .iter()
+            .all(|position| voxel_at(*position) == solid));
+
+        history.undo(&registry);
+        assert!(positions
+            .iter()
+            .all(|position| voxel_at(*position) == Voxel::default()));
+
+        history.redo(&registry);
+        assert!(positions
+            .iter()
+            .all(|position| voxel_at(*position) == solid));
+        assert!(!history.can_redo());
+    }
+}
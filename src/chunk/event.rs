@@ -1,4 +1,9 @@
-use super::registry::{ChunkRegistry, Coordinates};
+use super::{
+    chunk::Chunk,
+    events::discovery::ChunkDiscoveryEvent,
+    pool::VoxelBufferPool,
+    registry::{ChunkCoordinates, ChunkRegistry, Coordinates},
+};
 use bevy::prelude::*;
 
 #[derive(Event)]
@@ -8,22 +13,33 @@ pub struct ChunkCreateEvent {
 
 pub fn create_chunk(
     mut reader: EventReader<ChunkCreateEvent>,
-    mut registry: ResMut<ChunkRegistry>,
+    registry: Res<ChunkRegistry>,
+    mut discovery_writer: EventWriter<ChunkDiscoveryEvent>,
+    mut pool: ResMut<VoxelBufferPool>,
 ) {
     let iter = reader.iter();
     let length = iter.len();
 
+    if length > 0 {
+        // the newly created chunks still have to be generated, which only happens through
+        // discovery.
+        discovery_writer.send(ChunkDiscoveryEvent);
+    }
+
     registry.reserve_chunks(length);
 
+    let (width, height, depth) = (
+        ChunkRegistry::CHUNK_SIZE as u32,
+        ChunkRegistry::CHUNK_HEIGHT as u32,
+        ChunkRegistry::CHUNK_SIZE as u32,
+    );
+
     for ChunkCreateEvent { coordinates } in iter {
+        let voxels = pool.take((width * height * depth) as usize);
+
         registry.push_chunk_at(
             *coordinates,
-            super::chunk::Chunk::new(
-                ChunkRegistry::CHUNK_SIZE as u32,
-                ChunkRegistry::CHUNK_HEIGHT as u32,
-                ChunkRegistry::CHUNK_SIZE as u32,
-                ChunkRegistry::get_chunk_center(*coordinates),
-            ),
+            Chunk::with_voxels(width, height, depth, coordinates.to_world_origin(), voxels),
         )
     }
 }
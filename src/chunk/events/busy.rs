@@ -0,0 +1,134 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::chunk::registry::{ChunkRegistry, Coordinates};
+
+use super::{discovery::ChunkDiscoveryEvent, gen::GenerationQueue};
+
+// how long (in seconds) a chunk can be busy without anything working on it, before it's released.
+// a task is spawned through `Commands`, so it only shows up a little while after the chunk got
+// marked as busy.
+pub const BUSY_TIMEOUT: f64 = 5.0;
+
+// how often (in seconds) the registry is checked for stale busy chunks.
+const BUSY_CHECK_INTERVAL: f64 = 1.0;
+
+/// Marks the entity of a task working on a chunk, such as a generation or mesh task. The chunk is
+/// busy for as long as the task exists.
+#[derive(Component)]
+pub struct ChunkTaskToken(pub Coordinates);
+
+/// Releases the chunks that are busy, without any task working on them (or them waiting in the
+/// `GenerationQueue`).
+///
+/// Every stage clears the busy flag once its task is done, but a task that never finishes (e.g.
+/// because its entity got despawned) would leave the chunk busy forever; discovery skips busy
+/// chunks, so it would never be processed again.
+pub fn release_stale_busy_chunks(
+    registry: Res<ChunkRegistry>,
+    tokens: Query<&ChunkTaskToken>,
+    generation_queue: Res<GenerationQueue>,
+    mut stale: Local<HashMap<Coordinates, f64>>,
+    mut last_check: Local<f64>,
+    time: Res<Time>,
+    mut discovery_writer: EventWriter<ChunkDiscoveryEvent>,
+) {
+    let now = time.elapsed_seconds_f64();
+
+    if now - *last_check < BUSY_CHECK_INTERVAL {
+        return;
+    }
+
+    *last_check = now;
+
+    let in_flight = tokens.iter().map(|token| token.0).collect::<HashSet<_>>();
+    let released = release_stale(
+        &registry,
+        |coordinates| in_flight.contains(&coordinates) || generation_queue.contains(coordinates),
+        &mut stale,
+        now,
+    );
+
+    if released > 0 {
+        warn!("released {released} chunks that were busy without a task working on them");
+
+        // the released chunks are picked up by the next scan.
+        discovery_writer.send(ChunkDiscoveryEvent);
+    }
+}
+
+// clears the busy flag of the chunks that have been busy for at least `BUSY_TIMEOUT`, without
+// being in flight. `stale` keeps track of since when every chunk has been stale, the amount of
+// released chunks is returned.
+fn release_stale(
+    registry: &ChunkRegistry,
+    in_flight: impl Fn(Coordinates) -> bool,
+    stale: &mut HashMap<Coordinates, f64>,
+    now: f64,
+) -> usize {
+    let mut released = 0;
+    let mut still_stale = HashMap::new();
+
+    registry.for_each_chunk_mut(|chunk| {
+        let coordinates = chunk.world_position;
+
+        if !chunk.is_busy() || in_flight(coordinates) {
+            return;
+        }
+
+        let since = stale.get(&coordinates).copied().unwrap_or(now);
+
+        if now - since >= BUSY_TIMEOUT {
+            chunk.set_busy(false);
+            released += 1;
+        } else {
+            still_stale.insert(coordinates, since);
+        }
+    });
+
+    *stale = still_stale;
+
+    released
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::utils::HashMap;
+
+    use super::{release_stale, BUSY_TIMEOUT};
+    use crate::chunk::{
+        chunk::Chunk,
+        registry::{ChunkRegistry, Coordinates},
+    };
+
+    #[test]
+    fn test_release_stale() {
+        let registry = ChunkRegistry::new();
+        let size = ChunkRegistry::CHUNK_SIZE;
+
+        // the first chunk is being generated, the task of the second one got despawned halfway
+        // through; nothing is ever going to clear its busy flag.
+        let (generating, abandoned) = (Coordinates::ZERO, Coordinates::new(size, 0, 0));
+
+        for position in [generating, abandoned] {
+            let mut chunk = Chunk::new(1, 1, 1, position);
+            chunk.set_busy(true);
+
+            registry.push_chunk_at(position, chunk);
+        }
+
+        let mut stale = HashMap::new();
+        let in_flight = |coordinates| coordinates == generating;
+
+        assert_eq!(release_stale(&registry, in_flight, &mut stale, 10.0), 0);
+        assert_eq!(
+            release_stale(&registry, in_flight, &mut stale, 10.0 + BUSY_TIMEOUT),
+            1
+        );
+
+        assert!(registry.get_chunk_at(generating).unwrap().is_busy());
+        assert!(!registry.get_chunk_at(abandoned).unwrap().is_busy());
+    }
+}
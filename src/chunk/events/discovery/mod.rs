@@ -1,3 +1,5 @@
+use std::f32::consts::SQRT_2;
+
 use bevy::{prelude::*, utils::HashSet};
 use bevy_tasks::Task;
 
@@ -17,7 +19,85 @@ pub mod query;
 // the performance hit is mostly noticeable when having to process a lot of chunks are added to the
 // queue at the same time, for example, if you suddenly move into a section of the world where no
 // chunks have been loaded yet.
-pub const QUEUE_PROCESS_LIMIT: usize = usize::MAX;
+//
+// the actual limit is adapted to the frame rate every frame (see `QueueLimit`), these are the
+// bounds it stays within.
+pub const QUEUE_PROCESS_LIMIT: usize = 1024;
+pub const MIN_QUEUE_PROCESS_LIMIT: usize = 16;
+
+// how long (in seconds) a chunk waits for its neighbors to be generated before it gets meshed
+// anyway, see `processing::apply_inspection`. neighbors outside of the discovery area are never
+// waited for, so this only kicks in when generation can't keep up.
+pub const MESH_DEFER_TIMEOUT: f64 = 1.0;
+
+/// The amount of processed discovery results that are handled per frame.
+///
+/// This follows the frame rate: whenever it drops below `PerformanceSettings::target_fps`, the
+/// limit is halved, otherwise it slowly grows back towards `QUEUE_PROCESS_LIMIT`. This way a
+/// spike of new chunks (e.g. moving into an unloaded part of the world) is spread over multiple
+/// frames, rather than causing a stutter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueLimit(pub usize);
+
+impl QueueLimit {
+    /// Adapts the limit to the current frame rate.
+    pub fn adapt(&mut self, fps: f64, target_fps: f64) {
+        self.0 = match fps < target_fps {
+            true => self.0 / 2,
+            false => self.0 + MIN_QUEUE_PROCESS_LIMIT,
+        }
+        .clamp(MIN_QUEUE_PROCESS_LIMIT, QUEUE_PROCESS_LIMIT);
+    }
+}
+
+impl Default for QueueLimit {
+    fn default() -> Self {
+        Self(QUEUE_PROCESS_LIMIT)
+    }
+}
+
+// the area of the entire screen, in normalized device coordinates.
+const SCREEN_AREA: f32 = 4.0;
+
+// how much the priority of a chunk in the corner of the screen is lowered, compared to a chunk of
+// the same size in the center of the screen; see `screen_priority()`.
+const CENTER_WEIGHT: f32 = 0.5;
+
+/// The priority of a chunk when it comes to loading it, based on how much of the screen it covers
+/// and how close it is to the center of the screen.
+///
+/// The corners of the chunk (given by its minimum corner and size, relative to the render origin)
+/// are projected onto the screen, the priority is the area of the rectangle around them that's
+/// actually on screen; weighted by how close the center of that rectangle is to the center of the
+/// screen. Chunks that are (partly) behind the camera, such as the chunk the camera is in, cover
+/// the entire screen.
+pub fn screen_priority(view_projection: Mat4, min: Vec3, size: Vec3) -> f32 {
+    let mut lower = Vec2::splat(f32::MAX);
+    let mut upper = Vec2::splat(f32::MIN);
+
+    for corner in 0..8 {
+        let offset = UVec3::new(corner & 1, corner >> 1 & 1, corner >> 2 & 1).as_vec3();
+        let clip = view_projection * (min + offset * size).extend(1.0);
+
+        if clip.w <= 0.0 {
+            return SCREEN_AREA;
+        }
+
+        let ndc = Vec2::new(clip.x, clip.y) / clip.w;
+
+        lower = lower.min(ndc);
+        upper = upper.max(ndc);
+    }
+
+    // chunks that are entirely off screen end up with an area of zero.
+    let lower = lower.clamp(Vec2::NEG_ONE, Vec2::ONE);
+    let upper = upper.clamp(Vec2::NEG_ONE, Vec2::ONE);
+    let area = (upper - lower).x * (upper - lower).y;
+
+    let distance_to_center = ((lower + upper) / 2.0).length() / SQRT_2;
+
+    area * (1.0 - CENTER_WEIGHT * distance_to_center)
+}
 
 #[derive(Event)]
 pub struct ChunkDiscoveryEvent;
@@ -37,3 +117,59 @@ pub enum ProcessWriterType {
     GenerateWriter(ChunkGenerateEvent),
     ChunkCreationWriter(ChunkCreateEvent),
 }
+
+#[cfg(test)]
+pub mod test {
+    use std::f32::consts::FRAC_PI_2;
+
+    use bevy::prelude::{Mat4, Vec3};
+
+    use super::{screen_priority, QueueLimit, MIN_QUEUE_PROCESS_LIMIT, QUEUE_PROCESS_LIMIT};
+
+    #[test]
+    fn test_queue_limit() {
+        let mut limit = QueueLimit::default();
+
+        // slow frames quickly bring the limit down, but never below the minimum.
+        for _ in 0..32 {
+            limit.adapt(20.0, 60.0);
+        }
+
+        assert_eq!(limit.0, MIN_QUEUE_PROCESS_LIMIT);
+
+        limit.adapt(120.0, 60.0);
+        assert_eq!(limit.0, MIN_QUEUE_PROCESS_LIMIT * 2);
+
+        for _ in 0..1000 {
+            limit.adapt(120.0, 60.0);
+        }
+
+        assert_eq!(limit.0, QUEUE_PROCESS_LIMIT);
+    }
+
+    #[test]
+    fn test_screen_priority() {
+        // the camera sits at the origin, looking down the negative z axis.
+        let view_projection = Mat4::perspective_rh(FRAC_PI_2, 1.0, 0.1, 1000.0);
+        let priority = |min: Vec3| screen_priority(view_projection, min, Vec3::ONE);
+
+        let near = priority(Vec3::new(-0.5, -0.5, -5.0));
+        let far = priority(Vec3::new(-0.5, -0.5, -50.0));
+        let off_center = priority(Vec3::new(3.0, -0.5, -5.0));
+        let behind = priority(Vec3::new(-0.5, -0.5, 5.0));
+
+        assert!(near > far);
+        assert!(off_center > far);
+
+        // seen at an angle, more of the chunk is visible; with the depth out of the picture, the
+        // same area in the center of the screen comes first.
+        let flat = |min: Vec3| screen_priority(view_projection, min, Vec3::new(1.0, 1.0, 0.0));
+
+        assert!(flat(Vec3::new(-0.5, -0.5, -5.0)) > flat(Vec3::new(3.0, -0.5, -5.0)));
+
+        // the corners behind the camera can't be projected, so these are treated as covering the
+        // entire screen; the frustum test is what puts these last.
+        assert!(behind > near);
+        assert_eq!(priority(Vec3::splat(-0.5)), behind);
+    }
+}
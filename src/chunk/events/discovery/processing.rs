@@ -1,18 +1,26 @@
-use crate::chunk::events::discovery::QUEUE_PROCESS_LIMIT;
+use crate::chunk::discovery::{is_within_discovery, lod_for_distance};
 use crate::chunk::events::draw::ChunkDrawEvent;
 use crate::chunk::events::gen::ChunkGenerateEvent;
 use crate::chunk::events::mesh::ChunkMeshEvent;
 use crate::chunk::{
-    chunk::ChunkFlags,
+    chunk::{ChunkFlags, VoxelFace},
     event::ChunkCreateEvent,
-    registry::{ChunkRegistry, Coordinates},
+    origin::FloatingOrigin,
+    registry::{ChunkCoordinates, ChunkRegistry, Coordinates},
+    ChunkStats, DiscoverySettings, MeshSettings, MeshTopology, PerformanceSettings,
 };
+use crate::input::camera::PlayerController;
+use bevy::diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin};
 use bevy::prelude::*;
-use bevy::utils::HashSet;
+use bevy::utils::{HashMap, HashSet};
 use enumset::EnumSet;
 use futures_lite::future;
+use rayon::prelude::*;
 
-use super::{BusyLocations, ChunkDiscoveryTask, ProcessWriterType};
+use super::{
+    BusyLocations, ChunkDiscoveryEvent, ChunkDiscoveryTask, ProcessWriterType, QueueLimit,
+    MESH_DEFER_TIMEOUT,
+};
 
 pub fn process_discovery_tasks(
     mut commands: Commands,
@@ -21,94 +29,355 @@ pub fn process_discovery_tasks(
     mut generate_writer: EventWriter<ChunkGenerateEvent>,
     mut draw_writer: EventWriter<ChunkDrawEvent>,
     mut mesh_writer: EventWriter<ChunkMeshEvent>,
-    mut process_queue: Local<Vec<ProcessWriterType>>,
+    mut discovery_writer: EventWriter<ChunkDiscoveryEvent>,
+    (mut process_queue, mut queue_limit): (Local<Vec<ProcessWriterType>>, Local<QueueLimit>),
+    // the chunks that have a mesh event waiting in the process queue, and the chunks that are
+    // waiting for their neighbors to be generated before they get meshed (along with since when).
+    (mut queued_meshes, mut deferred_meshes): (
+        Local<HashSet<Coordinates>>,
+        Local<HashMap<Coordinates, f64>>,
+    ),
     // is it worth to use a HashSet for this instead of a Vec?
     mut busy_locations: ResMut<BusyLocations>,
     mut last_time: Local<u128>,
-    mut registry: ResMut<ChunkRegistry>,
-    time: Res<Time>,
+    (registry, mut stats, mesh_settings): (
+        Res<ChunkRegistry>,
+        ResMut<ChunkStats>,
+        Res<MeshSettings>,
+    ),
+    discovery_settings: Res<DiscoverySettings>,
+    camera: Query<&Transform, With<PlayerController>>,
+    (time, diagnostics, performance): (
+        Res<Time>,
+        Option<Res<DiagnosticsStore>>,
+        Res<PerformanceSettings>,
+    ),
+    origin: Res<FloatingOrigin>,
 ) {
-    let mut busy_locations = &mut busy_locations.0;
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+
+    let camera_position = origin.to_chunk_space(camera.translation);
+    let camera_chunk = origin.to_chunk_coords(camera.translation);
+
+    // chunks outside of the discovery area are never going to be loaded.
+    let within_discovery = |coordinates: Coordinates| {
+        is_within_discovery(
+            camera_chunk,
+            coordinates.to_chunk_coords(),
+            &discovery_settings,
+        )
+    };
+
+    let busy_locations = &mut busy_locations.0;
+    let now = time.elapsed_seconds_f64();
 
     // clear the coordinate process list, we'll do this every 150 milliseconds,
     // less could probably work, but can't really tell too big of a difference.
     if time.elapsed().as_millis() - *last_time >= 150 {
+        // the cleared locations can be processed again, which requires a new scan to pick them up.
+        if !busy_locations.is_empty() {
+            discovery_writer.send(ChunkDiscoveryEvent);
+        }
+
         busy_locations.clear();
         *last_time = time.elapsed().as_millis();
+
+        // the chunks that left the discovery area (or that don't have to be meshed anymore) aren't
+        // going to be retried, so there's no point in keeping track of them.
+        deferred_meshes.retain(|coordinates, _| {
+            within_discovery(*coordinates)
+                && registry
+                    .get_chunk_at(*coordinates)
+                    .is_some_and(|chunk| chunk.is_dirty())
+        });
     }
 
-    let mut result = tasks
-        .iter_mut()
-        .flat_map(|(entity, mut task)| {
-            if let Some(data) = future::block_on(future::poll_once(&mut task.0)) {
-                commands.entity(entity).remove::<ChunkDiscoveryTask>();
-
-                let registry = &mut registry;
-                let mut process_list = &mut busy_locations;
-
-                let result: Vec<_> = data
-                    .into_iter()
-                    .flat_map(|coordinates| {
-                        if process_list.contains(&coordinates) {
-                            None
-                        } else {
-                            Some(process_event_data(coordinates, registry, &mut process_list))
-                        }
-                    })
-                    // double flatten, otherwise it would be a Vec<Option<T>>
-                    .flatten()
-                    .collect();
-
-                return Some(result);
-            }
-            None
-        })
-        .flatten()
-        .collect::<Vec<_>>();
+    let mut result = Vec::new();
+
+    for (entity, mut task) in tasks.iter_mut() {
+        let Some(data) = future::block_on(future::poll_once(&mut task.0)) else {
+            continue;
+        };
+
+        commands.entity(entity).despawn();
+
+        let discovered = inspect_discovered(
+            data,
+            busy_locations,
+            &registry,
+            &discovery_settings,
+            mesh_settings.topology,
+            camera_position,
+            &within_discovery,
+        );
+
+        stats.discovered = discovered.len();
+        stats.settled = discovered.iter().filter(|(_, _, settled)| *settled).count();
+
+        // everything that has to be written happens here, in the order the chunks were
+        // discovered in; see `inspect_discovered()`.
+        result.extend(
+            discovered
+                .into_iter()
+                .filter_map(|(coordinates, inspection, _)| {
+                    apply_inspection(
+                        coordinates,
+                        inspection,
+                        busy_locations,
+                        &mut deferred_meshes,
+                        now,
+                    )
+                }),
+        );
+    }
+
+    // a chunk only has to be meshed once, no matter how often it got marked as dirty in the
+    // meantime; the mesh is built from whatever the voxels are at the time it gets meshed.
+    result.retain(|writer_type| match writer_type {
+        ProcessWriterType::MeshWriter(event) => queued_meshes.insert(event.coordinates),
+        _ => true,
+    });
 
     if !result.is_empty() {
         process_queue.append(&mut result);
     }
 
+    // without a frame rate (such as during the first couple of frames, or without the
+    // diagnostics plugins), the limit stays as is.
+    if let Some(fps) = diagnostics
+        .as_ref()
+        .and_then(|diagnostics| diagnostics.get(FrameTimeDiagnosticsPlugin::FPS))
+        .and_then(|fps| fps.smoothed())
+    {
+        queue_limit.adapt(fps, performance.target_fps);
+    }
+
     // this slows down chunk loading, but the fps improvement far exceeds it.
     let length = process_queue.len();
-    let range = 0..length.min(QUEUE_PROCESS_LIMIT);
+    let range = 0..length.min(queue_limit.0);
 
     let iter = process_queue.drain(range);
 
     for writer_type in iter {
         match writer_type {
             ProcessWriterType::GenerateWriter(event) => generate_writer.send(event),
-            ProcessWriterType::MeshWriter(event) => mesh_writer.send(event),
+            ProcessWriterType::MeshWriter(event) => {
+                queued_meshes.remove(&event.coordinates);
+                mesh_writer.send(event);
+            }
             ProcessWriterType::DrawWriter(event) => draw_writer.send(event),
             ProcessWriterType::ChunkCreationWriter(event) => chunk_creation_writer.send(event),
         }
     }
 }
 
-fn process_event_data(
+// the least amount of chunks that are inspected by a single thread at once, see
+// `inspect_discovered()`. smaller scans aren't worth splitting up at all.
+const INSPECT_BATCH_SIZE: usize = 4096;
+
+/// What a discovered chunk needs next, as far as the registry is concerned; see
+/// `inspect_chunk()`.
+enum Inspection {
+    // the chunk isn't in the registry yet, so it has to be created first.
+    Missing,
+    Ready(Option<ProcessWriterType>),
+    // the chunk has to be meshed, which might have to wait for its neighbors.
+    Mesh {
+        event: ChunkMeshEvent,
+        neighbors_ready: bool,
+    },
+}
+
+/// Inspects the results of a discovery scan, skipping the chunks that are still busy. Every chunk
+/// comes with whether it's done loading, see [`is_settled()`].
+///
+/// At larger radii a scan returns hundreds of thousands of chunks, so these are inspected in
+/// parallel. This only reads from the registry (and the busy locations), the results are applied
+/// afterwards through `apply_inspection()`; the order of the scan is kept as is.
+fn inspect_discovered(
+    data: Vec<Coordinates>,
+    busy_locations: &HashSet<Coordinates>,
+    registry: &ChunkRegistry,
+    discovery_settings: &DiscoverySettings,
+    topology: MeshTopology,
+    camera_position: Vec3,
+    within_discovery: &(impl Fn(Coordinates) -> bool + Sync),
+) -> Vec<(Coordinates, Inspection, bool)> {
+    data.into_par_iter()
+        .with_min_len(INSPECT_BATCH_SIZE)
+        .map(|coordinates| {
+            let flags = registry
+                .get_chunk_at(coordinates)
+                .map(|chunk| chunk.get_flags());
+            let settled = flags.is_some_and(is_settled);
+
+            if busy_locations.contains(&coordinates) {
+                return (coordinates, Inspection::Ready(None), settled);
+            }
+
+            // distant chunks can be generated at a lower resolution right away, as they'll be
+            // meshed at a lower level of detail either way.
+            let lod = match discovery_settings.lod {
+                true => lod_for_distance(
+                    coordinates.to_chunk_coords().as_vec3() - camera_position,
+                    discovery_settings.lod_bias,
+                ),
+                false => 0,
+            };
+
+            let inspection = inspect_chunk(
+                coordinates,
+                flags,
+                lod,
+                topology,
+                registry,
+                within_discovery,
+            );
+
+            (coordinates, inspection, settled)
+        })
+        .collect()
+}
+
+/// Whether a chunk is done loading: it's either drawn, or it's been generated without any visible
+/// voxels, in which case it's never drawn at all. A chunk that's drawn stays settled while it's
+/// being meshed again.
+fn is_settled(flags: EnumSet<ChunkFlags>) -> bool {
+    flags.contains(ChunkFlags::Drawn)
+        || (flags.contains(ChunkFlags::Generated) && flags.contains(ChunkFlags::Empty))
+}
+
+fn inspect_chunk(
     coordinates: Coordinates,
-    registry: &mut ChunkRegistry,
+    flags: Option<EnumSet<ChunkFlags>>,
+    lod: u32,
+    topology: MeshTopology,
+    registry: &ChunkRegistry,
+    within_discovery: &impl Fn(Coordinates) -> bool,
+) -> Inspection {
+    let Some(mut flags) = flags else {
+        return Inspection::Missing;
+    };
+
+    // an empty chunk is normally never meshed, but a smooth surface crossing into one of its
+    // neighbors is still part of its mesh; see `borders_solid_neighbor()`.
+    if topology == MeshTopology::Smooth
+        && flags.contains(ChunkFlags::Empty)
+        && !flags.contains(ChunkFlags::Meshed)
+        && borders_solid_neighbor(registry, coordinates)
+    {
+        flags.remove(ChunkFlags::Empty);
+    }
+
+    match process_flags(coordinates, lod, &mut flags) {
+        Some(ProcessWriterType::MeshWriter(event)) => Inspection::Mesh {
+            event,
+            // only a smooth surface is built from the voxels of the neighbors, the faces (and
+            // points) of a chunk don't depend on them at all.
+            neighbors_ready: topology != MeshTopology::Smooth
+                || neighbors_ready(registry, coordinates, within_discovery),
+        },
+        result => Inspection::Ready(result),
+    }
+}
+
+fn apply_inspection(
+    coordinates: Coordinates,
+    inspection: Inspection,
     process_list: &mut HashSet<IVec3>,
+    deferred_meshes: &mut HashMap<Coordinates, f64>,
+    now: f64,
 ) -> Option<ProcessWriterType> {
-    let Some(chunk) = registry.get_chunk_at_mut(coordinates) else {
-        let event = ChunkCreateEvent { coordinates };
-        let writer = ProcessWriterType::ChunkCreationWriter(event);
+    let result = match inspection {
+        Inspection::Missing => {
+            let event = ChunkCreateEvent { coordinates };
+            let writer = ProcessWriterType::ChunkCreationWriter(event);
 
-        return Some(writer);
-    };
+            return Some(writer);
+        }
+        Inspection::Ready(result) => result,
+        // a smooth surface meshed before the neighbors of the chunk are generated would have to
+        // be redone once they are, so it's held back until they are. chunks on the edge of the
+        // discovery area never get all of their neighbors though, the ones outside of it are
+        // treated as air right away. if generation can't keep up, the chunk is meshed anyway once
+        // `MESH_DEFER_TIMEOUT` has passed.
+        Inspection::Mesh {
+            event,
+            neighbors_ready,
+        } => {
+            if !neighbors_ready {
+                let since = *deferred_meshes.entry(coordinates).or_insert(now);
+
+                if now - since < MESH_DEFER_TIMEOUT {
+                    // the busy locations get cleared every so often, which retries the chunk.
+                    process_list.insert(coordinates);
+
+                    return None;
+                }
+            }
+
+            deferred_meshes.remove(&coordinates);
 
-    let result = process_flags(coordinates, &mut chunk.get_flags());
+            Some(ProcessWriterType::MeshWriter(event))
+        }
+    };
 
-    if let Some(_) = result {
+    if result.is_some() {
         process_list.insert(coordinates);
     }
 
-    return result;
+    result
+}
+
+/// Whether a chunk is ready to be meshed as a smooth surface as far as its neighbors are
+/// concerned: every neighbor has either been generated, or lies outside of the discovery area,
+/// meaning it's not going to be loaded at all.
+///
+/// The surface reaches into the neighbors sharing an edge or a corner with the chunk as well, so
+/// these are waited for too.
+fn neighbors_ready(
+    registry: &ChunkRegistry,
+    coordinates: Coordinates,
+    within_discovery: &impl Fn(Coordinates) -> bool,
+) -> bool {
+    ChunkRegistry::surrounding_coordinates(coordinates)
+        .into_iter()
+        .all(|neighbor| {
+            !within_discovery(neighbor)
+                || registry
+                    .get_chunk_at(neighbor)
+                    .is_some_and(|chunk| chunk.is_generated())
+        })
+}
+
+/// Whether any of the neighbors on the positive side of a chunk has solid voxels on the face it
+/// shares with the chunk.
+///
+/// A smooth surface covers the edges starting within a chunk, which includes the ones crossing
+/// over into these neighbors (see `surface_quads()`). Without any visible voxels of its own, the
+/// chunk still has to be meshed for the surface of such a neighbor to be closed; the neighbors on
+/// the negative side cover the edges crossing into the chunk themselves.
+fn borders_solid_neighbor(registry: &ChunkRegistry, coordinates: Coordinates) -> bool {
+    let size = ChunkRegistry::chunk_dimensions();
+
+    [
+        (VoxelFace::Right, VoxelFace::Left),
+        (VoxelFace::Up, VoxelFace::Down),
+        (VoxelFace::Front, VoxelFace::Back),
+    ]
+    .iter()
+    .any(|(side, shared)| {
+        registry
+            .get_chunk_at(coordinates + side.offset() * size)
+            .is_some_and(|neighbor| neighbor.solid_faces[shared.index()] > 0)
+    })
 }
 
 fn process_flags(
     coordinates: Coordinates,
+    lod: u32,
     flags: &mut EnumSet<ChunkFlags>,
 ) -> Option<ProcessWriterType> {
     if flags.contains(ChunkFlags::Busy) {
@@ -117,20 +386,29 @@ fn process_flags(
 
     flags.insert(ChunkFlags::Busy);
 
-    if !flags.contains(ChunkFlags::Generated) && !flags.contains(ChunkFlags::Meshed) {
-        let event = ChunkGenerateEvent { coordinates };
-        let writer = ProcessWriterType::GenerateWriter(event);
+    // a generated chunk without any visible voxels doesn't need a mesh, nor does it have to be
+    // drawn; it's done as soon as it's generated. if the chunk has been meshed before (it got
+    // emptied after the fact), it still has to go through meshing to get rid of the old mesh.
+    if flags.contains(ChunkFlags::Generated)
+        && flags.contains(ChunkFlags::Empty)
+        && !flags.contains(ChunkFlags::Meshed)
+    {
+        flags.remove(ChunkFlags::Busy);
 
-        return Some(writer);
+        return None;
     }
 
-    if flags.contains(ChunkFlags::Meshed) && !flags.contains(ChunkFlags::Drawn) {
-        let event = ChunkDrawEvent { coordinates };
-        let writer = ProcessWriterType::DrawWriter(event);
+    if !flags.contains(ChunkFlags::Generated) && !flags.contains(ChunkFlags::Meshed) {
+        let event = ChunkGenerateEvent { coordinates, lod };
+        let writer = ProcessWriterType::GenerateWriter(event);
 
         return Some(writer);
     }
 
+    // a dirty chunk is meshed before it's drawn, drawing the outdated mesh first would only be
+    // undone right after. a chunk that's drawn already keeps showing its current mesh in the
+    // meantime; if the new mesh can't be swapped in place, the chunk is drawn again afterwards.
+    // see `process_chunk_meshing`.
     if flags.contains(ChunkFlags::Dirty) {
         let event = ChunkMeshEvent { coordinates };
         let writer = ProcessWriterType::MeshWriter(event);
@@ -138,9 +416,134 @@ fn process_flags(
         return Some(writer);
     }
 
+    if flags.contains(ChunkFlags::Meshed) && !flags.contains(ChunkFlags::Drawn) {
+        let event = ChunkDrawEvent { coordinates };
+        let writer = ProcessWriterType::DrawWriter(event);
+
+        return Some(writer);
+    }
+
     // none of the cases were met, meaning the chunk is not busy. if we don't remove the busy flag,
     // it will be marked as busy forever.
     flags.remove(ChunkFlags::Busy);
 
     None
 }
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::{Color, IVec3};
+    use enumset::{enum_set, EnumSet};
+    use half::f16;
+
+    use super::{inspect_chunk, process_flags, Inspection};
+    use crate::chunk::{
+        chunk::{Chunk, ChunkFlags},
+        events::discovery::ProcessWriterType,
+        registry::{ChunkRegistry, Coordinates},
+        voxel::Voxel,
+        MeshTopology,
+    };
+
+    #[test]
+    fn test_process_flags() {
+        use ChunkFlags::{Busy, Dirty, Drawn, Empty, Generated, Meshed};
+
+        let cases = [
+            // a busy chunk is left alone, whatever else it is.
+            (Busy | Dirty, None),
+            (Busy | Meshed, None),
+            (EnumSet::empty(), Some("generate")),
+            (enum_set!(Dirty), Some("generate")),
+            // nothing to mesh or draw, unless there's an old mesh to get rid of.
+            (Generated | Empty, None),
+            (Generated | Empty | Dirty, None),
+            (Generated | Empty | Meshed | Dirty, Some("mesh")),
+            (Generated | Dirty, Some("mesh")),
+            // drawn or not, an edited chunk is meshed again before it's drawn.
+            (Generated | Meshed | Dirty, Some("mesh")),
+            (Generated | Meshed | Drawn | Dirty, Some("mesh")),
+            (Generated | Meshed, Some("draw")),
+            (enum_set!(Meshed), Some("draw")),
+            (Generated | Meshed | Drawn, None),
+            (enum_set!(Generated), None),
+        ];
+
+        for (flags, expected) in cases {
+            let mut result_flags = flags;
+            let result =
+                process_flags(IVec3::ZERO, 0, &mut result_flags).map(|writer| match writer {
+                    ProcessWriterType::GenerateWriter(_) => "generate",
+                    ProcessWriterType::MeshWriter(_) => "mesh",
+                    ProcessWriterType::DrawWriter(_) => "draw",
+                    ProcessWriterType::ChunkCreationWriter(_) => "create",
+                });
+
+            assert_eq!(result, expected, "{flags:?}");
+
+            // a chunk is only left busy if something is going to happen to it, otherwise it
+            // would never be picked up again.
+            assert_eq!(
+                result_flags.contains(Busy),
+                flags.contains(Busy) || result.is_some(),
+                "{flags:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_smooth_border_of_empty_chunk() {
+        let registry = ChunkRegistry::new();
+        let dimensions = ChunkRegistry::chunk_dimensions();
+        let (width, height, depth) = (
+            dimensions.x as u32,
+            dimensions.y as u32,
+            dimensions.z as u32,
+        );
+
+        let push = |position: Coordinates, voxel: Voxel| {
+            let voxels = vec![voxel; (width * height * depth) as usize];
+            let mut chunk = Chunk::with_voxels(width, height, depth, position, voxels);
+
+            chunk.set_generated(true);
+            chunk.set_dirty(true);
+            registry.push_chunk_at(position, chunk);
+        };
+
+        // a chunk of air right below a solid one, and another one right above it.
+        let (below, above) = (IVec3::ZERO, IVec3::Y * dimensions * 2);
+
+        push(below, Voxel::default());
+        push(
+            IVec3::Y * dimensions,
+            Voxel::new_solid(Color::GRAY, f16::ONE),
+        );
+        push(above, Voxel::default());
+
+        let inspect = |coordinates: Coordinates, topology: MeshTopology| {
+            let flags = registry
+                .get_chunk_at(coordinates)
+                .map(|chunk| chunk.get_flags());
+
+            assert!(flags.is_some_and(|flags| flags.contains(ChunkFlags::Empty)));
+            inspect_chunk(coordinates, flags, 0, topology, &registry, &|_| true)
+        };
+
+        // the surface between the air and the solid chunk above it starts within the air, so
+        // that's where it's meshed.
+        assert!(matches!(
+            inspect(below, MeshTopology::Smooth),
+            Inspection::Mesh { .. }
+        ));
+        // the solid chunk covers the surface between itself and the air above it.
+        assert!(matches!(
+            inspect(above, MeshTopology::Smooth),
+            Inspection::Ready(None)
+        ));
+        // the faces of the solid chunk are its own, so the air doesn't have anything to mesh.
+        assert!(matches!(
+            inspect(below, MeshTopology::Faces),
+            Inspection::Ready(None)
+        ));
+    }
+}
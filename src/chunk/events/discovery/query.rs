@@ -1,100 +1,201 @@
-use bevy::{prelude::*, render::primitives::Frustum};
+use std::cmp::Reverse;
+
+use bevy::{
+    prelude::*,
+    render::primitives::{Frustum, HalfSpace},
+};
 use bevy_tasks::{AsyncComputeTaskPool, Task};
+use rayon::prelude::*;
 
 use crate::{
     chunk::{
+        discovery::is_within_discovery,
+        origin::FloatingOrigin,
         registry::{ChunkRegistry, Coordinates},
         DiscoverySettings,
     },
-    util::frustum::{create_frustum_points, is_in_frustum_batch_unsized},
+    input::camera::PlayerController,
+    util::frustum::is_box_in_frustum,
 };
 
-use super::ChunkDiscoveryTask;
+use super::{screen_priority, ChunkDiscoveryEvent, ChunkDiscoveryTask};
+
+/// The state of the camera during the last discovery scan.
+#[derive(Default)]
+pub struct LastDiscovery {
+    center: Coordinates,
+    // whether a rescan was requested through a `ChunkDiscoveryEvent` while we weren't able to
+    // spawn a new task yet.
+    requested: bool,
+    scanned: bool,
+}
 
 pub fn handle_chunk_discovery(
     mut commands: Commands,
+    mut reader: EventReader<ChunkDiscoveryEvent>,
+    mut last: Local<LastDiscovery>,
     discovery_settings: Res<DiscoverySettings>,
-    transform: Query<(&Transform, &Frustum)>,
+    transform: Query<(&Transform, &Frustum, &Camera, &GlobalTransform), With<PlayerController>>,
+    pending: Query<(), With<ChunkDiscoveryTask>>,
+    origin: Res<FloatingOrigin>,
 ) {
-    let (transform, frustum) = transform.single();
-
-    let translation = transform.translation;
-
-    let chunk_size = ChunkRegistry::CHUNK_SIZE as f32;
-    let chunk_height = ChunkRegistry::CHUNK_HEIGHT as f32;
-
-    let center_chunk_x = (translation.x / chunk_size) as i32;
-    let center_chunk_y = (translation.y / chunk_height) as i32;
-    let center_chunk_z = (translation.z / chunk_size) as i32;
-
-    let (radius, radius_height) = (
-        discovery_settings.discovery_radius as i32,
-        discovery_settings.discovery_radius_height as i32,
-    );
-
-    let task = spawn_discovery_task(
-        (center_chunk_x, center_chunk_y, center_chunk_z),
-        (radius, radius_height),
-        (chunk_size, chunk_height),
-        &frustum,
-    );
+    // we have to consume the events even if we're not going to scan this frame, otherwise the
+    // request would get lost once the events are dropped.
+    if !reader.is_empty() {
+        reader.clear();
+        last.requested = true;
+    }
+
+    // the previous scan is still running; spawning another one would only redo the exact same
+    // work, as the results of this one haven't even been processed yet.
+    if !pending.is_empty() {
+        return;
+    }
+
+    let Ok((transform, frustum, camera, global_transform)) = transform.get_single() else {
+        return;
+    };
+
+    let center_chunk = origin.to_chunk_coords(transform.translation);
+
+    // standing still (or moving within the same chunk) doesn't change the result of the scan, so
+    // there's no point in doing it again; unless something else requested it, such as a chunk
+    // finishing generation and being ready for the next step. the direction the camera is
+    // looking at doesn't matter here, the frustum only affects the order the chunks are loaded in;
+    // bevy takes care of not rendering the chunks that are out of view.
+    let should_scan = !last.scanned
+        || last.requested
+        || discovery_settings.is_changed()
+        || last.center != center_chunk;
+
+    if !should_scan {
+        return;
+    }
+
+    *last = LastDiscovery {
+        center: center_chunk,
+        requested: false,
+        scanned: true,
+    };
+
+    let view = DiscoveryView {
+        half_spaces: frustum.half_spaces,
+        view_projection: camera.projection_matrix() * global_transform.compute_matrix().inverse(),
+        origin: origin.offset,
+    };
+
+    let task = spawn_discovery_task(center_chunk, discovery_settings.clone(), view);
 
     commands.spawn(ChunkDiscoveryTask(task));
 }
 
+/// The view of the camera at the time of a discovery scan, this decides the order the chunks are
+/// loaded in.
+struct DiscoveryView {
+    half_spaces: [HalfSpace; 6],
+    view_projection: Mat4,
+    // the render origin; the frustum and the camera are relative to this, see `FloatingOrigin`.
+    origin: Coordinates,
+}
+
+/// Scans the discovery area around the given chunk, returning the world positions of every chunk
+/// within it.
+///
+/// The chunks are sorted by how important they are visually: the chunks in view come first, from
+/// the ones covering the most of the screen to the ones covering the least (see
+/// `screen_priority()`). The results are processed in this order, so generation, meshing and
+/// drawing all follow it as well.
 fn spawn_discovery_task(
-    center_chunk: (i32, i32, i32),
-    radius: (i32, i32),
-    chunk_sizes: (f32, f32),
-    frustum: &Frustum,
+    center_chunk: Coordinates,
+    settings: DiscoverySettings,
+    view: DiscoveryView,
 ) -> Task<Vec<Coordinates>> {
     let pool = AsyncComputeTaskPool::get();
-    let spaces = frustum.half_spaces;
-    let radius_squared = radius.0.pow(2);
 
     pool.spawn(async move {
-        // reserve elements to avoid resizing the vector; if we don't do this we could resize the
-        // result vector thousands of times within the loop below.
-        let mut result = Vec::with_capacity((radius.0 * radius.0 * radius.1).try_into().expect(
-            "radius.0 * radius.0 * radius.1 does not fit in usize; is your chunk radius too big?",
-        ));
-
-        for x_offset in -radius.0..=radius.0 {
-            for z_offset in -radius.0..=radius.0 {
-                for y_offset in -radius.1..=radius.1 {
-                    if x_offset * x_offset + z_offset * z_offset >= radius_squared {
-                        continue;
-                    }
-
-                    let chunk_size = chunk_sizes.0 as i32;
-                    let chunk_height = chunk_sizes.1 as i32;
-
-                    let x = (center_chunk.0 + x_offset) * chunk_size;
-                    let y = (center_chunk.1 + y_offset) * chunk_height;
-                    let z = (center_chunk.2 + z_offset) * chunk_size;
-
-                    let point = Coordinates { x, y, z };
-
-                    let points = create_frustum_points(
-                        point,
-                        (
-                            ChunkRegistry::CHUNK_SIZE,
-                            ChunkRegistry::CHUNK_HEIGHT,
-                            ChunkRegistry::CHUNK_SIZE,
-                        )
-                            .into(),
-                    );
-
-                    if is_in_frustum_batch_unsized(points, spaces)
-                        .iter()
-                        .any(|result| *result)
-                    {
-                        result.push(point);
-                    }
-                }
-            }
-        }
-
-        result
+        let mut chunks = scan_discovery_area(center_chunk, &settings);
+
+        // the chunk is grown by a whole chunk on every side before testing it against the
+        // frustum, which gives a generous margin; the chunks right outside of the view are likely
+        // to come into view at any moment.
+        chunks.par_sort_by_cached_key(|chunk| {
+            let dimensions = ChunkRegistry::chunk_dimensions();
+            let min = *chunk - view.origin;
+
+            let in_view =
+                is_box_in_frustum(min - dimensions, min + dimensions * 2, &view.half_spaces);
+
+            let priority =
+                screen_priority(view.view_projection, min.as_vec3(), dimensions.as_vec3());
+
+            // the priority is never negative, the bits of these sort just like the floats do.
+            (!in_view, Reverse(priority.to_bits()))
+        });
+
+        chunks
     })
 }
+
+/// The world positions of every chunk within the discovery area around the given chunk, see
+/// `is_within_discovery()`.
+fn scan_discovery_area(
+    center_chunk: Coordinates,
+    settings: &DiscoverySettings,
+) -> Vec<Coordinates> {
+    let (radius, radius_height) = (
+        settings.discovery_radius as i32,
+        settings.discovery_radius_height as i32,
+    );
+
+    // every column of chunks is scanned separately; at larger radii this is tens of thousands of
+    // chunks, which is well worth splitting up.
+    (-radius..=radius)
+        .into_par_iter()
+        .flat_map_iter(|x_offset| {
+            (-radius..=radius).flat_map(move |z_offset| {
+                (-radius_height..=radius_height)
+                    .map(move |y_offset| {
+                        center_chunk + Coordinates::new(x_offset, y_offset, z_offset)
+                    })
+                    // the range above is the bounding box of the discovery area, the shape itself
+                    // is the same one unloading uses.
+                    .filter(move |chunk| is_within_discovery(center_chunk, *chunk, settings))
+                    .map(ChunkRegistry::chunk_to_world)
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::scan_discovery_area;
+    use crate::chunk::{
+        registry::{ChunkCoordinates, Coordinates},
+        DiscoverySettings, WorldBounds,
+    };
+
+    #[test]
+    fn test_bounds_are_never_discovered() {
+        let bounds = WorldBounds {
+            min: Coordinates::new(-2, -1, -2),
+            max: Coordinates::new(3, 1, 0),
+        };
+        let settings = DiscoverySettings {
+            bounds: Some(bounds),
+            ..Default::default()
+        };
+
+        // the camera is right on the edge of the world, most of the discovery area is outside.
+        let chunks = scan_discovery_area(Coordinates::new(3, 0, 0), &settings);
+
+        assert!(chunks
+            .iter()
+            .all(|chunk| bounds.contains(chunk.to_chunk_coords())));
+
+        // far outside of the world there's nothing to discover at all.
+        assert!(scan_discovery_area(Coordinates::splat(100), &settings).is_empty());
+
+        // the bounds are small enough to fit within the discovery area entirely.
+        assert_eq!(chunks.len(), 6 * 3 * 3);
+    }
+}
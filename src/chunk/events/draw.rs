@@ -1,58 +1,559 @@
 use crate::chunk::{
+    origin::FloatingOrigin,
+    palette::PaletteMaterial,
     registry::{ChunkRegistry, Coordinates},
-    ChunkEntity,
+    voxel::BlockMaterial,
+    ChunkEntity, DiscoverySettings, MeshSettings, MeshTopology,
 };
 
-use bevy::prelude::*;
-use bevy_tweening::*;
+use super::fade::{pop_in_animator, spawn_lod_fade};
+
+use bevy::{
+    ecs::system::EntityCommands,
+    pbr::wireframe::Wireframe,
+    prelude::*,
+    render::{primitives::Aabb, render_resource::Face, view::NoFrustumCulling},
+    utils::HashMap,
+};
 
 #[derive(Event)]
 pub struct ChunkDrawEvent {
     pub coordinates: Coordinates,
 }
 
+/// The materials every chunk is drawn with, one for every `BlockMaterial`. these are shared by all
+/// of the chunks, and only created once they're needed.
+#[derive(Resource, Default)]
+pub struct ChunkMaterials {
+    handles: HashMap<BlockMaterial, Handle<StandardMaterial>>,
+}
+
 pub fn draw_chunks(
     mut commands: Commands,
     mut reader: EventReader<ChunkDrawEvent>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    mut material_cache: Local<Option<Handle<StandardMaterial>>>,
-    mut registry: ResMut<ChunkRegistry>,
+    mut chunk_materials: ResMut<ChunkMaterials>,
+    registry: Res<ChunkRegistry>,
+    settings: Res<MeshSettings>,
+    discovery: Res<DiscoverySettings>,
+    meshes: Res<Assets<Mesh>>,
+    origin: Res<FloatingOrigin>,
+    // the fading material doesn't know about palettes, so the meshes that have one aren't faded.
+    drawn: Query<(&Handle<Mesh>, &Visibility), Without<Handle<PaletteMaterial>>>,
 ) {
-    let material = material_cache.get_or_insert_with(|| materials.add(StandardMaterial::default()));
+    let mut material_of = |material: BlockMaterial| {
+        chunk_materials
+            .handles
+            .entry(material)
+            .or_insert_with(|| materials.add(standard_material(material, &settings)))
+            .clone_weak()
+    };
     let iter = reader.iter();
+    let mut fades = Vec::new();
+
+    // bevy only computes the bounding box of an entity once, when it doesn't have one yet. we swap
+    // out the mesh of an existing entity whenever the chunk gets meshed again, so we have to
+    // update the bounding box ourselves; otherwise the entity would be frustum culled using the
+    // bounds of its very first mesh. see `process_chunk_meshing` as well.
+    let aabb_of = |mesh: &Handle<Mesh>| -> Aabb {
+        meshes
+            .get(mesh)
+            .and_then(|mesh| mesh.compute_aabb())
+            .unwrap_or_default()
+    };
 
     for ChunkDrawEvent { coordinates } in iter {
-        let Some(chunk) = registry.get_chunk_at_mut(*coordinates) else {
+        let Some(mut chunk) = registry.get_chunk_at_mut(*coordinates) else {
             continue;
         };
 
-        if let Some(mesh) = chunk.get_mesh() {
-            if let None = chunk.get_entity() {
-                chunk.set_entity(commands.spawn_empty().id());
+        // the entity might have been despawned by something else in the meantime; inserting into
+        // it would fail, so the chunk gets a new one instead. its children are gone along with it.
+        if let Some(entity) = chunk.get_entity() {
+            if commands.get_entity(entity).is_none() {
+                chunk.clear_entity();
+            }
+        }
+
+        // the meshes the entity is still showing, these are faded out if the chunk is drawn at a
+        // different level of detail now. see `spawn_lod_fade`.
+        let lod_changed = chunk.get_drawn_lod().is_some_and(|lod| lod != chunk.lod);
+
+        if let (Some(parent), true) = (chunk.get_entity(), lod_changed && settings.lod_fade > 0.0) {
+            let entities = std::iter::once((parent, BlockMaterial::Opaque)).chain(
+                chunk
+                    .submeshes
+                    .iter()
+                    .filter_map(|submesh| submesh.entity.map(|child| (child, submesh.material))),
+            );
+
+            for (entity, material) in entities {
+                // a hidden entity still has the mesh it showed before it got hidden. the chunk
+                // entity doesn't have one if the chunk only contains water (for example).
+                if let Ok((mesh, Visibility::Inherited | Visibility::Visible)) = drawn.get(entity) {
+                    if meshes.contains(mesh) {
+                        fades.push((parent, mesh.clone(), aabb_of(mesh), material));
+                    }
+                }
+            }
+        }
+
+        // the children are drawn through the chunk entity, so a chunk without any opaque voxels
+        // (such as a chunk of nothing but water) still needs one; it just doesn't have a mesh.
+        let has_geometry = chunk.get_mesh().is_some()
+            || chunk.submeshes.iter().any(|submesh| submesh.mesh.is_some());
+
+        if has_geometry {
+            let mesh = chunk.get_mesh().unwrap_or_default();
+
+            // the bundle is only inserted once, when the entity gets spawned. after that, the
+            // components stay in place and we only swap out the mesh and visibility; hiding a
+            // chunk is nothing more than setting its visibility (see `unload_distant_chunks`),
+            // so showing it again is just as cheap.
+            let entity = match chunk.get_entity() {
+                Some(entity) => {
+                    commands
+                        .entity(entity)
+                        .insert((aabb_of(&mesh), mesh, Visibility::Inherited));
+
+                    entity
+                }
+                None => {
+                    let entity = commands
+                        .spawn((
+                            ChunkEntity {
+                                position: *coordinates,
+                            },
+                            aabb_of(&mesh),
+                            MaterialMeshBundle {
+                                mesh,
+                                material: material_of(BlockMaterial::Opaque),
+                                transform: Transform::from_translation(
+                                    origin.to_render(*coordinates),
+                                ),
+                                ..Default::default()
+                            },
+                        ))
+                        .id();
+
+                    if settings.wireframe {
+                        commands.entity(entity).insert(Wireframe);
+                    }
+
+                    if !discovery.frustum_cull {
+                        commands.entity(entity).insert(NoFrustumCulling);
+                    }
+
+                    // the children are scaled along with the chunk entity.
+                    if settings.pop_in > 0.0 {
+                        commands.entity(entity).insert(pop_in_animator(&settings));
+                    }
+
+                    chunk.set_entity(entity);
+                    entity
+                }
+            };
+
+            insert_material(
+                &mut commands.entity(entity),
+                chunk.get_palette(),
+                material_of(BlockMaterial::Opaque),
+            );
+
+            // the voxels of the other materials are drawn by children of the chunk entity, these
+            // are shown and hidden along with it. there's only a child for the materials the chunk
+            // actually contains (or has contained), which is rarely more than one or two.
+            for submesh in &mut chunk.submeshes {
+                match (&submesh.mesh, submesh.entity) {
+                    (Some(mesh), Some(child)) => {
+                        commands.entity(child).insert((
+                            aabb_of(mesh),
+                            mesh.clone(),
+                            Visibility::Inherited,
+                        ));
+                        insert_material(
+                            &mut commands.entity(child),
+                            submesh.palette.clone(),
+                            material_of(submesh.material),
+                        );
+                    }
+                    (Some(mesh), None) => {
+                        let child = commands
+                            .spawn((
+                                aabb_of(mesh),
+                                MaterialMeshBundle {
+                                    mesh: mesh.clone(),
+                                    material: material_of(submesh.material),
+                                    ..Default::default()
+                                },
+                            ))
+                            .id();
+
+                        if settings.wireframe {
+                            commands.entity(child).insert(Wireframe);
+                        }
+
+                        if !discovery.frustum_cull {
+                            commands.entity(child).insert(NoFrustumCulling);
+                        }
+
+                        insert_material(
+                            &mut commands.entity(child),
+                            submesh.palette.clone(),
+                            material_of(submesh.material),
+                        );
+
+                        commands.entity(entity).add_child(child);
+                        submesh.entity = Some(child);
+                    }
+                    // the chunk no longer contains any voxels of this material. the old mesh is
+                    // dropped along with it, so nothing is left to show if the child is ever made
+                    // visible again.
+                    (None, Some(child)) => {
+                        commands
+                            .entity(child)
+                            .remove::<Handle<Mesh>>()
+                            .insert(Visibility::Hidden);
+                    }
+                    (None, None) => {}
+                }
+            }
+        } else if let Some(entity) = chunk.get_entity() {
+            // the chunk got meshed, but there's nothing to draw. it can still have an entity from
+            // before it got emptied, which has to be hidden (along with its children). the entity
+            // is shown again as soon as it's back in range (see `unload_distant_chunks`), so the
+            // old meshes have to go as well; otherwise these would be drawn again.
+            commands
+                .entity(entity)
+                .remove::<Handle<Mesh>>()
+                .insert(Visibility::Hidden);
+
+            for child in chunk.submeshes.iter().filter_map(|submesh| submesh.entity) {
+                commands.entity(child).remove::<Handle<Mesh>>();
+            }
+        }
+
+        chunk.set_drawn(true);
+        let drawn_lod = chunk.get_entity().map(|_| chunk.lod);
+
+        chunk.set_drawn_lod(drawn_lod);
+        chunk.set_busy(false);
+    }
+
+    for (parent, mesh, aabb, material) in fades {
+        spawn_lod_fade(
+            &mut commands,
+            &mut materials,
+            parent,
+            mesh,
+            aabb,
+            material,
+            &settings,
+        );
+    }
+}
+
+// the meshes whose colors got compressed are drawn with their own palette material, see
+// `MeshSettings::palette`. every other mesh is drawn with the shared material of its
+// `BlockMaterial`.
+fn insert_material(
+    entity: &mut EntityCommands,
+    palette: Option<Handle<PaletteMaterial>>,
+    standard: Handle<StandardMaterial>,
+) {
+    match palette {
+        Some(palette) => entity.remove::<Handle<StandardMaterial>>().insert(palette),
+        None => entity.remove::<Handle<PaletteMaterial>>().insert(standard),
+    };
+}
+
+/// Updates the face culling and lighting of the chunk materials whenever
+/// `MeshSettings::double_sided` or `MeshSettings::topology` change.
+pub fn apply_material_settings(
+    settings: Res<MeshSettings>,
+    chunk_materials: Res<ChunkMaterials>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut palettes: ResMut<Assets<PaletteMaterial>>,
+    mut previous: Local<Option<(bool, MeshTopology)>>,
+) {
+    // changing a material has bevy prepare it (and its pipeline) again, so the materials are only
+    // touched once one of these actually differs; see `inspector_ui`.
+    let current = (settings.double_sided, settings.topology);
+
+    if *previous == Some(current) {
+        return;
+    }
+
+    *previous = Some(current);
+
+    for (block_material, handle) in &chunk_materials.handles {
+        if let Some(material) = materials.get_mut(handle) {
+            set_cull_mode(material, &settings);
+            set_lighting(material, *block_material, &settings);
+        }
+    }
+
+    // every palette material belongs to a chunk mesh.
+    for (_, palette) in palettes.iter_mut() {
+        palette.set_cull_mode(&settings);
+    }
+}
+
+fn set_cull_mode(material: &mut StandardMaterial, settings: &MeshSettings) {
+    // `double_sided` flips the normals of the back faces, otherwise these would be lit as if they
+    // were facing the other way.
+    material.double_sided = settings.double_sided;
+    material.cull_mode = match settings.double_sided {
+        true => None,
+        false => Some(Face::Back),
+    };
+}
+
+fn set_lighting(material: &mut StandardMaterial, block: BlockMaterial, settings: &MeshSettings) {
+    // the brightness of emissive voxels is already part of their vertex colors, see
+    // `lighting::emissive_brightness`. points don't have a surface (nor normals) to be lit.
+    material.unlit = block == BlockMaterial::Emissive || settings.topology == MeshTopology::Points;
+}
+
+pub(super) fn standard_material(block: BlockMaterial, settings: &MeshSettings) -> StandardMaterial {
+    let mut material = match block {
+        BlockMaterial::Transparent => StandardMaterial {
+            alpha_mode: AlphaMode::Blend,
+            ..Default::default()
+        },
+        BlockMaterial::Opaque | BlockMaterial::Emissive => StandardMaterial::default(),
+    };
+
+    set_cull_mode(&mut material, settings);
+    set_lighting(&mut material, block, settings);
+    material
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::{
+        prelude::*,
+        render::{mesh::Mesh, render_resource::PrimitiveTopology},
+    };
+    use bevy_tweening::Animator;
+
+    use bevy::utils::HashSet;
+    use half::f16;
+
+    use super::{draw_chunks, ChunkDrawEvent, ChunkMaterials};
+    use crate::chunk::events::{
+        discovery::ChunkDiscoveryEvent,
+        fade::LodFade,
+        mesh::{mesh_chunk, process_chunk_meshing, ChunkMeshEvent, ChunkMeshTask},
+    };
+    use crate::chunk::{
+        block::BlockRegistry, chunk::Chunk, discovery::unload_distant_chunks,
+        events::discovery::BusyLocations, origin::FloatingOrigin, palette::PaletteMaterial,
+        registry::ChunkRegistry, timings::ChunkTimings, voxel::BlockMaterial, voxel::Voxel,
+        ChunkEntity, ChunkStats, DiscoverySettings, MeshSettings,
+    };
+    use crate::input::camera::PlayerController;
+
+    fn draw_app() -> App {
+        let mut app = App::new();
+
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_asset::<Mesh>()
+            .add_asset::<StandardMaterial>()
+            .add_event::<ChunkDrawEvent>()
+            .insert_resource(ChunkRegistry::new())
+            .insert_resource(MeshSettings::default())
+            .insert_resource(DiscoverySettings::default())
+            .init_resource::<ChunkMaterials>()
+            .init_resource::<FloatingOrigin>()
+            .add_systems(Update, draw_chunks);
+
+        app
+    }
+
+    #[test]
+    fn test_redraw_despawned_entity() {
+        let mut app = draw_app();
+        let position = IVec3::ZERO;
+        let mesh = app
+            .world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Mesh::new(PrimitiveTopology::TriangleList));
+
+        // the chunk was drawn before, but its entity got despawned behind its back.
+        let stale = app.world.spawn_empty().id();
+        app.world.despawn(stale);
+
+        let mut chunk = Chunk::new(1, 1, 1, position);
+        chunk.set_mesh(Some(mesh));
+        chunk.set_entity(stale);
+
+        app.world
+            .resource::<ChunkRegistry>()
+            .push_chunk_at(position, chunk);
+
+        app.world.send_event(ChunkDrawEvent {
+            coordinates: position,
+        });
+        app.update();
+
+        let registry = app.world.resource::<ChunkRegistry>();
+        let chunk = registry.get_chunk_at(position).unwrap();
+        let entity = chunk.get_entity().unwrap();
+
+        assert_ne!(entity, stale);
+        assert!(chunk.is_drawn());
+        assert!(app.world.get::<ChunkEntity>(entity).is_some());
+        // the new entity pops in, just like any other chunk that's drawn for the first time.
+        assert!(app.world.get::<Animator<Transform>>(entity).is_some());
+    }
+
+    #[test]
+    fn test_fade_previous_lod() {
+        let mut app = draw_app();
+        let position = IVec3::ZERO;
+
+        app.add_asset::<PaletteMaterial>()
+            .add_event::<ChunkMeshEvent>()
+            .add_event::<ChunkDiscoveryEvent>()
+            .init_resource::<BlockRegistry>()
+            .init_resource::<ChunkTimings>()
+            .add_systems(
+                Update,
+                (mesh_chunk, process_chunk_meshing).before(draw_chunks),
+            );
+
+        let mut chunk = Chunk::new(4, 4, 4, position);
+        chunk.set_voxel([1, 1, 1], Voxel::new_solid(Color::GRAY, f16::ONE));
+
+        app.world
+            .resource::<ChunkRegistry>()
+            .push_chunk_at(position, chunk);
+
+        let mesh = |app: &mut App| {
+            app.world.send_event(ChunkMeshEvent {
+                coordinates: position,
+            });
+
+            for _ in 0..1000 {
+                app.update();
+
+                let mut tasks = app.world.query::<&ChunkMeshTask>();
+
+                if tasks.iter(&app.world).next().is_none() {
+                    break;
+                }
             }
+        };
+
+        let draw = |app: &mut App| {
+            app.world.send_event(ChunkDrawEvent {
+                coordinates: position,
+            });
+            app.update();
+        };
+
+        mesh(&mut app);
+        draw(&mut app);
+
+        let entity = {
+            let registry = app.world.resource::<ChunkRegistry>();
+            let mut chunk = registry.get_chunk_at_mut(position).unwrap();
+
+            // the chunk moved further away, so it gets meshed at a lower level of detail.
+            chunk.set_lod(1);
+            chunk.get_entity().unwrap()
+        };
+        let previous = app.world.get::<Handle<Mesh>>(entity).unwrap().clone();
 
-            let entity = chunk.get_entity().expect("entity not found");
-            let mut entity_mut = commands.entity(entity);
-
-            // taken this from my old implementation, is this bad?
-            entity_mut
-                .remove::<Visibility>()
-                .remove::<MaterialMeshBundle<StandardMaterial>>()
-                .remove::<Animator<Transform>>()
-                .insert((
-                    ChunkEntity {
-                        position: *coordinates,
-                    },
-                    MaterialMeshBundle {
-                        mesh,
-                        material: material.clone_weak(),
-                        transform: Transform::from_translation(coordinates.as_vec3()),
-                        ..Default::default()
-                    },
-                ));
-
-            chunk.set_drawn(true);
-            chunk.set_busy(false);
+        mesh(&mut app);
+
+        {
+            let registry = app.world.resource::<ChunkRegistry>();
+            let chunk = registry.get_chunk_at(position).unwrap();
+
+            // the entity still shows the old mesh, it has to be drawn again to switch over.
+            assert!(!chunk.is_drawn());
+            assert_ne!(chunk.get_mesh(), Some(previous.clone()));
+            assert_eq!(app.world.get::<Handle<Mesh>>(entity), Some(&previous));
         }
+
+        draw(&mut app);
+
+        let mut fades = app
+            .world
+            .query_filtered::<(&Parent, &Handle<Mesh>), With<LodFade>>();
+        let (parent, fading) = fades.single(&app.world);
+        let registry = app.world.resource::<ChunkRegistry>();
+        let chunk = registry.get_chunk_at(position).unwrap();
+
+        assert_eq!(*fading, previous);
+        assert_eq!(parent.get(), entity);
+        assert_eq!(
+            app.world.get::<Handle<Mesh>>(entity),
+            chunk.get_mesh().as_ref()
+        );
+        assert!(app.world.resource::<Assets<Mesh>>().contains(&previous));
+        assert_eq!(chunk.get_drawn_lod(), Some(1));
+    }
+
+    #[test]
+    fn test_unload_emptied_chunk() {
+        let mut app = draw_app();
+        let position = IVec3::ZERO;
+
+        app.insert_resource(BusyLocations(HashSet::new()))
+            .init_resource::<ChunkStats>()
+            .add_systems(Update, unload_distant_chunks.after(draw_chunks));
+        app.world
+            .spawn((PlayerController::default(), Transform::default()));
+
+        let mut meshes = app.world.resource_mut::<Assets<Mesh>>();
+        let (mesh, water) = (
+            meshes.add(Mesh::new(PrimitiveTopology::TriangleList)),
+            meshes.add(Mesh::new(PrimitiveTopology::TriangleList)),
+        );
+
+        let mut chunk = Chunk::new(1, 1, 1, position);
+        chunk.set_mesh(Some(mesh));
+        chunk.set_submeshes(vec![(BlockMaterial::Transparent, water, None)]);
+
+        app.world
+            .resource::<ChunkRegistry>()
+            .push_chunk_at(position, chunk);
+
+        let draw = |app: &mut App| {
+            app.world.send_event(ChunkDrawEvent {
+                coordinates: position,
+            });
+            app.update();
+        };
+
+        draw(&mut app);
+
+        // every voxel of the chunk got removed, so it got meshed without anything to draw.
+        let (entity, child) = {
+            let registry = app.world.resource::<ChunkRegistry>();
+            let mut chunk = registry.get_chunk_at_mut(position).unwrap();
+
+            chunk.clear_mesh();
+            chunk.set_drawn(false);
+
+            (
+                chunk.get_entity().unwrap(),
+                chunk.submeshes[0].entity.unwrap(),
+            )
+        };
+
+        draw(&mut app);
+        // the chunk is within range, so unloading shows the entity again.
+        app.update();
+
+        assert_eq!(
+            app.world.get::<Visibility>(entity),
+            Some(&Visibility::Inherited)
+        );
+        assert!(app.world.get::<Handle<Mesh>>(entity).is_none());
+        assert!(app.world.get::<Handle<Mesh>>(child).is_none());
     }
 }
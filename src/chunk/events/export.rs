@@ -0,0 +1,65 @@
+use std::{fs::File, io::BufWriter, path::PathBuf};
+
+use bevy::prelude::*;
+
+use crate::{
+    chunk::{registry::ChunkRegistry, ChunkEntity},
+    util::export::write_obj,
+};
+
+/// Exports the meshes of all drawn chunks into a single OBJ file at the given path.
+#[derive(Event)]
+pub struct ChunkExportEvent {
+    pub path: PathBuf,
+}
+
+pub fn export_chunks(
+    mut reader: EventReader<ChunkExportEvent>,
+    registry: Res<ChunkRegistry>,
+    meshes: Res<Assets<Mesh>>,
+    chunks: Query<&ChunkEntity>,
+) {
+    for ChunkExportEvent { path } in reader.iter() {
+        let mut missing = 0;
+
+        let chunk_meshes = chunks
+            .iter()
+            .filter_map(|ChunkEntity { position }| registry.get_chunk_at(*position))
+            .filter(|chunk| chunk.is_drawn())
+            .flat_map(|chunk| {
+                let offset = chunk.world_position.as_vec3();
+
+                // the handles have to be collected, as the chunk is only borrowed within here.
+                let handles = std::iter::once(chunk.get_mesh())
+                    .chain(chunk.submeshes.iter().map(|submesh| submesh.mesh.clone()))
+                    .flatten()
+                    .collect::<Vec<_>>();
+
+                handles.into_iter().map(move |handle| (handle, offset))
+            })
+            // the handle could still point to a mesh that has been removed, or hasn't been
+            // uploaded yet; we'll just leave those out.
+            .filter_map(|(handle, offset)| match meshes.get(&handle) {
+                Some(mesh) => Some((mesh, offset)),
+                None => {
+                    missing += 1;
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let result =
+            File::create(path).and_then(|file| write_obj(&mut BufWriter::new(file), chunk_meshes));
+
+        match result {
+            Ok(written) => info!(
+                "exported {written} chunk meshes to {} ({missing} missing)",
+                path.display()
+            ),
+            Err(error) => error!(
+                "failed to export chunk meshes to {}: {error}",
+                path.display()
+            ),
+        }
+    }
+}
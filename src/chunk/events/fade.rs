@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, render::primitives::Aabb};
+use bevy_tweening::{
+    lens::TransformScaleLens, Animator, AssetAnimator, EaseFunction, Lens, Tween, TweenCompleted,
+};
+
+use crate::chunk::{voxel::BlockMaterial, MeshSettings};
+
+use super::draw::standard_material;
+
+// identifies the tweens of the fading meshes among the other `TweenCompleted` events.
+const LOD_FADE_EVENT: u64 = 0x10d;
+// same as the above, for the tweens of the chunks popping in.
+const POP_IN_EVENT: u64 = 0x909;
+
+// the height a chunk starts out at while popping in, relative to its actual height. this isn't
+// zero, as a flattened transform doesn't have a valid normal matrix.
+const POP_IN_SCALE: f32 = 0.01;
+
+/// A mesh of the previous level of detail of a chunk, which is faded out after the chunk got drawn
+/// at a different one; see `spawn_lod_fade`. It's despawned once it's fully transparent.
+#[derive(Component)]
+pub struct LodFade;
+
+/// Fades out the alpha of the `base_color` of a material, the vertex colors are multiplied by it.
+struct FadeOutLens;
+
+impl Lens<StandardMaterial> for FadeOutLens {
+    fn lerp(&mut self, target: &mut StandardMaterial, ratio: f32) {
+        target.base_color.set_a(1.0 - ratio);
+    }
+}
+
+/// Keeps drawing the previous mesh of a chunk on top of the new one for a little while, fading it
+/// out over `MeshSettings::lod_fade` seconds; this way a chunk doesn't visibly pop when its level
+/// of detail changes.
+///
+/// The mesh becomes a child of the chunk entity, so it's moved and hidden along with the chunk
+/// (and despawned along with it when the chunk gets unloaded).
+pub fn spawn_lod_fade(
+    commands: &mut Commands,
+    materials: &mut Assets<StandardMaterial>,
+    parent: Entity,
+    mesh: Handle<Mesh>,
+    aabb: Aabb,
+    material: BlockMaterial,
+    settings: &MeshSettings,
+) {
+    // every fading mesh gets its own material, as these all fade out at their own pace. the
+    // material is dropped together with the entity.
+    let material = materials.add(StandardMaterial {
+        alpha_mode: AlphaMode::Blend,
+        // the old and the new mesh mostly line up, which would z-fight while fading.
+        depth_bias: 16.0,
+        ..standard_material(material, settings)
+    });
+
+    let tween = Tween::new(
+        EaseFunction::QuadraticIn,
+        Duration::from_secs_f32(settings.lod_fade),
+        FadeOutLens,
+    )
+    .with_completed_event(LOD_FADE_EVENT);
+
+    let fade = commands
+        .spawn((
+            LodFade,
+            aabb,
+            AssetAnimator::new(material.clone(), tween),
+            MaterialMeshBundle {
+                mesh,
+                material,
+                ..Default::default()
+            },
+        ))
+        .id();
+
+    commands.entity(parent).add_child(fade);
+}
+
+/// Despawns the fading meshes once their tween is done.
+pub fn finish_lod_fades(
+    mut commands: Commands,
+    mut reader: EventReader<TweenCompleted>,
+    fades: Query<Entity, With<LodFade>>,
+) {
+    for TweenCompleted { entity, user_data } in reader.iter() {
+        // the chunk could have been unloaded in the meantime, which takes the fade along with it.
+        if *user_data == LOD_FADE_EVENT && fades.contains(*entity) {
+            commands.entity(*entity).despawn_recursive();
+        }
+    }
+}
+
+/// Grows a newly drawn chunk out of the ground over `MeshSettings::pop_in` seconds, rather than
+/// having it appear out of nowhere.
+///
+/// Only the scale of the chunk is tweened; the translation belongs to the floating origin, which
+/// can move the chunk while it's still popping in. See `FloatingOrigin`.
+pub fn pop_in_animator(settings: &MeshSettings) -> Animator<Transform> {
+    let tween = Tween::new(
+        EaseFunction::QuadraticOut,
+        Duration::from_secs_f32(settings.pop_in),
+        TransformScaleLens {
+            start: Vec3::new(1.0, POP_IN_SCALE, 1.0),
+            end: Vec3::ONE,
+        },
+    )
+    .with_completed_event(POP_IN_EVENT);
+
+    Animator::new(tween)
+}
+
+/// Removes the animator of a chunk once it's done popping in, so it isn't ticked every frame.
+pub fn finish_pop_ins(mut commands: Commands, mut reader: EventReader<TweenCompleted>) {
+    for TweenCompleted { entity, user_data } in reader.iter() {
+        if *user_data != POP_IN_EVENT {
+            continue;
+        }
+
+        // the chunk could have been unloaded in the meantime.
+        if let Some(mut entity) = commands.get_entity(*entity) {
+            entity.remove::<Animator<Transform>>();
+        }
+    }
+}
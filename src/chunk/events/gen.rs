@@ -1,81 +1,477 @@
-use bevy::prelude::*;
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::{prelude::*, utils::HashSet};
 use bevy_tasks::{AsyncComputeTaskPool, Task};
 use futures_lite::future;
+use half::f16;
 
-use crate::chunk::{
-    generation::generate_voxels,
-    registry::{ChunkRegistry, Coordinates},
-    voxel::Voxel,
-    GenerationSettings, OpenSimplexResource,
+use crate::{
+    chunk::{
+        chunk::{ChunkDimensions, ChunkFlags},
+        discovery::is_out_of_range,
+        events::{busy::ChunkTaskToken, discovery::ChunkDiscoveryEvent},
+        generation::{generate_voxels_into, generation_cost},
+        lighting::propagate_light,
+        mesh::max_lod,
+        noise_source::NoiseSource,
+        origin::FloatingOrigin,
+        pool::VoxelBufferPool,
+        registry::{ChunkCoordinates, ChunkRegistry, Coordinates},
+        timings::{measure, ChunkTimings},
+        voxel::Voxel,
+        DiscoverySettings, GenerationSettings, MeshSettings, MeshTopology, PerformanceSettings,
+    },
+    input::camera::PlayerController,
 };
 
 #[derive(Event)]
 pub struct ChunkGenerateEvent {
     pub coordinates: Coordinates,
+    // the level of detail to generate the chunk at, see `generate_voxels()`.
+    pub lod: u32,
+}
+
+/// Counts how often the `GenerationSettings` have changed, see `regenerate_outdated_chunks()`.
+///
+/// Every generation task is stamped with the revision it was spawned at; the results of tasks
+/// that were spawned before the latest change are thrown away, rather than ending up next to
+/// chunks that were generated using the new settings.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GenerationRevision(pub u32);
+
+/// The result of a generation task.
+pub struct GeneratedChunk {
+    coordinates: Coordinates,
+    voxels: Vec<Voxel>,
+    // the voxel the entire chunk is filled with, if every voxel came out the same. the chunk is
+    // stored as a single voxel in that case, see `VoxelStorage`.
+    uniform: Option<Voxel>,
+    light: Vec<u8>,
+    // empty for uniform chunks, just like the light.
+    density: Vec<f16>,
+    lod: u32,
+    revision: GenerationRevision,
+    // the revision of the chunk itself when the task got spawned, see `Chunk::revision`.
+    chunk_revision: u64,
+    elapsed: Option<Duration>,
 }
 
 #[derive(Component)]
-pub struct ChunkGenerationTask(Task<(Coordinates, Vec<Voxel>)>);
+pub struct ChunkGenerationTask(Task<GeneratedChunk>);
+
+/// The estimated cost of a running generation task, see `generation_cost()`.
+#[derive(Component)]
+pub struct GenerationCost(u64);
+
+/// The chunks waiting for a generation task, in the order they're generated in; see
+/// `generate_chunk()`. These are already marked as busy.
+#[derive(Resource, Default, Debug)]
+pub struct GenerationQueue {
+    queue: VecDeque<(Coordinates, u32)>,
+    queued: HashSet<Coordinates>,
+}
+
+impl GenerationQueue {
+    pub fn contains(&self, coordinates: Coordinates) -> bool {
+        self.queued.contains(&coordinates)
+    }
+}
 
 pub fn generate_chunk(
     mut commands: Commands,
     mut reader: EventReader<ChunkGenerateEvent>,
-    mut registry: ResMut<ChunkRegistry>,
+    registry: Res<ChunkRegistry>,
+    mut generation_queue: ResMut<GenerationQueue>,
+    running: Query<&GenerationCost, With<ChunkGenerationTask>>,
+    performance: Res<PerformanceSettings>,
     settings: Res<GenerationSettings>,
-    simplex: Res<OpenSimplexResource>,
+    noise: Res<NoiseSource>,
+    discovery: Res<DiscoverySettings>,
+    camera: Query<&Transform, With<PlayerController>>,
+    mut buffers: ResMut<VoxelBufferPool>,
+    (origin, revision): (Res<FloatingOrigin>, Res<GenerationRevision>),
 ) {
     let pool = AsyncComputeTaskPool::get();
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+
+    // the chunk the camera is in, see `is_out_of_range()`.
+    let camera_chunk = origin.to_chunk_coords(camera.translation);
+    let GenerationQueue { queue, queued } = &mut *generation_queue;
 
-    for ChunkGenerateEvent { coordinates } in reader.iter() {
-        let coordinates = *coordinates;
-        let Some(chunk) = registry.get_chunk_at_mut(coordinates) else {
+    for ChunkGenerateEvent { coordinates, lod } in reader.iter() {
+        if !queued.insert(*coordinates) {
+            continue;
+        }
+
+        // mark the chunk as busy while it's waiting in the queue, otherwise discovery would keep
+        // sending generation events for it.
+        if let Some(mut chunk) = registry.get_chunk_at_mut(*coordinates) {
+            chunk.set_busy(true);
+        }
+
+        queue.push_back((*coordinates, *lod));
+    }
+
+    // only spawn tasks while they fit within the budget; everything else stays queued until the
+    // running tasks have finished. the cost of a chunk depends on its level of detail and the
+    // settings, so this keeps the load the same no matter how expensive the chunks are.
+    let mut spent = running.iter().map(|cost| cost.0).sum::<u64>();
+
+    while let Some((coordinates, lod)) = queue.pop_front() {
+        let Some(chunk) = registry.get_chunk_at(coordinates) else {
+            queued.remove(&coordinates);
             continue;
         };
 
+        let cost = generation_cost(chunk.get_dimensions(), lod, &settings);
+
+        // a chunk that doesn't fit within the budget on its own is still generated, as long as
+        // nothing else is being generated.
+        if spent > 0 && spent + cost > performance.generation_budget {
+            queue.push_front((coordinates, lod));
+            break;
+        }
+
+        queued.remove(&coordinates);
+
+        // the camera might have moved (or teleported) away since the chunk got queued, there's no
+        // point in generating it anymore. it's no longer busy, so discovery can pick it up again
+        // once it comes back within range.
+        if is_out_of_range(camera_chunk, coordinates.to_chunk_coords(), &discovery) {
+            drop(chunk);
+
+            if let Some(mut chunk) = registry.get_chunk_at_mut(coordinates) {
+                chunk.set_busy(false);
+            }
+
+            continue;
+        }
+
+        spent += cost;
+
         let settings = settings.clone();
-        let simplex = simplex.0;
+        let noise = noise.noise().clone();
+        let revision = *revision;
 
         let world_position = chunk.world_position;
+        let chunk_revision = chunk.revision;
+        let dimensions = *chunk.get_dimensions();
+        let mut voxels = buffers.take(dimensions.volume());
 
         let task = pool.spawn(async move {
-            let voxels = generate_voxels(
-                &settings,
-                simplex,
-                world_position,
-                (
-                    ChunkRegistry::CHUNK_SIZE as u32,
-                    ChunkRegistry::CHUNK_HEIGHT as u32,
-                    ChunkRegistry::CHUNK_SIZE as u32,
-                ),
-            );
-
-            return (coordinates, voxels);
+            let ChunkDimensions {
+                width,
+                height,
+                depth,
+            } = dimensions;
+
+            let lod = lod.min(max_lod(&dimensions));
+            let mut density = Vec::new();
+
+            let ((uniform, light), elapsed) = measure(|| {
+                generate_voxels_into(
+                    &mut voxels,
+                    &mut density,
+                    &settings,
+                    noise.as_ref(),
+                    world_position,
+                    (width, height, depth),
+                    lod,
+                );
+
+                // most chunks are either entirely air or entirely solid, these don't need any
+                // light; see `Chunk::set_uniform()`.
+                let first = voxels[0];
+
+                if voxels.iter().all(|voxel| *voxel == first) {
+                    density.clear();
+
+                    return (Some(first), Vec::new());
+                }
+
+                // the light only depends on the voxels of the chunk (for now), so we might as
+                // well compute it here while we're still off the main thread.
+                (None, propagate_light(&voxels, &dimensions))
+            });
+
+            GeneratedChunk {
+                coordinates,
+                voxels,
+                uniform,
+                light,
+                density,
+                lod,
+                revision,
+                chunk_revision,
+                elapsed,
+            }
         });
 
-        commands.spawn(ChunkGenerationTask(task));
+        commands.spawn((
+            ChunkGenerationTask(task),
+            GenerationCost(cost),
+            ChunkTaskToken(coordinates),
+        ));
     }
 }
 
 pub fn process_chunk_generation(
     mut commands: Commands,
     mut tasks: Query<(Entity, &mut ChunkGenerationTask)>,
-    mut registry: ResMut<ChunkRegistry>,
+    registry: Res<ChunkRegistry>,
+    mut discovery_writer: EventWriter<ChunkDiscoveryEvent>,
+    mut buffers: ResMut<VoxelBufferPool>,
+    mut timings: ResMut<ChunkTimings>,
+    (revision, mesh_settings): (Res<GenerationRevision>, Res<MeshSettings>),
 ) {
     tasks.iter_mut().for_each(|(entity, mut task)| {
         let task = &mut task.0;
-        let Some((coordinates, voxels)) = future::block_on(future::poll_once(task)) else {
+        let Some(GeneratedChunk {
+            coordinates,
+            voxels,
+            uniform,
+            light,
+            density,
+            lod,
+            revision: generated_revision,
+            chunk_revision,
+            elapsed,
+        }) = future::block_on(future::poll_once(task))
+        else {
             return;
         };
 
-        commands.entity(entity).remove::<ChunkGenerationTask>();
+        commands.entity(entity).despawn();
+
+        if let Some(elapsed) = elapsed {
+            timings.generation.record(elapsed);
+        }
 
-        let Some(chunk) = registry.get_chunk_at_mut(coordinates) else {
+        let Some(mut chunk) = registry.get_chunk_at_mut(coordinates) else {
+            buffers.give(voxels);
             return;
         };
 
-        chunk.set_voxels(voxels);
+        // the settings changed while the chunk was being generated. it's no longer busy, so
+        // discovery sends it off to be generated again, this time using the new settings.
+        if generated_revision != *revision {
+            buffers.give(voxels);
+            chunk.set_busy(false);
+
+            discovery_writer.send(ChunkDiscoveryEvent);
+            return;
+        }
+
+        // the chunk got edited while it was being generated. the edits win, just like a modified
+        // chunk is never generated again; the light has to catch up with them though.
+        if chunk.revision != chunk_revision && chunk.is_modified() {
+            buffers.give(voxels);
+
+            if chunk.voxels.uniform().is_none() {
+                let dimensions = *chunk.get_dimensions();
+                let light =
+                    propagate_light(&chunk.voxels.to_dense(dimensions.volume()), &dimensions);
+
+                chunk.set_light(light);
+            }
+
+            chunk.set_busy(false);
+            chunk.set_dirty(true);
+
+            discovery_writer.send(ChunkDiscoveryEvent);
+            return;
+        }
+
+        let previous = match uniform {
+            Some(voxel) => {
+                buffers.give(voxels);
+                chunk.set_uniform(voxel)
+            }
+            None => {
+                let previous = chunk.replace_voxels(voxels);
+
+                chunk.set_light(light);
+                chunk.set_density(density);
+                previous
+            }
+        };
+
+        if let Some(previous) = previous {
+            buffers.give(previous);
+        }
+
+        chunk.set_generated_lod(lod);
+        // there's no point in meshing the chunk at a higher level of detail than it was generated
+        // at, the result would be the exact same.
+        let mesh_lod = chunk.get_lod().max(lod);
+        chunk.set_lod(mesh_lod);
         chunk.set_busy(false);
         chunk.set_dirty(true);
         chunk.set_generated(true);
+
+        drop(chunk);
+
+        // the smooth surfaces that were meshed without this chunk have to be meshed again, now
+        // that it's here; see `ChunkFlags::MissingNeighbors`. the surface reaches into the chunks
+        // sharing an edge or a corner as well.
+        if mesh_settings.topology == MeshTopology::Smooth {
+            for neighbor in ChunkRegistry::surrounding_coordinates(coordinates) {
+                if let Some(mut neighbor) = registry.get_chunk_at_mut(neighbor) {
+                    if neighbor.get_flags().contains(ChunkFlags::MissingNeighbors) {
+                        neighbor.set_dirty(true);
+                    }
+                }
+            }
+        }
+
+        // the chunk is ready to be meshed, which only happens through discovery.
+        discovery_writer.send(ChunkDiscoveryEvent);
+    });
+}
+
+/// Regenerates every chunk whenever the `GenerationSettings` (or the seed of the `NoiseSource`)
+/// change, so the whole world converges to the new settings; see `GenerationRevision`.
+///
+/// Just like when a chunk is regenerated at a higher level of detail, the old meshes stay in place
+/// until the new ones are ready. Chunks that are busy at the time are remembered, these are
+/// regenerated as soon as they're done with whatever they were doing. Modified chunks are never
+/// regenerated, see `ChunkFlags::Modified`.
+pub fn regenerate_outdated_chunks(
+    settings: Res<GenerationSettings>,
+    source: Res<NoiseSource>,
+    mut previous: Local<Option<(GenerationSettings, u32)>>,
+    mut outdated: Local<HashSet<Coordinates>>,
+    mut revision: ResMut<GenerationRevision>,
+    registry: Res<ChunkRegistry>,
+    mut discovery_writer: EventWriter<ChunkDiscoveryEvent>,
+) {
+    // regenerating the world is about as expensive as it gets, so this only happens once the
+    // settings (or the seed) differ from the ones the world was generated with.
+    let current = Some((&*settings, source.seed));
+    let changed = settings.is_changed() || source.is_changed();
+
+    if changed && previous.as_ref().map(|(settings, seed)| (settings, *seed)) != current {
+        if previous.is_some() {
+            revision.0 = revision.0.wrapping_add(1);
+
+            registry.for_each_chunk_mut(|chunk| {
+                outdated.insert(chunk.world_position);
+            });
+        }
+
+        *previous = Some((settings.clone(), source.seed));
+    }
+
+    if outdated.is_empty() {
+        return;
+    }
+
+    outdated.retain(|coordinates| {
+        let Some(mut chunk) = registry.get_chunk_at_mut(*coordinates) else {
+            return false;
+        };
+
+        if chunk.is_busy() {
+            return true;
+        }
+
+        if chunk.is_modified() {
+            return false;
+        }
+
+        chunk.set_generated(false);
+        chunk.set_flag(ChunkFlags::Meshed, false);
+
+        false
     });
+
+    discovery_writer.send(ChunkDiscoveryEvent);
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::*;
+    use bevy_tasks::AsyncComputeTaskPool;
+    use half::f16;
+
+    use super::{
+        process_chunk_generation, ChunkGenerationTask, GeneratedChunk, GenerationRevision,
+    };
+    use crate::chunk::{
+        chunk::Chunk,
+        events::discovery::ChunkDiscoveryEvent,
+        pool::VoxelBufferPool,
+        registry::{ChunkRegistry, Coordinates},
+        timings::ChunkTimings,
+        voxel::Voxel,
+        MeshSettings,
+    };
+
+    #[test]
+    fn test_edited_while_generating() {
+        let mut app = App::new();
+
+        app.add_plugins(MinimalPlugins)
+            .add_event::<ChunkDiscoveryEvent>()
+            .insert_resource(ChunkRegistry::new())
+            .insert_resource(VoxelBufferPool::new(1))
+            .insert_resource(MeshSettings::default())
+            .init_resource::<ChunkTimings>()
+            .init_resource::<GenerationRevision>()
+            .add_systems(Update, process_chunk_generation);
+
+        let position = Coordinates::ZERO;
+        let solid = Voxel::new_solid(Color::GRAY, f16::ONE);
+
+        let mut chunk = Chunk::new(4, 4, 4, position);
+        chunk.set_generated(true);
+        chunk.set_busy(true);
+
+        let chunk_revision = chunk.revision;
+
+        // the chunk gets edited while it's being generated again, which comes out as all air.
+        chunk.set_voxel([1, 1, 1], solid);
+        chunk.mark_edited(IVec3::new(1, 1, 1));
+
+        app.world
+            .resource::<ChunkRegistry>()
+            .push_chunk_at(position, chunk);
+
+        let generated = GeneratedChunk {
+            coordinates: position,
+            voxels: vec![Voxel::default(); 4 * 4 * 4],
+            uniform: Some(Voxel::default()),
+            light: Vec::new(),
+            density: Vec::new(),
+            lod: 0,
+            revision: GenerationRevision::default(),
+            chunk_revision,
+            elapsed: None,
+        };
+
+        let task = AsyncComputeTaskPool::get().spawn(async move { generated });
+        app.world.spawn(ChunkGenerationTask(task));
+
+        for _ in 0..1000 {
+            app.update();
+
+            let mut tasks = app.world.query::<&ChunkGenerationTask>();
+
+            if tasks.iter(&app.world).next().is_none() {
+                break;
+            }
+        }
+
+        let registry = app.world.resource::<ChunkRegistry>();
+        let chunk = registry.get_chunk_at(position).unwrap();
+
+        assert_eq!(chunk.get_voxel([1, 1, 1]), Some(&solid));
+        assert!(!chunk.is_busy());
+        assert!(chunk.is_dirty());
+
+        // the light is propagated again, through the edited voxels.
+        assert_eq!(chunk.light.len(), 4 * 4 * 4);
+    }
 }
@@ -1,11 +1,21 @@
-use bevy::prelude::*;
+use std::time::Duration;
+
+use bevy::{prelude::*, utils::HashSet};
 use bevy_tasks::{AsyncComputeTaskPool, Task};
 use futures_lite::future;
 
 use crate::chunk::{
-    mesh::mesh,
+    block::BlockRegistry,
+    chunk::{Chunk, ChunkFlags},
+    collider::{greedy_boxes, ColliderBox},
+    events::{busy::ChunkTaskToken, discovery::ChunkDiscoveryEvent},
+    mesh::ChunkMeshes,
+    palette::PaletteMaterial,
     registry::{ChunkRegistry, Coordinates},
-    MeshSettings,
+    surface_nets::Neighborhood,
+    timings::{measure, ChunkTimings},
+    voxel::BlockMaterial,
+    MeshSettings, MeshTopology,
 };
 
 #[derive(Event, Clone)]
@@ -13,37 +23,88 @@ pub struct ChunkMeshEvent {
     pub coordinates: Coordinates,
 }
 
+// everything a meshing task hands back to `process_chunk_meshing`.
+type MeshTaskResult = (
+    Option<ChunkMeshes>,
+    Vec<ColliderBox>,
+    Coordinates,
+    // the revision and the amount of edits the mesh was built from, see
+    // `Chunk::finish_meshing()`.
+    (u64, usize),
+    Option<Duration>,
+);
+
 #[derive(Component)]
-pub struct ChunkMeshTask(Task<Option<(Mesh, Coordinates)>>);
+pub struct ChunkMeshTask(Task<Option<MeshTaskResult>>);
 
 pub fn mesh_chunk(
     mut commands: Commands,
     mut reader: EventReader<ChunkMeshEvent>,
-    mut registry: ResMut<ChunkRegistry>,
+    registry: Res<ChunkRegistry>,
     settings: Res<MeshSettings>,
+    blocks: Res<BlockRegistry>,
 ) {
     let pool = AsyncComputeTaskPool::get();
 
+    // multiple events for the same chunk within a single frame would all mesh the exact same
+    // voxels, so only the first one is kept.
+    let mut seen = HashSet::new();
+
     for event in reader.iter() {
         let ChunkMeshEvent { coordinates } = event;
 
         let coordinates = *coordinates;
-        let registry = &mut registry;
 
-        if let Some(chunk) = registry.get_chunk_at_mut(coordinates) {
+        if !seen.insert(coordinates) {
+            continue;
+        }
+
+        // a smooth surface is built from the voxels of the neighbors as well. these have to be
+        // gathered before the chunk itself gets locked, as they could share a shard with it.
+        let neighbors = match settings.topology {
+            MeshTopology::Smooth => Some(Neighborhood::gather(&registry, coordinates)),
+            _ => None,
+        };
+
+        if let Some(mut chunk) = registry.get_chunk_at_mut(coordinates) {
             chunk.set_busy(true);
 
             let settings = settings.clone();
-            let dimensions = *chunk.get_dimensions();
+            let blocks = blocks.clone();
+
+            // this shares the voxels with the chunk (through an Arc<T>), rather than cloning them.
+            let mut snapshot = chunk.snapshot();
+
+            // the neighbors that are still missing might be loaded later on (e.g. once the camera
+            // moves towards them), at which point the chunk has to be meshed again; see
+            // `ChunkFlags::MissingNeighbors`. the faces (and points) don't depend on them.
+            let missing = neighbors
+                .as_ref()
+                .is_some_and(|neighbors| !neighbors.is_complete());
+            chunk.set_flag(ChunkFlags::MissingNeighbors, missing);
 
-            let lod = chunk.get_lod();
+            if let Some(neighbors) = neighbors {
+                snapshot.neighbors = neighbors;
+            }
 
-            // we clone an Arc<T> here, not the voxels themselves
-            let voxels = chunk.get_voxels().clone();
+            let covered = (snapshot.revision, snapshot.edits.len());
 
-            commands.spawn(ChunkMeshTask(pool.spawn(async move {
-                return Some((mesh(&voxels, lod, settings, &dimensions), coordinates));
-            })));
+            let task = pool.spawn(async move {
+                let ((meshes, colliders), elapsed) = measure(|| {
+                    // the colliders are built from the same voxels as the mesh, this way they can
+                    // never go out of sync with what's actually being rendered.
+                    let colliders = match settings.colliders {
+                        true => greedy_boxes(&snapshot.voxels(), &snapshot.dimensions),
+                        false => Vec::new(),
+                    };
+
+                    (snapshot.mesh(settings, &blocks), colliders)
+                });
+
+                return Some((meshes, colliders, coordinates, covered, elapsed));
+            });
+
+            commands.spawn((ChunkMeshTask(task), ChunkTaskToken(coordinates)));
         }
     }
 }
@@ -51,28 +112,155 @@ pub fn mesh_chunk(
 pub fn process_chunk_meshing(
     mut commands: Commands,
     mut tasks: Query<(Entity, &mut ChunkMeshTask)>,
-    mut registry: ResMut<ChunkRegistry>,
+    registry: Res<ChunkRegistry>,
     mut meshes: ResMut<Assets<Mesh>>,
+    mut palette_materials: ResMut<Assets<PaletteMaterial>>,
+    settings: Res<MeshSettings>,
+    mut discovery_writer: EventWriter<ChunkDiscoveryEvent>,
+    mut timings: ResMut<ChunkTimings>,
 ) {
     tasks.iter_mut().for_each(|(entity, mut task)| {
         let task = &mut task.0;
-        let Some(Some((mesh, coordinates))) = future::block_on(future::poll_once(task)) else {
+        let Some(Some((chunk_meshes, colliders, coordinates, (revision, edits), elapsed))) =
+            future::block_on(future::poll_once(task))
+        else {
             return;
         };
 
-        commands.entity(entity).remove::<ChunkMeshTask>();
+        commands.entity(entity).despawn();
 
-        let Some(chunk) = registry.get_chunk_at_mut(coordinates) else {
+        if let Some(elapsed) = elapsed {
+            timings.meshing.record(elapsed);
+        }
+
+        let Some(mut chunk) = registry.get_chunk_at_mut(coordinates) else {
             return;
         };
 
-        let mesh_id = match chunk.get_mesh() {
-            Some(handle) => meshes.set(handle, mesh),
-            None => meshes.add(mesh),
-        };
+        let previous = drawn_handles(&chunk);
+
+        // a mesh of another level of detail doesn't replace the one that's drawn in place, it's
+        // uploaded under a handle of its own instead. this way the entity keeps showing the old
+        // mesh until the chunk gets drawn again, which is what `draw_chunks` fades out.
+        let lod_changed = chunk.get_drawn_lod().is_some_and(|lod| lod != chunk.lod);
+
+        match chunk_meshes {
+            Some(ChunkMeshes {
+                opaque,
+                submeshes,
+                mut palettes,
+                layout,
+            }) => {
+                // the entities that are already drawn keep their mesh handles, so bevy doesn't
+                // notice the mesh changed; their bounding boxes have to be updated right away,
+                // otherwise they'd be culled using the bounds of the old mesh until the chunk
+                // gets drawn again.
+                let mut update_aabb = |entity: Option<Entity>, mesh: &Mesh| {
+                    // the entity keeps showing the old mesh until it's drawn again, see above.
+                    if lod_changed {
+                        return;
+                    }
 
-        chunk.set_mesh(mesh_id);
+                    if let (Some(entity), Some(aabb)) = (entity, mesh.compute_aabb()) {
+                        commands.entity(entity).insert(aabb);
+                    }
+                };
+
+                // the existing handles are reused, so the entities don't have to be updated. that
+                // is, unless the mesh gained (or lost) its palette or the level of detail changed;
+                // the entity keeps drawing the old mesh then, until it's drawn again.
+                let mut upload = |existing: Option<Handle<Mesh>>,
+                                  existing_palette: Option<Handle<PaletteMaterial>>,
+                                  mesh: Mesh,
+                                  material: BlockMaterial| {
+                    let palette = palettes[material.index()]
+                        .take()
+                        .map(|colors| PaletteMaterial::new(colors, material, &settings));
+
+                    match existing.filter(|_| !lod_changed) {
+                        Some(handle) if existing_palette.is_some() == palette.is_some() => (
+                            meshes.set(handle, mesh),
+                            existing_palette
+                                .zip(palette)
+                                .map(|(handle, palette)| palette_materials.set(handle, palette)),
+                        ),
+                        _ => (
+                            meshes.add(mesh),
+                            palette.map(|palette| palette_materials.add(palette)),
+                        ),
+                    }
+                };
+
+                let (mesh_id, palette) = match opaque {
+                    Some(opaque) => {
+                        update_aabb(chunk.get_entity(), &opaque);
+
+                        let (id, palette) = upload(
+                            chunk.get_mesh(),
+                            chunk.get_palette(),
+                            opaque,
+                            BlockMaterial::Opaque,
+                        );
+
+                        (Some(id), palette)
+                    }
+                    // the chunk only contains voxels of the other materials, such as water.
+                    None => (None, None),
+                };
+
+                let submesh_ids = submeshes
+                    .into_iter()
+                    .map(|(material, mesh)| {
+                        let submesh = chunk.get_submesh(material);
+                        let existing = submesh.and_then(|submesh| submesh.mesh.clone());
+                        let existing_palette = submesh.and_then(|submesh| submesh.palette.clone());
+
+                        update_aabb(submesh.and_then(|submesh| submesh.entity), &mesh);
+
+                        let (id, palette) = upload(existing, existing_palette, mesh, material);
+
+                        (material, id, palette)
+                    })
+                    .collect();
+
+                chunk.set_mesh(mesh_id);
+                chunk.set_palette(palette);
+                chunk.set_submeshes(submesh_ids);
+                chunk.set_layout(layout);
+            }
+            // nothing to upload; the chunk is still meshed, it just doesn't have anything to draw.
+            // dropping the old handles frees up the meshes it had before.
+            None => chunk.clear_mesh(),
+        }
+
+        // the entity of a drawn chunk only picks up meshes that were swapped in place; a new
+        // handle (or no mesh at all anymore) has to be drawn again, otherwise the entity would
+        // keep showing the old mesh.
+        if chunk.is_drawn() && drawn_handles(&chunk) != previous {
+            chunk.set_drawn(false);
+        }
+
+        chunk.set_colliders(colliders);
         chunk.set_busy(false);
-        chunk.set_dirty(false);
+        // the chunk might have been edited in the meantime, which still has to be meshed.
+        chunk.finish_meshing(revision, edits);
+
+        // the chunk is ready to be drawn, which only happens through discovery.
+        discovery_writer.send(ChunkDiscoveryEvent);
     });
 }
+
+// the mesh of an entity, along with its palette material if it has one.
+type DrawnHandles = (Option<Handle<Mesh>>, Option<Handle<PaletteMaterial>>);
+
+// every mesh (and palette) handle the entities of a chunk are drawing, see `draw_chunks`.
+fn drawn_handles(chunk: &Chunk) -> Vec<DrawnHandles> {
+    std::iter::once((chunk.get_mesh(), chunk.get_palette()))
+        .chain(
+            chunk
+                .submeshes
+                .iter()
+                .map(|submesh| (submesh.mesh.clone(), submesh.palette.clone())),
+        )
+        .collect()
+}
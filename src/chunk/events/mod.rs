@@ -1,4 +1,7 @@
+pub mod busy;
 pub mod discovery;
 pub mod draw;
+pub mod export;
+pub mod fade;
 pub mod gen;
 pub mod mesh;
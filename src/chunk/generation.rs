@@ -1,105 +1,743 @@
 use bevy::prelude::*;
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::chunk::{voxel::Voxel, GenerationSettings};
+use crate::chunk::{
+    chunk::ChunkDimensions,
+    mesh::max_lod,
+    noise_source::DynNoise,
+    voxel::{BlockType, Voxel},
+    GenerationSettings,
+};
 use half::f16;
-use noise::{NoiseFn, OpenSimplex};
 
+// how far every octave is moved through the noise, relative to the previous one; see
+// `fractal_noise()`.
+const OCTAVE_OFFSET: f64 = 17.31;
+
+const GLOWSTONE_THRESHOLD: f64 = 0.8;
+const GLOWSTONE_COLOR: Color = Color::rgb(1.0, 0.85, 0.45);
+
+const WATER_COLOR: Color = Color::rgba(0.15, 0.35, 0.8, 0.6);
+
+const LAVA_HEAT: f64 = 0.9;
+const LAVA_COLOR: Color = Color::rgb(1.0, 0.35, 0.05);
+
+// the color of the solid voxels if there aren't any height bands at all.
+const FALLBACK_COLOR: Color = Color::GRAY;
+
+// the density is kept within this range, far from the surface it can grow beyond what a `f16`
+// can hold. only the densities around the surface matter anyway.
+const MAX_DENSITY: f64 = 1024.0;
+
+/// A band of terrain, colored based on its (world) height.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HeightBand {
+    // the band covers every voxel below this y level, that isn't already covered by a previous band.
+    pub max_height: f64,
+    pub color: Color,
+}
+
+/// The height bands the terrain is colored with by default; sand around the sea level, followed by
+/// grass, rock and snow caps on top.
+pub fn default_height_bands() -> Vec<HeightBand> {
+    vec![
+        HeightBand {
+            max_height: 10.0,
+            color: Color::rgb(0.86, 0.8, 0.55),
+        },
+        HeightBand {
+            max_height: 40.0,
+            color: Color::rgb(0.3, 0.6, 0.25),
+        },
+        HeightBand {
+            max_height: 70.0,
+            color: Color::rgb(0.5, 0.5, 0.5),
+        },
+        HeightBand {
+            max_height: f64::MAX,
+            color: Color::rgb(0.95, 0.95, 0.95),
+        },
+    ]
+}
+
+/// Generates the voxels of a chunk.
+///
+/// The noise is sampled once for every cube of `2^lod` voxels on each axis, and that voxel is used
+/// for the entire cube. Distant chunks get meshed at a lower level of detail either way, so there's
+/// no point in sampling every single voxel for those. The level of detail is clamped the same way
+/// as it is while meshing, see [`max_lod()`](../mesh/fn.max_lod.html).
+///
+/// The result always contains every voxel of the chunk, regardless of the level of detail.
 pub fn generate_voxels(
     settings: &GenerationSettings,
-    simplex: OpenSimplex,
+    noise: &DynNoise,
+    world_position: IVec3,
+    dimensions: (u32, u32, u32),
+    lod: u32,
+) -> Vec<Voxel> {
+    let mut voxels = Vec::new();
+
+    generate_voxels_into(
+        &mut voxels,
+        &mut Vec::new(),
+        settings,
+        noise,
+        world_position,
+        dimensions,
+        lod,
+    );
+
+    voxels
+}
+
+/// Generates the voxels of a chunk into an existing buffer, see [`generate_voxels()`]. the buffer
+/// is resized to fit every voxel of the chunk, whatever it contained before is overwritten.
+///
+/// The density of every voxel is written to `density` the same way, see `Chunk::density`.
+pub fn generate_voxels_into(
+    voxels: &mut Vec<Voxel>,
+    density: &mut Vec<f16>,
+    settings: &GenerationSettings,
+    noise: &DynNoise,
+    world_position: IVec3,
+    (width, height, depth): (u32, u32, u32),
+    lod: u32,
+) {
+    let dimensions = ChunkDimensions {
+        width,
+        height,
+        depth,
+    };
+
+    let lod = lod.min(max_lod(&dimensions));
+    let lod_dimensions = ChunkDimensions {
+        width: width >> lod,
+        height: height >> lod,
+        depth: depth >> lod,
+    };
+
+    // the lattice is only worth it if it's coarser than the samples themselves.
+    let lattice = settings.interpolated_noise.then(|| {
+        let resolution = settings.lattice_resolution.max(1 << lod);
+
+        NoiseLattice::new(settings, noise, world_position, &dimensions, resolution)
+    });
+
+    let sample = |index: usize| {
+        let local = lod_dimensions.index_to_local(index) << lod;
+
+        match &lattice {
+            Some(lattice) => sample_interpolated_voxel(
+                world_position + local.as_ivec3(),
+                lattice,
+                settings,
+                noise,
+            ),
+            None => sample_voxel(
+                local,
+                world_position,
+                (width, height, depth),
+                settings,
+                noise,
+            ),
+        }
+    };
+
+    // resizing never reallocates a buffer that's already large enough, such as one that's being
+    // reused from a previous chunk.
+    voxels.resize(dimensions.volume(), Voxel::default());
+    density.resize(dimensions.volume(), f16::ZERO);
+
+    let to_f16 = |value: f64| f16::from_f64(value.clamp(-MAX_DENSITY, MAX_DENSITY));
+
+    if lod == 0 {
+        voxels
+            .par_iter_mut()
+            .zip(density.par_iter_mut())
+            .enumerate()
+            .for_each(|(index, (voxel, density))| {
+                let (sample, value) = sample(index);
+
+                *voxel = sample;
+                *density = to_f16(value);
+            });
+
+        return;
+    }
+
+    let samples: Vec<(Voxel, f64)> = (0..lod_dimensions.volume())
+        .into_par_iter()
+        .map(sample)
+        .collect();
+
+    // spread every sample over the cube of voxels it represents.
+    voxels
+        .par_iter_mut()
+        .zip(density.par_iter_mut())
+        .enumerate()
+        .for_each(|(index, (voxel, density))| {
+            let (sample, value) =
+                samples[lod_dimensions.local_to_index(dimensions.index_to_local(index) >> lod)];
+
+            *voxel = sample;
+            *density = to_f16(value);
+        });
+}
+
+/// An estimate of how much work generating a chunk is, in voxel operations: every sample of the
+/// noise (see [`generate_voxels()`]) goes through every octave. With `interpolated_noise`, only the
+/// points of the lattice do; the samples are interpolated from these instead.
+///
+/// This is what the generation tasks are budgeted by, see `PerformanceSettings::generation_budget`.
+pub fn generation_cost(
+    dimensions: &ChunkDimensions,
+    lod: u32,
+    settings: &GenerationSettings,
+) -> u64 {
+    let lod = lod.min(max_lod(dimensions));
+    let samples = (dimensions.volume() >> (lod * 3)) as u64;
+    let octaves = settings.octaves.max(1) as u64;
+
+    if !settings.interpolated_noise {
+        return samples * octaves;
+    }
+
+    // the chunk is rarely aligned to the lattice, so there's usually an extra point on every axis.
+    let resolution = settings.lattice_resolution.max(1 << lod);
+    let points = [dimensions.width, dimensions.height, dimensions.depth]
+        .map(|length| (length / resolution + 2) as u64);
+
+    samples + points.iter().product::<u64>() * octaves
+}
+
+/// The terrain noise of a chunk, sampled at a coarse grid of points (every `resolution` voxels on
+/// each axis) rather than at every voxel; the voxels in between are trilinearly interpolated.
+///
+/// The grid is aligned to the world rather than to the chunk, so the points on the border between
+/// two chunks are at the exact same world positions in both lattices. Both chunks interpolate
+/// between the same values there, which keeps the terrain seamless.
+pub struct NoiseLattice {
+    // the world position of the first point, a multiple of the resolution.
+    origin: IVec3,
+    resolution: u32,
+    // the amount of points on every axis.
+    size: UVec3,
+    values: Vec<f64>,
+}
+
+impl NoiseLattice {
+    /// Samples the lattice points covering every voxel of a chunk.
+    pub fn new(
+        settings: &GenerationSettings,
+        noise: &DynNoise,
+        world_position: IVec3,
+        dimensions: &ChunkDimensions,
+        resolution: u32,
+    ) -> Self {
+        let step = resolution.max(1) as i32;
+        let origin = world_position.div_euclid(IVec3::splat(step)) * step;
+
+        // the last voxel of the chunk needs a point at or past it.
+        let last = world_position
+            + UVec3::new(dimensions.width, dimensions.height, dimensions.depth).as_ivec3()
+            - IVec3::ONE;
+        let size = ((last - origin) / step + 2).as_uvec3();
+
+        let values = (0..size.x * size.y * size.z)
+            .into_par_iter()
+            .map(|index| {
+                let point = IVec3::new(
+                    (index % size.x) as i32,
+                    (index / size.x % size.y) as i32,
+                    (index / (size.x * size.y)) as i32,
+                );
+
+                let world = (origin + point * step).as_dvec3() * settings.frequency_scale;
+
+                fractal_noise(
+                    noise,
+                    world.to_array(),
+                    settings.octaves,
+                    settings.persistence,
+                )
+            })
+            .collect();
+
+        Self {
+            origin,
+            resolution: step as u32,
+            size,
+            values,
+        }
+    }
+
+    /// The interpolated noise at the given world position, which has to be within the chunk the
+    /// lattice was created for.
+    pub fn sample(&self, world: IVec3) -> f64 {
+        let step = self.resolution as i32;
+        let offset = world - self.origin;
+
+        let cell = offset / step;
+        let t = (offset - cell * step).as_dvec3() / step as f64;
+
+        let value_at = |x: i32, y: i32, z: i32| {
+            let point = (cell + IVec3::new(x, y, z)).as_uvec3();
+
+            self.values
+                [(point.x + point.y * self.size.x + point.z * self.size.x * self.size.y) as usize]
+        };
+
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+
+        let x00 = lerp(value_at(0, 0, 0), value_at(1, 0, 0), t.x);
+        let x10 = lerp(value_at(0, 1, 0), value_at(1, 1, 0), t.x);
+        let x01 = lerp(value_at(0, 0, 1), value_at(1, 0, 1), t.x);
+        let x11 = lerp(value_at(0, 1, 1), value_at(1, 1, 1), t.x);
+
+        lerp(lerp(x00, x10, t.y), lerp(x01, x11, t.y), t.z)
+    }
+}
+
+/// Samples the voxel at the given (chunk-local) coordinates of a chunk, along with its density;
+/// see [`terrain_density()`].
+///
+/// This is the only place the terrain noise gets evaluated per voxel, every other generation path
+/// should go through this function (or [`sample_interpolated_voxel()`]) to make sure they all
+/// produce the same terrain.
+pub fn sample_voxel(
+    UVec3 { x, y, z }: UVec3,
     IVec3 {
         x: world_pos_x,
         y: world_pos_y,
         z: world_pos_z,
     }: IVec3,
     (width, height, depth): (u32, u32, u32),
-) -> Vec<Voxel> {
-    let mut voxels: Vec<Voxel> =
-        // preallocate voxel vector with Voxel::default() values (empty voxels).
-        // avoids re-allocating the vector, and ensures the vector is always the same length.
-        vec![Voxel::default(); (width * height * depth).try_into().unwrap()];
-
+    settings: &GenerationSettings,
+    noise: &DynNoise,
+) -> (Voxel, f64) {
     let frequency_scale: f64 = settings.frequency_scale;
-    let amplitude_scale: f64 = settings.amplitude_scale;
-    let threshold: f64 = settings.threshold;
-
     let octaves: i32 = settings.octaves;
     let persistence: f64 = settings.persistence;
 
-    let amplitudes: Vec<f64> = (0..octaves).map(|i| persistence.powi(i)).collect(); // Precompute amplitudes
-
     let width_scale = frequency_scale / width as f64;
     let height_scale = frequency_scale / height as f64;
 
-    voxels
-        .par_iter_mut()
-        .enumerate()
-        .for_each(|(index, voxel)| {
-            let z = index / (width * height) as usize;
-            let y = (index % (width * height) as usize) / width as usize;
-            let x = index % width as usize;
+    let z_coord = (z as f64 + world_pos_z as f64) * frequency_scale;
+    let z_offset = z_coord + (z as f64 / depth as f64) * width_scale;
 
-            let z_coord = (z as f64 + world_pos_z as f64) * frequency_scale;
-            let z_offset = z_coord + (z as f64 / depth as f64) * width_scale;
+    let x_coord = (x as f64 + world_pos_x as f64) * frequency_scale;
+    let x_offset = x_coord + (x as f64 / width as f64) * width_scale;
 
-            let x_coord = (x as f64 + world_pos_x as f64) * frequency_scale;
-            let x_offset = x_coord + (x as f64 / width as f64) * width_scale;
+    let y_coord = (y as f64 + world_pos_y as f64) * frequency_scale;
+    let y_offset = y_coord + (y as f64 / height as f64) * height_scale;
 
-            let y_coord = (y as f64 + world_pos_y as f64) * frequency_scale;
-            let y_offset = y_coord + (y as f64 / height as f64) * height_scale;
+    let point = [x_offset, y_offset, z_offset];
+    let noise_value = fractal_noise(noise, point, octaves, persistence);
+    let world_y = y as f64 + world_pos_y as f64;
+    let density = terrain_density(noise_value, world_y, settings);
 
-            let mut noise_value = 0.0;
-            let value = simplex.get([x_offset, y_offset, z_offset]);
+    (
+        voxel_from_density(density, point, world_y, settings, noise),
+        density,
+    )
+}
 
-            noise_value += amplitudes
-                .iter()
-                .zip([value].iter().cycle())
-                .map(|(amp, &val)| amp * val)
-                .sum::<f64>();
+/// Samples the voxel at the given world position along with its density, interpolating the
+/// terrain noise from a lattice rather than evaluating it; see
+/// `GenerationSettings::interpolated_noise`.
+pub fn sample_interpolated_voxel(
+    world: IVec3,
+    lattice: &NoiseLattice,
+    settings: &GenerationSettings,
+    noise: &DynNoise,
+) -> (Voxel, f64) {
+    let point = (world.as_dvec3() * settings.frequency_scale).to_array();
+    let density = terrain_density(lattice.sample(world), world.y as f64, settings);
 
-            noise_value *= amplitude_scale;
-            noise_value += (y as f64 / height as f64) * 4.0;
+    (
+        voxel_from_density(density, point, world.y as f64, settings, noise),
+        density,
+    )
+}
 
-            if noise_value > threshold {
-                let heat = ((noise_value - threshold) / (amplitude_scale - threshold))
-                    .max(0.0)
-                    .min(1.0);
+/// How far the terrain noise of a voxel is past the threshold: positive within the terrain,
+/// negative outside of it. The surface of the terrain lies where this crosses zero, which makes
+/// for a smooth surface when it's interpolated between the voxels on either side.
+pub fn terrain_density(noise_value: f64, world_y: f64, settings: &GenerationSettings) -> f64 {
+    // bias the voxels below the surface towards being solid, and the ones above it towards air.
+    // this has to be based on the world y rather than the y within the chunk, otherwise every
+    // vertically stacked chunk would end up with a surface of its own.
+    noise_value * settings.amplitude_scale
+        + (settings.surface_level - world_y) / settings.height_falloff
+        - settings.threshold
+}
 
-                *voxel = Voxel::new_solid(
-                    generate_color_from_height(y_offset) + generate_color_from_heat(heat),
-                    f16::from_f32(1.0),
-                );
-            }
-        });
+// turns the density of a voxel into the voxel itself, `point` is where the noise was sampled at.
+// this is shared by every way of getting the noise, so they all produce the same kind of terrain.
+fn voxel_from_density(
+    density: f64,
+    [x_offset, y_offset, z_offset]: [f64; 3],
+    world_y: f64,
+    settings: &GenerationSettings,
+    noise: &DynNoise,
+) -> Voxel {
+    let amplitude_scale: f64 = settings.amplitude_scale;
+    let threshold: f64 = settings.threshold;
+    let sea_level: f64 = settings.sea_level;
 
-    voxels
+    if density > 0.0 {
+        let heat = (density / (amplitude_scale - threshold)).max(0.0).min(1.0);
+
+        // sample the noise at a much higher frequency for the emissive blocks, this
+        // gives us small veins of glowstone scattered throughout the terrain.
+        let vein = noise.get([x_offset * 8.0, y_offset * 8.0, z_offset * 8.0]);
+
+        if heat >= LAVA_HEAT {
+            Voxel::new_block(BlockType::Lava, LAVA_COLOR, f16::from_f32(1.0))
+        } else if vein >= GLOWSTONE_THRESHOLD {
+            Voxel::new_block(BlockType::Glowstone, GLOWSTONE_COLOR, f16::from_f32(1.0))
+        } else {
+            Voxel::new_solid(
+                color_from_height(&settings.height_bands, world_y) * color_from_heat(heat),
+                f16::from_f32(1.0),
+            )
+        }
+    } else if world_y < sea_level {
+        // only the voxels which didn't end up solid get filled with water, this also
+        // floods any caves below the sea level.
+        Voxel::new_block(BlockType::Water, WATER_COLOR, f16::from_f32(1.0))
+    } else {
+        Voxel::default()
+    }
 }
 
+/// Fractal brownian motion: the sum of `octaves` samples of the noise, every octave sampled at
+/// twice the frequency of the previous one and weighted by `persistence` times the weight of the
+/// previous one. The first octave gives the overall shape of the terrain, the others add
+/// increasingly finer detail on top.
+pub fn fractal_noise(noise: &DynNoise, point: [f64; 3], octaves: i32, persistence: f64) -> f64 {
+    (0..octaves)
+        .map(|octave| {
+            let frequency = 2f64.powi(octave);
+            // without an offset, every octave would line up with the others around the origin.
+            let offset = octave as f64 * OCTAVE_OFFSET;
+
+            persistence.powi(octave) * noise.get(point.map(|axis| axis * frequency + offset))
+        })
+        .sum()
+}
+
+/// Gets the tint of a solid voxel based on how deep it is within the terrain; this is multiplied
+/// with the color of its height band.
 #[inline]
-fn generate_color_from_heat(heat: f64) -> Color {
+fn color_from_heat(heat: f64) -> [f32; 3] {
     const DARK_FACTOR: f64 = 0.6;
     const SENSITIVITY: f64 = 5.0;
 
-    let modified_heat = (heat * SENSITIVITY).max(0.0);
+    // the heat has to stay within 0 and 1, the square roots below would produce NaN colors for
+    // negative values otherwise. unlike `clamp()`, `max()` and `min()` also get rid of NaN
+    // itself, which the heat can be if the amplitude scale is equal to the threshold.
+    #[allow(clippy::manual_clamp)]
+    let modified_heat = (heat * SENSITIVITY).max(0.0).min(1.0);
 
     let r = (1.0 - modified_heat).sqrt() * (1.0 - DARK_FACTOR) + DARK_FACTOR;
     let g = modified_heat.sqrt() * (1.0 - DARK_FACTOR) + DARK_FACTOR;
-    let b = (modified_heat - 1.0).sqrt() * (1.0 - DARK_FACTOR) + DARK_FACTOR;
+    let b = (1.0 - modified_heat).sqrt() * (1.0 - DARK_FACTOR) + DARK_FACTOR;
 
-    Color::rgb(r as f32, g as f32, b as f32)
+    [r as f32, g as f32, b as f32]
 }
 
+/// Gets the color of the first band the given world y falls within. anything above the last band
+/// gets the color of the last band.
 #[inline]
-fn generate_color_from_height(height: f64) -> Color {
-    const DARK_FACTOR: f64 = 1.0;
-    const HEIGHT_RANGE: f64 = 100.0; // Adjust this based on your height data
+fn color_from_height(bands: &[HeightBand], y: f64) -> Color {
+    bands
+        .iter()
+        .find(|band| y < band.max_height)
+        .or(bands.last())
+        .map_or(FALLBACK_COLOR, |band| band.color)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{
+        color_from_heat, default_height_bands, fractal_noise, generate_voxels,
+        generate_voxels_into, generation_cost, sample_voxel, NoiseLattice,
+    };
+    use crate::chunk::{
+        chunk::ChunkDimensions,
+        noise_source::{NoiseSource, NoiseType},
+        GenerationSettings,
+    };
+    use bevy::prelude::IVec3;
+    use half::f16;
+    use noise::Constant;
+
+    #[test]
+    fn test_generate_matches_sample() {
+        let settings = GenerationSettings {
+            frequency_scale: 0.03,
+            amplitude_scale: 20.0,
+            threshold: 0.4,
+            octaves: 2,
+            persistence: 0.5,
+            sea_level: 8.0,
+            surface_level: 16.0,
+            height_falloff: 8.0,
+            noise: NoiseType::OpenSimplex,
+            height_bands: default_height_bands(),
+            interpolated_noise: false,
+            lattice_resolution: 4,
+        };
+
+        let source = NoiseSource::new(NoiseType::OpenSimplex, 1234);
+        let noise = source.noise().as_ref();
+        let world_position = IVec3::new(-16, 0, 32);
+        let dimensions = (8, 16, 4);
+
+        let voxels = generate_voxels(&settings, noise, world_position, dimensions, 0);
+
+        let chunk_dimensions = ChunkDimensions {
+            width: 8,
+            height: 16,
+            depth: 4,
+        };
+
+        for (index, voxel) in voxels.iter().enumerate() {
+            let coordinates = chunk_dimensions.index_to_local(index);
+
+            assert_eq!(
+                *voxel,
+                sample_voxel(coordinates, world_position, dimensions, &settings, noise).0
+            );
+        }
+    }
+
+    #[test]
+    fn test_heat_color_is_never_nan() {
+        let samples = (-1000..=1000).map(|step| step as f64 / 100.0).chain([
+            f64::NAN,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+        ]);
+
+        for heat in samples {
+            for channel in color_from_heat(heat) {
+                assert!((0.0..=1.0).contains(&channel), "{heat} gives {channel}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_surface_is_world_based() {
+        let settings = GenerationSettings {
+            frequency_scale: 0.03,
+            amplitude_scale: 20.0,
+            threshold: 0.4,
+            octaves: 2,
+            persistence: 0.5,
+            sea_level: -1024.0,
+            surface_level: 0.0,
+            height_falloff: 8.0,
+            noise: NoiseType::OpenSimplex,
+            height_bands: default_height_bands(),
+            interpolated_noise: false,
+            lattice_resolution: 4,
+        };
+
+        let source = NoiseSource::new(NoiseType::OpenSimplex, 1234);
+        let noise = source.noise().as_ref();
+        let dimensions = (4, 4, 4);
+
+        // the noise can't get anywhere close to the bias this far away from the surface, so every
+        // chunk far below it has to be solid and every chunk far above it has to be air.
+        for (chunk_y, solid) in [(-512, true), (-256, true), (256, false), (512, false)] {
+            let voxels =
+                generate_voxels(&settings, noise, IVec3::new(0, chunk_y, 0), dimensions, 0);
 
-    let normalized_height = height / HEIGHT_RANGE;
+            assert!(voxels.iter().all(|voxel| voxel.is_solid == solid));
+        }
+    }
 
-    let r = (1.0 - normalized_height).sqrt() * (1.0 - DARK_FACTOR) + DARK_FACTOR;
-    let g = normalized_height.sqrt() * (1.0 - DARK_FACTOR) + DARK_FACTOR;
-    let b = (normalized_height - 1.0).sqrt() * (1.0 - DARK_FACTOR) + DARK_FACTOR;
+    #[test]
+    fn test_generation_cost() {
+        let dimensions = ChunkDimensions {
+            width: 32,
+            height: 32,
+            depth: 32,
+        };
+        let settings = GenerationSettings {
+            octaves: 4,
+            ..Default::default()
+        };
 
-    Color::rgb(r as f32, g as f32, b as f32)
+        assert_eq!(generation_cost(&dimensions, 0, &settings), 32 * 32 * 32 * 4);
+
+        // every level of detail samples an eighth of the voxels of the previous one.
+        assert_eq!(generation_cost(&dimensions, 2, &settings), 8 * 8 * 8 * 4);
+
+        // a chunk without any octaves still has to go over every single voxel.
+        let settings = GenerationSettings {
+            octaves: 0,
+            ..settings
+        };
+
+        assert_eq!(generation_cost(&dimensions, 0, &settings), 32 * 32 * 32);
+    }
+
+    #[test]
+    fn test_octaves_add_detail() {
+        let source = NoiseSource::new(NoiseType::OpenSimplex, 1234);
+        let noise = source.noise().as_ref();
+
+        let points = (0..16).map(|step| [step as f64 * 0.37, 1.5, step as f64 * -0.21]);
+        let ratios = points
+            .map(|point| fractal_noise(noise, point, 3, 0.5) / fractal_noise(noise, point, 1, 0.5))
+            .collect::<Vec<_>>();
+
+        // if every octave sampled the exact same value, more octaves would only scale the first
+        // one; the ratio would be the same everywhere.
+        assert!(ratios.iter().any(|ratio| (ratio - ratios[0]).abs() > 0.1));
+        assert_eq!(fractal_noise(noise, [1.0, 2.0, 3.0], 0, 0.5), 0.0);
+    }
+
+    #[test]
+    fn test_octave_weights() {
+        // every octave samples the same value here, leaving only the weights of the octaves.
+        let noise = Constant::new(1.0);
+
+        for octaves in 0..6 {
+            let expected = (0..octaves).map(|octave| 0.5f64.powi(octave)).sum::<f64>();
+
+            assert_eq!(
+                fractal_noise(&noise, [0.3, 1.0, -2.0], octaves, 0.5),
+                expected
+            );
+        }
+
+        // the first octave always has the full weight, whatever the persistence.
+        assert_eq!(fractal_noise(&noise, [0.0; 3], 3, 2.0), 1.0 + 2.0 + 4.0);
+    }
+
+    #[test]
+    fn test_lattice_is_seamless() {
+        let settings = GenerationSettings::default();
+        let source = NoiseSource::new(NoiseType::OpenSimplex, 1234);
+        let noise = source.noise().as_ref();
+
+        let dimensions = ChunkDimensions {
+            width: 8,
+            height: 8,
+            depth: 8,
+        };
+        let both = ChunkDimensions {
+            width: 16,
+            ..dimensions
+        };
+
+        // two neighboring chunks, neither of which is aligned to the lattice, and a lattice
+        // covering both of them at once.
+        let (left, right) = (IVec3::new(-11, 3, 5), IVec3::new(-3, 3, 5));
+        let lattices = [left, right]
+            .map(|position| NoiseLattice::new(&settings, noise, position, &dimensions, 4));
+        let shared = NoiseLattice::new(&settings, noise, left, &both, 4);
+
+        for (lattice, position) in lattices.iter().zip([left, right]) {
+            for index in 0..dimensions.volume() {
+                let world = position + dimensions.index_to_local(index).as_ivec3();
+
+                assert_eq!(lattice.sample(world), shared.sample(world));
+            }
+        }
+
+        // the points of the lattice are sampled exactly.
+        let point = IVec3::new(-8, 4, 8);
+        let exact = fractal_noise(
+            noise,
+            (point.as_dvec3() * settings.frequency_scale).to_array(),
+            settings.octaves,
+            settings.persistence,
+        );
+
+        assert_eq!(shared.sample(point), exact);
+    }
+
+    #[test]
+    fn test_deterministic_across_threads() {
+        let source = NoiseSource::new(NoiseType::OpenSimplex, 1234);
+        let noise = source.noise().as_ref();
+        let (world_position, dimensions) = (IVec3::new(-32, 0, 64), (32, 32, 32));
+
+        let generate = |threads: usize, settings: &GenerationSettings, lod: u32| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(|| generate_voxels(settings, noise, world_position, dimensions, lod))
+        };
+
+        for interpolated_noise in [false, true] {
+            let settings = GenerationSettings {
+                octaves: 4,
+                interpolated_noise,
+                ..Default::default()
+            };
+
+            for lod in [0, 2] {
+                let expected = generate(1, &settings, lod);
+
+                assert_eq!(generate(4, &settings, lod), expected);
+
+                // a buffer that's being reused (see `VoxelBufferPool`) doesn't leave anything
+                // behind either.
+                let mut reused = generate(4, &settings, 1);
+                generate_voxels_into(
+                    &mut reused,
+                    &mut Vec::new(),
+                    &settings,
+                    noise,
+                    world_position,
+                    dimensions,
+                    lod,
+                );
+
+                assert_eq!(reused, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_density_matches_solidity() {
+        let source = NoiseSource::new(NoiseType::OpenSimplex, 1234);
+        let noise = source.noise().as_ref();
+        let (mut voxels, mut density) = (Vec::new(), Vec::new());
+
+        for lod in [0, 1] {
+            let settings = GenerationSettings::default();
+
+            // a tall chunk around the surface level, which has to contain some of the surface.
+            generate_voxels_into(
+                &mut voxels,
+                &mut density,
+                &settings,
+                noise,
+                IVec3::new(8, -48, -24),
+                (16, 128, 16),
+                lod,
+            );
+
+            assert_eq!(density.len(), voxels.len());
+            assert!(voxels.iter().any(|voxel| voxel.is_solid()));
+            assert!(voxels.iter().any(|voxel| !voxel.is_solid()));
+
+            // the surface lies where the density crosses zero, right between solid and air.
+            for (voxel, density) in voxels.iter().zip(&density) {
+                match voxel.is_solid() {
+                    true => assert!(*density >= f16::ZERO),
+                    false => assert!(*density <= f16::ZERO),
+                }
+            }
+        }
+    }
 }
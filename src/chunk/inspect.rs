@@ -0,0 +1,111 @@
+use bevy::{math::Ray, prelude::*};
+
+use crate::input::{
+    bindings::{InputAction, InputBindings},
+    camera::PlayerController,
+};
+
+use super::{
+    chunk::VoxelFace,
+    origin::FloatingOrigin,
+    raycast::raycast,
+    registry::{ChunkRegistry, Coordinates},
+};
+
+// how far away (in voxels) a chunk can be inspected from.
+const INSPECT_DISTANCE: f32 = 512.0;
+
+/// The world position of the chunk that was last inspected, see [`inspect_chunk()`].
+#[derive(Resource, Default, Debug)]
+pub struct InspectedChunk(pub Option<Coordinates>);
+
+/// Logs everything about the chunk under the crosshair whenever `InputAction::InspectChunk` is
+/// pressed, and keeps highlighting it until another chunk is inspected.
+///
+/// Looking at nothing (or at a chunk that's out of reach) clears the highlight again.
+pub fn inspect_chunk(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    registry: Res<ChunkRegistry>,
+    origin: Res<FloatingOrigin>,
+    camera: Query<&Transform, With<PlayerController>>,
+    mut inspected: ResMut<InspectedChunk>,
+) {
+    if !bindings.just_pressed(InputAction::InspectChunk, &keys) {
+        return;
+    }
+
+    let Ok(transform) = camera.get_single() else {
+        return;
+    };
+
+    let ray = Ray {
+        origin: transform.translation,
+        direction: transform.forward(),
+    };
+
+    let Some(hit) = raycast(&registry, &origin, ray, INSPECT_DISTANCE) else {
+        info!("there's no chunk under the crosshair");
+        inspected.0 = None;
+        return;
+    };
+
+    let Some(chunk) = registry.get_chunk_at(hit.chunk()) else {
+        inspected.0 = None;
+        return;
+    };
+
+    let dimensions = chunk.get_dimensions();
+
+    info!(
+        "chunk at {} (hit voxel {} at {:.1} voxels):\n  \
+         flags: {:?}\n  \
+         voxels: {} solid, {} visible out of {}{}\n  \
+         solid faces: {:?}\n  \
+         lod: {}, generated at {}, drawn at {:?}\n  \
+         mesh: {:?}, palette: {:?}, submeshes: {}\n  \
+         entity: {:?}",
+        chunk.world_position,
+        hit.position,
+        hit.distance,
+        chunk.get_flags(),
+        chunk.solid,
+        chunk.occupancy.iter().sum::<u32>(),
+        dimensions.volume(),
+        match chunk.voxels.uniform() {
+            Some(_) => " (uniform)",
+            None => "",
+        },
+        VoxelFace::ALL
+            .iter()
+            .filter(|face| chunk.is_face_solid(face))
+            .collect::<Vec<_>>(),
+        chunk.lod,
+        chunk.get_generated_lod(),
+        chunk.get_drawn_lod(),
+        chunk.get_mesh().map(|mesh| mesh.id()),
+        chunk.get_palette().map(|palette| palette.id()),
+        chunk.submeshes.len(),
+        chunk.get_entity(),
+    );
+
+    inspected.0 = Some(chunk.world_position);
+}
+
+/// Draws a box around the inspected chunk, if there is one.
+pub fn draw_inspected_chunk(
+    inspected: Res<InspectedChunk>,
+    origin: Res<FloatingOrigin>,
+    mut gizmos: Gizmos,
+) {
+    let Some(position) = inspected.0 else {
+        return;
+    };
+
+    let size = ChunkRegistry::chunk_dimensions().as_vec3();
+
+    gizmos.cuboid(
+        Transform::from_translation(origin.to_render(position) + size / 2.0).with_scale(size),
+        Color::YELLOW,
+    );
+}
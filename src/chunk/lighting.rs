@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::{IVec3, UVec3};
+
+use super::{chunk::ChunkDimensions, voxel::Voxel};
+
+/// The highest light level a voxel can have; every voxel that's directly exposed to the sky has
+/// this light level.
+pub const MAX_LIGHT: u8 = 15;
+
+/// The brightness of a face that doesn't receive any light. without this, everything below the
+/// surface would be pitch black.
+pub const MIN_BRIGHTNESS: f32 = 0.15;
+
+/// How much brighter than a fully lit face the faces of a voxel emitting [`MAX_LIGHT`] are.
+pub const EMISSION_BOOST: f32 = 1.5;
+
+const NEIGHBORS: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+/// Computes the light level of every voxel within a chunk.
+///
+/// Skylight is seeded from the top of the chunk: every non-solid voxel above the highest solid
+/// voxel of its column receives [`MAX_LIGHT`]. Block light is seeded from every emissive voxel,
+/// which receives its own emission as its light level. From there, the light is flood-filled
+/// through all non-solid voxels, losing a single level for every voxel it travels. Solid voxels
+/// which don't emit any light always have a light level of `0`.
+///
+/// This only considers the voxels of the chunk itself, light does not propagate across chunk
+/// borders (yet), meaning the top of every chunk is treated as if it's exposed to the sky.
+///
+/// The returned vector uses the same layout as the voxels of the chunk.
+pub fn propagate_light(voxels: &[Voxel], dimensions: &ChunkDimensions) -> Vec<u8> {
+    let ChunkDimensions {
+        width,
+        height,
+        depth,
+    } = *dimensions;
+
+    let mut light = vec![0; voxels.len()];
+    let mut queue = VecDeque::new();
+
+    let index_of = |x: u32, y: u32, z: u32| dimensions.local_to_index(UVec3::new(x, y, z));
+
+    for z in 0..depth {
+        for x in 0..width {
+            for y in (0..height).rev() {
+                let index = index_of(x, y, z);
+
+                if voxels.get(index).is_none_or(|voxel| voxel.is_solid()) {
+                    break;
+                }
+
+                light[index] = MAX_LIGHT;
+                queue.push_back(IVec3::new(x as i32, y as i32, z as i32));
+            }
+        }
+    }
+
+    for z in 0..depth {
+        for y in 0..height {
+            for x in 0..width {
+                let index = index_of(x, y, z);
+
+                match voxels.get(index) {
+                    Some(voxel) if voxel.emission > light[index] => {
+                        light[index] = voxel.emission.min(MAX_LIGHT);
+                        queue.push_back(IVec3::new(x as i32, y as i32, z as i32));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let bounds = IVec3::new(width as i32, height as i32, depth as i32);
+
+    while let Some(position) = queue.pop_front() {
+        let level = light[index_of(position.x as u32, position.y as u32, position.z as u32)];
+
+        if level <= 1 {
+            continue;
+        }
+
+        for offset in NEIGHBORS {
+            let neighbor = position + offset;
+
+            if neighbor.cmplt(IVec3::ZERO).any() || neighbor.cmpge(bounds).any() {
+                continue;
+            }
+
+            let index = index_of(neighbor.x as u32, neighbor.y as u32, neighbor.z as u32);
+
+            if voxels[index].is_solid() || light[index] >= level - 1 {
+                continue;
+            }
+
+            light[index] = level - 1;
+            queue.push_back(neighbor);
+        }
+    }
+
+    light
+}
+
+/// The brightness of the faces of an emissive voxel. these are never shaded, and are boosted
+/// according to the emission of the voxel to make them appear as if they're glowing.
+#[inline]
+pub fn emissive_brightness(emission: u8) -> f32 {
+    1.0 + EMISSION_BOOST * (emission.min(MAX_LIGHT) as f32 / MAX_LIGHT as f32)
+}
+
+/// Converts a light level into a brightness multiplier, ranging from [`MIN_BRIGHTNESS`] to `1.0`.
+#[inline]
+pub fn brightness(level: u8) -> f32 {
+    let level = level.min(MAX_LIGHT) as f32 / MAX_LIGHT as f32;
+
+    MIN_BRIGHTNESS + (1.0 - MIN_BRIGHTNESS) * level
+}
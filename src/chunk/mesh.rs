@@ -1,13 +1,22 @@
+use std::{borrow::Cow, collections::BTreeSet, ops::Range, sync::Arc};
+
 use bevy::{
-    prelude::{Mesh, UVec3},
-    render::{mesh::Indices, render_resource::PrimitiveTopology},
+    prelude::{IVec3, Mesh, UVec3, Vec3, Vec4},
+    render::{
+        mesh::{Indices, MeshVertexAttribute},
+        render_resource::{PrimitiveTopology, VertexFormat},
+    },
 };
 use half::f16;
 
 use super::{
+    block::BlockRegistry,
     chunk::{ChunkDimensions, VoxelFace},
-    voxel::{Voxel, VoxelMeshData},
-    MeshSettings,
+    lighting::{self, MAX_LIGHT},
+    palette::{compress_colors, ATTRIBUTE_COLOR_INDEX},
+    surface_nets::SurfaceQuad,
+    voxel::{BlockMaterial, Voxel, VoxelMeshData},
+    MeshSettings, MeshTopology,
 };
 
 const INDICES_SET: [[u32; 6]; 6] = [
@@ -19,137 +28,1117 @@ const INDICES_SET: [[u32; 6]; 6] = [
     [4, 1, 5, 4, 0, 1],
 ];
 
+/// The ambient occlusion of every vertex, ranging from `0.0` (fully occluded) to `1.0` (not
+/// occluded at all).
+///
+/// This is kept out of the vertex colors on purpose, so it can be combined with the lighting by a
+/// shader without touching the color of the block itself. `StandardMaterial` doesn't read this
+/// attribute, it only takes effect with a material that does.
+pub const ATTRIBUTE_AMBIENT_OCCLUSION: MeshVertexAttribute = MeshVertexAttribute::new(
+    "Vertex_AmbientOcclusion",
+    1_297_040_201,
+    VertexFormat::Float32,
+);
+
+/// The most edits a chunk can be meshed incrementally for, see [`MeshLayout::remesh()`]. Every
+/// edit means meshing the 27 voxels around it again, past this point it's quicker to just mesh
+/// the entire chunk again.
+pub const MAX_INCREMENTAL_EDITS: usize = 64;
+
+// the offsets of the vertices of `Voxel::mesh()`, in the same order.
+const CORNERS: [IVec3; 8] = [
+    IVec3::new(0, 0, 0),
+    IVec3::new(1, 0, 0),
+    IVec3::new(1, 1, 0),
+    IVec3::new(0, 1, 0),
+    IVec3::new(0, 0, 1),
+    IVec3::new(1, 0, 1),
+    IVec3::new(1, 1, 1),
+    IVec3::new(0, 1, 1),
+];
+
+/// The meshes of a single chunk.
+///
+/// Every `BlockMaterial` needs a different material to be rendered with, so the voxels of every
+/// material end up in a separate mesh. Every mesh is only built for the materials that are
+/// actually present in the chunk; most chunks only have an opaque one, and a chunk of nothing but
+/// water doesn't have an opaque one at all.
+///
+/// `mesh()` doesn't return any meshes at all if none of them would contain any geometry.
+pub struct ChunkMeshes {
+    pub opaque: Option<Mesh>,
+    pub submeshes: Vec<(BlockMaterial, Mesh)>,
+    // the palettes of the meshes whose colors got compressed (see `MeshSettings::palette`),
+    // indexed by `BlockMaterial::index()`.
+    pub palettes: [Option<Vec<Vec4>>; 3],
+    // the layout the meshes were built from, for the chunks that keep it around to be meshed
+    // incrementally; see `ChunkSnapshot::mesh()`.
+    pub layout: Option<Arc<MeshLayout>>,
+}
+
+/// The vertex data of a mesh that's still being built.
+#[derive(Default, Clone, Debug)]
+struct MeshBuffers {
+    vertices: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    colors: Vec<[f32; 4]>,
+    occlusion: Vec<f32>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuffers {
+    fn push_vertex(&mut self, vertex: [f32; 3], normal: Vec3, color: [f32; 4], occlusion: f32) {
+        self.indices.push(self.vertices.len() as u32);
+        self.vertices.push(vertex);
+        self.normals.push(normal.to_array());
+        self.colors.push(color);
+        self.occlusion.push(occlusion);
+    }
+
+    // copies the given vertices of another buffer over, see `MeshLayout::remesh()`.
+    fn extend_from(&mut self, other: &MeshBuffers, vertices: Range<usize>) {
+        for index in vertices {
+            self.push_vertex(
+                other.vertices[index],
+                Vec3::from(other.normals[index]),
+                other.colors[index],
+                other.occlusion[index],
+            );
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Builds the mesh, along with its palette if the colors got compressed; see
+    /// [`compress_colors()`]. `palette` is ignored for points, as `PaletteMaterial` needs the
+    /// normals to light the faces.
+    fn build(self, topology: MeshTopology, palette: bool) -> (Mesh, Option<Vec<Vec4>>) {
+        let primitive_topology = match topology {
+            MeshTopology::Faces | MeshTopology::Smooth => PrimitiveTopology::TriangleList,
+            MeshTopology::Points => PrimitiveTopology::PointList,
+        };
+
+        let mut mesh = Mesh::new(primitive_topology);
+
+        let compressed = match palette && topology != MeshTopology::Points {
+            true => compress_colors(&self.colors),
+            false => None,
+        };
+
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.vertices);
+        mesh.insert_attribute(ATTRIBUTE_AMBIENT_OCCLUSION, self.occlusion);
+
+        let palette = match compressed {
+            Some((palette, indices)) => {
+                mesh.insert_attribute(ATTRIBUTE_COLOR_INDEX, indices);
+                Some(palette)
+            }
+            None => {
+                mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors);
+                None
+            }
+        };
+
+        mesh.set_indices(Some(Indices::U32(self.indices)));
+
+        // points don't have a surface the normals could belong to, these are drawn unlit; see
+        // `events::draw::apply_material_settings`.
+        if topology == MeshTopology::Points {
+            return (mesh, palette);
+        }
+
+        // the faces of a voxel simply face along their axis, while a smooth surface follows the
+        // gradient of the densities; see `surface_nets::surface_quads()`.
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
+
+        // the bounding box used for frustum culling isn't part of the mesh itself, it's a separate
+        // component on the entity; see `draw_chunks`.
+        (mesh, palette)
+    }
+}
+
+/// The vertex buffers of the meshes of a chunk, along with the vertices every voxel ended up with;
+/// this is what lets a chunk be meshed again after an edit without meshing every other voxel
+/// again as well, see [`MeshLayout::remesh()`].
+#[derive(Clone, Debug)]
+pub struct MeshLayout {
+    // indexed by `BlockMaterial::index()`.
+    buffers: [MeshBuffers; 3],
+    // the index of every voxel with any vertices, along with the range of its vertices within the
+    // buffers of its material. indexed the same way as the buffers.
+    voxels: [Vec<(usize, Range<usize>)>; 3],
+    // everything that changes which vertices a voxel ends up with. downsampled voxels cover more
+    // than a single voxel, so only the layouts at the full level of detail can be reused.
+    lod: u32,
+    occlusion_culling: bool,
+    topology: MeshTopology,
+}
+
+impl MeshLayout {
+    fn new(lod: u32, settings: &MeshSettings) -> Self {
+        Self {
+            buffers: Default::default(),
+            voxels: Default::default(),
+            lod,
+            occlusion_culling: settings.occlusion_culling,
+            topology: settings.topology,
+        }
+    }
+
+    /// Whether the layout can be meshed again incrementally at the given level of detail and
+    /// settings; anything else has to be meshed entirely.
+    pub fn is_reusable(&self, lod: u32, settings: &MeshSettings) -> bool {
+        self.lod == 0
+            && lod == 0
+            && self.occlusion_culling == settings.occlusion_culling
+            && self.topology == settings.topology
+    }
+
+    /// Meshes the voxels around the given edits again, reusing the vertices of every other voxel
+    /// as they are. The edits are local positions, these can be just outside of the chunk for the
+    /// voxels on the edge of a neighbor.
+    ///
+    /// The faces of a voxel only depend on the voxels right next to it (which can hide its faces
+    /// and occlude its corners), so every voxel within one voxel of an edit has to be meshed
+    /// again. The light isn't propagated again after an edit, so nothing further away changes.
+    pub fn remesh(
+        &self,
+        voxels: &[Voxel],
+        light: &[u8],
+        edits: &[IVec3],
+        settings: &MeshSettings,
+        blocks: &BlockRegistry,
+        dimensions: &ChunkDimensions,
+    ) -> MeshLayout {
+        let ChunkDimensions {
+            width,
+            height,
+            depth,
+        } = *dimensions;
+        let size = UVec3::new(width, height, depth).as_ivec3();
+
+        // sorted, so the vertices end up in the same order no matter the order of the edits.
+        let affected = edits
+            .iter()
+            .flat_map(|edit| {
+                (-1..=1).flat_map(move |z| {
+                    (-1..=1).flat_map(move |y| (-1..=1).map(move |x| *edit + IVec3::new(x, y, z)))
+                })
+            })
+            .filter(|position| position.cmpge(IVec3::ZERO).all() && position.cmplt(size).all())
+            .map(|position| dimensions.local_to_index(position.as_uvec3()))
+            .collect::<BTreeSet<_>>();
+
+        let mut layout = MeshLayout::new(0, settings);
+
+        for (material, voxels) in self.voxels.iter().enumerate() {
+            for (index, vertices) in voxels {
+                if affected.contains(index) {
+                    continue;
+                }
+
+                let buffers = &mut layout.buffers[material];
+                let start = buffers.vertices.len();
+
+                buffers.extend_from(&self.buffers[material], vertices.clone());
+                layout.voxels[material].push((*index, start..buffers.vertices.len()));
+            }
+        }
+
+        let mesher = Mesher {
+            voxels,
+            light,
+            lod: 0,
+            dimensions: *dimensions,
+            base_dimensions: *dimensions,
+            settings,
+            blocks,
+        };
+
+        for index in affected {
+            mesher.mesh_voxel(dimensions.index_to_local(index), &mut layout);
+        }
+
+        layout
+    }
+
+    /// Builds the meshes out of the vertex buffers, keeping the layout around; see
+    /// [`into_meshes()`](Self::into_meshes).
+    pub fn build(&self, settings: &MeshSettings) -> Option<ChunkMeshes> {
+        build_meshes(self.buffers.clone(), settings)
+    }
+
+    /// Builds the meshes out of the vertex buffers. No meshes are returned at all if none of them
+    /// would contain any geometry.
+    pub fn into_meshes(self, settings: &MeshSettings) -> Option<ChunkMeshes> {
+        build_meshes(self.buffers, settings)
+    }
+
+    /// Adds a smooth surface to the vertex buffers, see `surface_nets::surface_quads()`. The
+    /// quads are colored and lit like the faces of the voxels they're the surface of; only the
+    /// light within the chunk itself is known, anything facing a neighbor is fully lit.
+    ///
+    /// The vertices of a surface aren't kept track of per voxel, so this layout can't be meshed
+    /// incrementally anymore.
+    pub fn push_surface(
+        &mut self,
+        quads: &[SurfaceQuad],
+        light: &[u8],
+        blocks: &BlockRegistry,
+        dimensions: &ChunkDimensions,
+    ) {
+        let size = UVec3::new(dimensions.width, dimensions.height, dimensions.depth).as_ivec3();
+
+        for quad in quads {
+            let SurfaceQuad {
+                vertices,
+                normals,
+                voxel,
+                direction,
+                facing,
+            } = quad;
+
+            let inside = facing.cmpge(IVec3::ZERO).all() && facing.cmplt(size).all();
+            let level = match inside {
+                true => light
+                    .get(dimensions.local_to_index(facing.as_uvec3()))
+                    .copied()
+                    .unwrap_or(MAX_LIGHT),
+                false => MAX_LIGHT,
+            };
+
+            let brightness = match voxel.is_emissive() {
+                true => lighting::emissive_brightness(voxel.emission),
+                false => lighting::brightness(level),
+            };
+
+            let face = VoxelFace::ALL
+                .into_iter()
+                .find(|face| face.offset() == *direction)
+                .unwrap_or(VoxelFace::Up);
+
+            let [r, g, b, a] = blocks.color(voxel, &face).as_rgba_f32();
+            let color = [r * brightness, g * brightness, b * brightness, a];
+
+            let buffers = &mut self.buffers[voxel.block.material().index()];
+
+            for corner in [0, 1, 2, 0, 2, 3] {
+                buffers.push_vertex(vertices[corner].to_array(), normals[corner], color, 1.0);
+            }
+        }
+    }
+}
+
+// everything needed to mesh a single voxel, see `Mesher::mesh_voxel()`.
+struct Mesher<'a> {
+    // these are the downsampled voxels, including the dimensions.
+    voxels: &'a [Voxel],
+    light: &'a [u8],
+    lod: u32,
+    dimensions: ChunkDimensions,
+    base_dimensions: ChunkDimensions,
+    settings: &'a MeshSettings,
+    blocks: &'a BlockRegistry,
+}
+
+impl Mesher<'_> {
+    // adds the vertices of a single voxel to the buffers of its material, and keeps track of which
+    // vertices these are within the layout.
+    fn mesh_voxel(&self, position: UVec3, layout: &mut MeshLayout) {
+        let index = self.dimensions.local_to_index(position);
+
+        let Some(voxel) = self.voxels.get(index) else {
+            return;
+        };
+
+        // the solid voxels are covered by a smooth surface instead, see `push_surface()`.
+        if !voxel.is_visible()
+            || (self.settings.topology == MeshTopology::Smooth && voxel.is_solid())
+        {
+            return;
+        }
+
+        let material = voxel.block.material().index();
+        let buffers = &mut layout.buffers[material];
+        let start = buffers.vertices.len();
+
+        self.push_vertices(voxel, position, buffers);
+
+        if buffers.vertices.len() > start {
+            layout.voxels[material].push((index, start..buffers.vertices.len()));
+        }
+    }
+
+    fn push_vertices(&self, voxel: &Voxel, UVec3 { x, y, z }: UVec3, buffers: &mut MeshBuffers) {
+        let Mesher {
+            voxels,
+            light,
+            lod,
+            dimensions,
+            base_dimensions,
+            settings,
+            blocks,
+        } = self;
+
+        // every voxel in the downsampled chunk covers `scale` voxels on every axis.
+        let scale = (1 << lod) as f32;
+        let voxel_size = f16::from_f32(voxel.size.to_f32() * scale);
+
+        if settings.topology == MeshTopology::Points {
+            // the voxel sits in the minimum corner of its cell, see `Voxel::size`.
+            let center =
+                (UVec3::new(x, y, z).as_vec3() * scale + voxel_size.to_f32() / 2.0).to_array();
+            let color = blocks.color(voxel, &VoxelFace::Up).as_rgba_f32();
+
+            buffers.push_vertex(center, Vec3::ZERO, color, 1.0);
+
+            return;
+        }
+
+        // not entirely sure why, but `VoxelFace::Back` and `VoxelFace::Top` have to be the other
+        // way around in comparison to the way we declared the indices, otherwise the wrong sides
+        // will be culled. `test_face_winding` makes sure they stay the right way around.
+        let voxel_faces = [
+            VoxelFace::Back,
+            VoxelFace::Right,
+            VoxelFace::Front,
+            VoxelFace::Left,
+            VoxelFace::Up,
+            VoxelFace::Down,
+        ];
+
+        // the voxels are spaced by the size of their cell, not by their own size; smaller voxels
+        // sit in the minimum corner of their cell (see `Voxel::size`).
+        let x_pos = f16::from_f32(x as f32 * scale);
+        let y_pos = f16::from_f32(y as f32 * scale);
+        let z_pos = f16::from_f32(z as f32 * scale);
+
+        let VoxelMeshData { vertices, .. } = voxel.mesh([x_pos, y_pos, z_pos], voxel_size);
+
+        for (face_index, face) in voxel_faces.iter().enumerate() {
+            if settings.occlusion_culling
+                && is_face_occluded(voxel, voxels, [x, y, z], face, dimensions)
+            {
+                continue;
+            }
+
+            // faces are shaded by the light level of the (non-solid) voxel they're facing. the
+            // light is stored at the base resolution, so we have to scale the neighbor back up
+            // when meshing at a lower level of detail.
+            let level = get_neighbor([x, y, z], face, dimensions)
+                .map(|neighbor| {
+                    let UVec3 { x, y, z } = neighbor << *lod;
+                    let ChunkDimensions { width, height, .. } = base_dimensions;
+
+                    (x + y * width + z * width * height) as usize
+                })
+                .and_then(|index| light.get(index).copied())
+                .unwrap_or(MAX_LIGHT);
+
+            let brightness = match voxel.is_emissive() {
+                true => lighting::emissive_brightness(voxel.emission),
+                false => lighting::brightness(level),
+            };
+
+            // the color of its block type takes the place of the color of the voxel, which can
+            // differ per face; see `BlockRegistry::color()`.
+            let [r, g, b, a] = blocks.color(voxel, face).as_rgba_f32();
+
+            // every face gets its own vertices, as the colors of a face depend on its light
+            // level; we can't share the corners with the adjacent faces.
+            for corner in INDICES_SET[face_index] {
+                buffers.push_vertex(
+                    vertices[corner as usize],
+                    face.offset().as_vec3(),
+                    [r * brightness, g * brightness, b * brightness, a],
+                    vertex_occlusion(voxels, [x, y, z], face, corner, dimensions),
+                );
+            }
+        }
+    }
+}
+
 pub fn mesh(
     voxels: &Vec<Voxel>,
+    light: &[u8],
+    occupancy: &[u32],
     lod: u32,
     settings: MeshSettings,
-    ChunkDimensions {
-        width: base_width,
-        height: base_height,
-        depth: base_depth,
-    }: &ChunkDimensions,
-) -> Mesh {
-    let lod_multiplier = lod.pow(2);
+    blocks: &BlockRegistry,
+    dimensions: &ChunkDimensions,
+) -> Option<ChunkMeshes> {
+    mesh_layout(voxels, light, occupancy, lod, &settings, blocks, dimensions).into_meshes(&settings)
+}
+
+/// Builds the vertex buffers of every voxel of a chunk, which [`MeshLayout::build()`] turns into
+/// the actual meshes; [`mesh()`] does both at once.
+pub fn mesh_layout(
+    voxels: &[Voxel],
+    light: &[u8],
+    occupancy: &[u32],
+    lod: u32,
+    settings: &MeshSettings,
+    blocks: &BlockRegistry,
+    dimensions: &ChunkDimensions,
+) -> MeshLayout {
+    let base_dimensions = *dimensions;
 
-    let width = base_width >> lod;
-    let height = base_height >> lod;
-    let depth = base_depth >> lod;
+    // everything below operates on the downsampled voxels, including the occlusion culling
+    // lookups. this ensures we're culling against the neighbors at the same resolution as the
+    // mesh we're building, rather than against the base-resolution voxels.
+    let (voxels, lod, dimensions) = downsample(voxels, lod, dimensions);
+    let ChunkDimensions {
+        width,
+        height,
+        depth,
+    } = dimensions;
 
-    let mut all_vertices = Vec::new();
-    let mut all_colors = Vec::new();
-    let mut all_indices = Vec::new();
+    let mesher = Mesher {
+        voxels: &voxels,
+        light,
+        lod,
+        dimensions,
+        base_dimensions,
+        settings,
+        blocks,
+    };
+
+    let mut layout = MeshLayout::new(lod, settings);
+
+    // the layers without any visible voxels don't produce any faces, so we don't even have to look
+    // at them. a layer of the downsampled chunk covers `scale` layers of the base chunk, it's only
+    // empty if all of them are. chunks above the terrain are entirely empty, so these are skipped
+    // altogether.
+    let empty_layers = (0..height)
+        .map(|y| {
+            let base = (y << lod) as usize;
+
+            occupancy.len() == base_dimensions.height as usize
+                && occupancy[base..base + (1 << lod)]
+                    .iter()
+                    .all(|count| *count == 0)
+        })
+        .collect::<Vec<_>>();
 
     for z in 0..depth {
         for y in 0..height {
+            if empty_layers[y as usize] {
+                continue;
+            }
+
             for x in 0..width {
-                let index = (z * base_width * base_height) + (y * base_width) + x;
-
-                if let Some(voxel) = voxels.get(index as usize) {
-                    if !voxel.is_solid() {
-                        continue;
-                    }
-
-                    let voxel_size =
-                        f16::from_f32(voxel.size.to_f32() * (lod_multiplier as f32 + 1.0));
-
-                    // not entirely sure why, but `VoxelFace::Back` and `VoxelFace::Top` have to
-                    // be the other way around in comparison to the way we declared the indices,
-                    // otherwise the wrong sides will be culled.
-                    let voxel_faces = [
-                        VoxelFace::Back,
-                        VoxelFace::Right,
-                        VoxelFace::Front,
-                        VoxelFace::Left,
-                        VoxelFace::Up,
-                        VoxelFace::Down,
-                    ];
-
-                    // Adjust indices for each voxel
-                    let base_vertex_index = all_vertices.len() as u32;
-
-                    // add the voxel size to the dimensions, although voxel size is currently
-                    // not actually used and should always be set to 1.0 (refer to the Voxel
-                    // struct for more information), we are still applying this here in case we
-                    // decide to use the voxel size in the future.
-                    let x_pos = f16::from_f32(x as f32) * voxel_size;
-                    let y_pos = f16::from_f32(y as f32) * voxel_size;
-                    let z_pos = f16::from_f32(z as f32) * voxel_size;
-
-                    let indices = voxel_faces
-                        .into_iter()
-                        .enumerate()
-                        .filter(|(_, face)| {
-                            !settings.occlusion_culling
-                                || get_voxel_face(
-                                    &voxels,
-                                    [x, y, z],
-                                    &face,
-                                    (base_width, base_height, base_depth),
-                                )
-                                .is_none()
-                        })
-                        .map(|(index, _)| {
-                            INDICES_SET[index]
-                                .iter()
-                                .map(|index| index + base_vertex_index)
-                                .collect::<Vec<u32>>()
-                        })
-                        .flatten();
-
-                    let VoxelMeshData { vertices, colors } =
-                        voxel.mesh([x_pos, y_pos, z_pos], voxel_size);
-
-                    all_indices.extend(indices);
-                    all_vertices.extend(vertices);
-                    all_colors.extend(colors);
-                }
+                mesher.mesh_voxel(UVec3::new(x, y, z), &mut layout);
             }
         }
     }
 
-    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    layout
+}
+
+// turns the vertex buffers of every material into their meshes, see `MeshLayout::build()`.
+fn build_meshes(buffers: [MeshBuffers; 3], settings: &MeshSettings) -> Option<ChunkMeshes> {
+    // all-air chunks (or chunks that are entirely hidden by their own voxels) don't produce any
+    // geometry, there's no point in uploading an empty mesh for these.
+    if buffers.iter().all(MeshBuffers::is_empty) {
+        return None;
+    }
+
+    let mut palettes: [Option<Vec<Vec4>>; 3] = Default::default();
+    let [opaque, submeshes @ ..] = buffers;
+
+    let opaque = (!opaque.is_empty()).then(|| {
+        let (mesh, palette) = opaque.build(settings.topology, settings.palette);
+        palettes[BlockMaterial::Opaque.index()] = palette;
+
+        mesh
+    });
+
+    let submeshes = BlockMaterial::ALL[1..]
+        .iter()
+        .copied()
+        .zip(submeshes)
+        .filter(|(_, buffers)| !buffers.is_empty())
+        .map(|(material, buffers)| {
+            let (mesh, palette) = buffers.build(settings.topology, settings.palette);
+            palettes[material.index()] = palette;
+
+            (material, mesh)
+        })
+        .collect();
+
+    Some(ChunkMeshes {
+        opaque,
+        submeshes,
+        palettes,
+        layout: None,
+    })
+}
+
+/// Whether the given face of a voxel is hidden by its neighbor.
+///
+/// Solid neighbors hide the faces of every voxel. Transparent voxels are also hidden by
+/// neighbors of the same block type; this way only the surface of a body of water gets rendered,
+/// rather than every face between two water voxels.
+///
+/// Neighbors smaller than a full voxel never hide a face, as they don't cover it entirely. A face
+/// of a smaller voxel can only be hidden if it's on the boundary of its cell, which is only the
+/// case for the faces on its minimum corner (see `Voxel::size`).
+fn is_face_occluded(
+    voxel: &Voxel,
+    voxels: &[Voxel],
+    coordinates: impl Into<UVec3>,
+    face: &VoxelFace,
+    dimensions: &ChunkDimensions,
+) -> bool {
+    let Some(UVec3 { x, y, z }) = get_neighbor(coordinates, face, dimensions) else {
+        return false;
+    };
+
+    let ChunkDimensions { width, height, .. } = dimensions;
+    let Some(neighbor) = voxels.get((x + y * width + z * width * height) as usize) else {
+        return false;
+    };
+
+    // the faces on the minimum corner are the ones pointing towards the negative axes.
+    let on_boundary = voxel.is_full_size() || face.offset().min_element() < 0;
+
+    if !on_boundary || !neighbor.is_full_size() {
+        return false;
+    }
+
+    neighbor.is_solid() || (voxel.is_transparent() && neighbor.block == voxel.block)
+}
+
+/// The ambient occlusion of a single corner of a face, see `ATTRIBUTE_AMBIENT_OCCLUSION`.
+///
+/// The corner is an index into the vertices of `Voxel::mesh()`. The occlusion is based on the
+/// three voxels touching the corner in the layer in front of the face: both sides and the corner
+/// in between them. If both sides are solid, the corner is fully occluded regardless of the
+/// voxel in between. Voxels outside of the chunk never occlude anything.
+fn vertex_occlusion(
+    voxels: &[Voxel],
+    coordinates: impl Into<UVec3>,
+    face: &VoxelFace,
+    corner: u32,
+    dimensions: &ChunkDimensions,
+) -> f32 {
+    let normal = face.offset();
+
+    // the direction from the center of the face towards the corner, without the component along
+    // the normal; that one is covered by looking at the layer in front of the face.
+    let direction = (CORNERS[corner as usize] * 2 - IVec3::ONE) * (IVec3::ONE - normal.abs());
+    let front = coordinates.into().as_ivec3() + normal;
+
+    let (first, second) = match normal.abs() {
+        IVec3::X => (direction * IVec3::Y, direction * IVec3::Z),
+        IVec3::Y => (direction * IVec3::X, direction * IVec3::Z),
+        _ => (direction * IVec3::X, direction * IVec3::Y),
+    };
+
+    let is_solid = |offset: IVec3| is_solid_at(voxels, front + offset, dimensions) as u32;
+
+    let occluded = match is_solid(first) + is_solid(second) {
+        2 => 3,
+        sides => sides + is_solid(first + second),
+    };
+
+    1.0 - occluded as f32 / 3.0
+}
+
+fn is_solid_at(voxels: &[Voxel], position: IVec3, dimensions: &ChunkDimensions) -> bool {
+    let ChunkDimensions {
+        width,
+        height,
+        depth,
+    } = *dimensions;
+
+    let inside = position.cmpge(IVec3::ZERO).all()
+        && position
+            .cmplt(UVec3::new(width, height, depth).as_ivec3())
+            .all();
+
+    inside
+        && voxels
+            .get(dimensions.local_to_index(position.as_uvec3()))
+            .is_some_and(|voxel| voxel.is_solid())
+}
+
+/// Downsamples the voxels of a chunk to the resolution of the given level of detail.
+///
+/// Every voxel in the result represents a cube of `2^lod` voxels on each axis of the source data.
+/// The first solid voxel found within that cube is used (or the first visible one, if there are no
+/// solid voxels), so thin features don't disappear completely at lower resolutions. The level of detail is clamped so the chunk never shrinks
+/// below a single voxel on any axis, the clamped value is returned alongside the voxels and
+/// dimensions of the downsampled chunk.
+///
+/// At a level of detail of `0`, the voxels are borrowed as-is.
+pub fn downsample<'a>(
+    voxels: &'a [Voxel],
+    lod: u32,
+    dimensions: &ChunkDimensions,
+) -> (Cow<'a, [Voxel]>, u32, ChunkDimensions) {
+    let ChunkDimensions {
+        width,
+        height,
+        depth,
+    } = *dimensions;
+
+    let lod = lod.min(max_lod(dimensions));
+
+    if lod == 0 {
+        return (Cow::Borrowed(voxels), lod, *dimensions);
+    }
+
+    let step = 1 << lod;
+    let lod_dimensions = ChunkDimensions {
+        width: width >> lod,
+        height: height >> lod,
+        depth: depth >> lod,
+    };
+
+    let mut result = Vec::with_capacity(
+        (lod_dimensions.width * lod_dimensions.height * lod_dimensions.depth) as usize,
+    );
 
-    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, all_vertices);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, all_colors);
+    for z in 0..lod_dimensions.depth {
+        for y in 0..lod_dimensions.height {
+            for x in 0..lod_dimensions.width {
+                let cell = (0..step * step * step)
+                    .map(|offset| {
+                        let base_x = x * step + offset % step;
+                        let base_y = y * step + (offset / step) % step;
+                        let base_z = z * step + offset / (step * step);
 
-    mesh.set_indices(Some(Indices::U32(all_indices)));
+                        dimensions.local_to_index(UVec3::new(base_x, base_y, base_z))
+                    })
+                    .filter_map(|index| voxels.get(index).copied())
+                    .collect::<Vec<_>>();
+
+                // prefer solid voxels, then any other visible voxels (such as water), and only
+                // leave the cell empty if there's nothing to render at all.
+                let voxel = cell
+                    .iter()
+                    .find(|voxel| voxel.is_solid())
+                    .or_else(|| cell.iter().find(|voxel| voxel.is_visible()))
+                    .or(cell.first())
+                    .copied()
+                    .unwrap_or_default();
+
+                result.push(voxel);
+            }
+        }
+    }
+
+    (Cow::Owned(result), lod, lod_dimensions)
+}
+
+/// The highest level of detail a chunk with the given dimensions can be downsampled to, without
+/// shrinking below a single voxel on any axis.
+pub fn max_lod(
+    ChunkDimensions {
+        width,
+        height,
+        depth,
+    }: &ChunkDimensions,
+) -> u32 {
+    width
+        .trailing_zeros()
+        .min(height.trailing_zeros())
+        .min(depth.trailing_zeros())
+}
 
-    // we have to generate the normals for shading; in this case, we'll be using flat normals.
-    // should don't see much point in creating our own normal set as they are quite
-    // literally.... cubes.
-    mesh.duplicate_vertices();
-    mesh.compute_flat_normals();
+/// Gets the coordinates of the voxel adjacent to the given voxel on the given face.
+///
+/// Returns `None` if the neighbor would fall outside of the chunk.
+pub fn get_neighbor(
+    coordinates: impl Into<UVec3>,
+    face: &VoxelFace,
+    ChunkDimensions {
+        width,
+        height,
+        depth,
+    }: &ChunkDimensions,
+) -> Option<UVec3> {
+    let neighbor = coordinates.into().as_ivec3() + face.offset();
+    let size = UVec3::new(*width, *height, *depth).as_ivec3();
 
-    // do we need aabb (axis aligned bounding boxes)? i feel like it would help with GPU frustum
-    // culling, and perhaps other GPU culling.
-    mesh.compute_aabb();
+    if neighbor.cmplt(IVec3::ZERO).any() || neighbor.cmpge(size).any() {
+        return None;
+    }
 
-    mesh
+    Some(neighbor.as_uvec3())
 }
 
+/// Gets the solid voxel adjacent to the given voxel on the given face, if there is any.
+///
+/// The coordinates and dimensions have to be of the same resolution as `voxels`; when meshing at
+/// a lower level of detail, these are the coordinates within the downsampled chunk. Neighbors
+/// that would fall outside of the chunk are never returned, so faces on the edges of a chunk are
+/// never culled.
 pub fn get_voxel_face<'a>(
-    voxels: &'a Vec<Voxel>,
+    voxels: &'a [Voxel],
     coordinates: impl Into<UVec3>,
-    face: &'a VoxelFace,
-    (width, height, _): (&'a u32, &'a u32, &'a u32),
+    face: &VoxelFace,
+    dimensions: &ChunkDimensions,
 ) -> Option<&'a Voxel> {
-    let coordinates = coordinates.into();
-    let UVec3 { x, y, z } = coordinates.try_into().unwrap(); // Use UVec3 instead of IVec3
-
-    let (nx, ny, nz) = match face {
-        VoxelFace::Front => (x, y, z + 1),
-        VoxelFace::Back => (x, y, z - 1),
-        VoxelFace::Left => (x - 1, y, z),
-        VoxelFace::Right => (x + 1, y, z),
-        VoxelFace::Up => (x, y + 1, z),
-        VoxelFace::Down => (x, y - 1, z),
+    let UVec3 { x, y, z } = get_neighbor(coordinates, face, dimensions)?;
+    let ChunkDimensions { width, height, .. } = dimensions;
+
+    voxels
+        .get((x + y * width + z * width * height) as usize)
+        .filter(|voxel| voxel.is_solid())
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::{
+        prelude::{Color, IVec3, Mesh, Vec3},
+        render::{mesh::VertexAttributeValues, render_resource::PrimitiveTopology},
     };
+    use half::f16;
+
+    use super::{mesh, mesh_layout, vertex_occlusion, MeshLayout};
+    use crate::chunk::{
+        block::BlockRegistry,
+        chunk::{ChunkDimensions, VoxelFace},
+        lighting,
+        voxel::{BlockMaterial, BlockType, Voxel},
+        MeshSettings, MeshTopology,
+    };
+
+    #[test]
+    fn test_half_size_voxel() {
+        let dimensions = ChunkDimensions {
+            width: 2,
+            height: 1,
+            depth: 1,
+        };
+
+        let voxels = vec![
+            Voxel::new_solid(Color::GRAY, f16::ONE),
+            Voxel::new_solid(Color::GRAY, f16::from_f32(0.5)),
+        ];
+
+        let settings = MeshSettings {
+            occlusion_culling: true,
+            colliders: false,
+            wireframe: false,
+            double_sided: false,
+            lod_fade: 0.0,
+            pop_in: 0.0,
+            palette: false,
+            topology: MeshTopology::Faces,
+        };
+
+        let opaque = mesh(
+            &voxels,
+            &[],
+            &[],
+            0,
+            settings,
+            &BlockRegistry::default(),
+            &dimensions,
+        )
+        .and_then(|meshes| meshes.opaque)
+        .unwrap();
+
+        let Some(VertexAttributeValues::Float32x3(vertices)) =
+            opaque.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("the mesh doesn't have any positions");
+        };
+
+        // the full voxel can't be culled against its smaller neighbor, while the left face of the
+        // half-size voxel is entirely covered by the full voxel. that leaves 11 faces, of 6
+        // vertices each.
+        assert_eq!(vertices.len(), 11 * 6);
 
-    if nx < *width && ny < *height {
-        return voxels
-            .get((nx + ny * (width) + nz * (width) * (height)) as usize)
-            .filter(|voxel| voxel.is_solid());
+        // the half-size voxel sits in the minimum corner of its own cell.
+        let max = vertices.iter().fold([0.0f32; 3], |max, [x, y, z]| {
+            [max[0].max(*x), max[1].max(*y), max[2].max(*z)]
+        });
+
+        assert_eq!(max, [1.5, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_vertex_occlusion() {
+        let dimensions = ChunkDimensions {
+            width: 2,
+            height: 2,
+            depth: 2,
+        };
+
+        let solid = Voxel::new_solid(Color::GRAY, f16::ONE);
+        let mut voxels = vec![Voxel::default(); 8];
+
+        // a voxel on the ground, with a voxel diagonally above it on both the x and z axis.
+        for position in [[0, 0, 0], [1, 1, 0], [0, 1, 1]] {
+            voxels[dimensions.local_to_index(position.into())] = solid;
+        }
+
+        let occlusion =
+            |corner| vertex_occlusion(&voxels, [0, 0, 0], &VoxelFace::Up, corner, &dimensions);
+
+        // the top corner at (0, 1, 0) only touches voxels outside of the chunk.
+        assert_eq!(occlusion(3), 1.0);
+        // the one at (1, 1, 0) touches a single voxel on its side.
+        assert_eq!(occlusion(2), 1.0 - 1.0 / 3.0);
+        // the one at (1, 1, 1) is enclosed by both sides.
+        assert_eq!(occlusion(6), 0.0);
+    }
+
+    #[test]
+    fn test_face_winding() {
+        let dimensions = ChunkDimensions {
+            width: 1,
+            height: 1,
+            depth: 1,
+        };
+
+        let voxels = vec![Voxel::new_solid(Color::GRAY, f16::ONE)];
+
+        let opaque = mesh(
+            &voxels,
+            &[],
+            &[],
+            0,
+            MeshSettings::default(),
+            &BlockRegistry::default(),
+            &dimensions,
+        )
+        .and_then(|meshes| meshes.opaque)
+        .unwrap();
+
+        let Some(VertexAttributeValues::Float32x3(vertices)) =
+            opaque.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("the mesh doesn't have any positions");
+        };
+
+        let Some(VertexAttributeValues::Float32x3(vertex_normals)) =
+            opaque.attribute(Mesh::ATTRIBUTE_NORMAL)
+        else {
+            panic!("the mesh doesn't have any normals");
+        };
+
+        // two triangles for every face of the voxel.
+        assert_eq!(vertices.len(), 6 * 2 * 3);
+
+        let center = Vec3::splat(0.5);
+        let mut normals = Vec::new();
+
+        // the vertices aren't shared (see `MeshBuffers::build()`), so every three vertices make up
+        // a triangle. front faces are wound counter-clockwise, which makes the normal point out of
+        // the voxel; otherwise the face would be culled when looking at it from the outside.
+        for (triangle, vertex_normals) in vertices.chunks_exact(3).zip(vertex_normals.chunks(3)) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(Vec3::from);
+            let normal = (b - a).cross(c - a).normalize();
+            let outward = ((a + b + c) / 3.0 - center).normalize();
+
+            assert!(
+                normal.dot(outward) > 0.0,
+                "the triangle {triangle:?} is facing into the voxel"
+            );
+
+            // the normals of the vertices have to agree with the way the face is wound.
+            for vertex_normal in vertex_normals {
+                assert_eq!(Vec3::from(*vertex_normal), normal.round());
+            }
+
+            normals.push(normal.round().as_ivec3());
+        }
+
+        // every side of the voxel is covered, rather than some of them twice.
+        for normal in [
+            IVec3::X,
+            IVec3::NEG_X,
+            IVec3::Y,
+            IVec3::NEG_Y,
+            IVec3::Z,
+            IVec3::NEG_Z,
+        ] {
+            assert_eq!(normals.iter().filter(|n| **n == normal).count(), 2);
+        }
+    }
+
+    #[test]
+    fn test_points() {
+        let dimensions = ChunkDimensions {
+            width: 3,
+            height: 1,
+            depth: 1,
+        };
+
+        // the voxel in the middle has both of its sides covered, which doesn't matter for points.
+        let voxels = vec![
+            Voxel::new_solid(Color::GRAY, f16::ONE),
+            Voxel::new_solid(Color::GRAY, f16::ONE),
+            Voxel::new_solid(Color::GRAY, f16::from_f32(0.5)),
+        ];
+
+        let settings = MeshSettings {
+            topology: MeshTopology::Points,
+            ..Default::default()
+        };
+
+        let opaque = mesh(
+            &voxels,
+            &[],
+            &[],
+            0,
+            settings,
+            &BlockRegistry::default(),
+            &dimensions,
+        )
+        .and_then(|meshes| meshes.opaque)
+        .unwrap();
+
+        let Some(VertexAttributeValues::Float32x3(points)) =
+            opaque.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("the mesh doesn't have any positions");
+        };
+
+        assert_eq!(opaque.primitive_topology(), PrimitiveTopology::PointList);
+        assert_eq!(
+            *points,
+            vec![[0.5, 0.5, 0.5], [1.5, 0.5, 0.5], [2.25, 0.25, 0.25]]
+        );
+    }
+
+    #[test]
+    fn test_remesh() {
+        let dimensions = ChunkDimensions {
+            width: 4,
+            height: 4,
+            depth: 4,
+        };
+
+        let solid = Voxel::new_solid(Color::GRAY, f16::ONE);
+        let mut voxels = vec![Voxel::default(); 64];
+
+        for position in [[0, 0, 0], [1, 0, 0], [2, 0, 0], [1, 1, 0], [3, 3, 3]] {
+            voxels[dimensions.local_to_index(position.into())] = solid;
+        }
+
+        let settings = MeshSettings::default();
+        let blocks = BlockRegistry::default();
+        let layout =
+            |voxels: &[Voxel]| mesh_layout(voxels, &[], &[], 0, &settings, &blocks, &dimensions);
+
+        // every vertex, in an order that doesn't depend on the order the voxels were meshed in.
+        let vertices = |layout: &MeshLayout| {
+            let buffers = &layout.buffers[0];
+            let mut vertices = (0..buffers.vertices.len())
+                .map(|index| {
+                    let [x, y, z] = buffers.vertices[index];
+                    let [r, g, b, a] = buffers.colors[index];
+
+                    [x, y, z, r, g, b, a, buffers.occlusion[index]].map(f32::to_bits)
+                })
+                .collect::<Vec<_>>();
+
+            vertices.sort();
+            vertices
+        };
+
+        let before = layout(&voxels);
+
+        // removing the voxel in the middle exposes (and unoccludes) the faces of its neighbors,
+        // while placing one in the corner of the chunk only touches the voxels around it.
+        let edits = [IVec3::new(1, 0, 0), IVec3::new(3, 0, 3)];
+
+        voxels[dimensions.local_to_index([1, 0, 0].into())] = Voxel::default();
+        voxels[dimensions.local_to_index([3, 0, 3].into())] = solid;
+
+        let remeshed = before.remesh(&voxels, &[], &edits, &settings, &blocks, &dimensions);
+
+        assert_ne!(vertices(&remeshed), vertices(&before));
+        assert_eq!(vertices(&remeshed), vertices(&layout(&voxels)));
+        assert_eq!(remeshed.voxels[0].len(), 5);
     }
 
-    None
+    #[test]
+    fn test_water_only() {
+        let dimensions = ChunkDimensions {
+            width: 1,
+            height: 1,
+            depth: 1,
+        };
+
+        let voxels = vec![Voxel::new_block(BlockType::Water, Color::BLUE, f16::ONE)];
+
+        let meshes = mesh(
+            &voxels,
+            &[],
+            &[],
+            0,
+            MeshSettings::default(),
+            &BlockRegistry::default(),
+            &dimensions,
+        )
+        .unwrap();
+
+        // there's nothing opaque to upload, the water is drawn by a submesh of its own.
+        assert!(meshes.opaque.is_none());
+        assert_eq!(meshes.submeshes.len(), 1);
+        assert_eq!(meshes.submeshes[0].0, BlockMaterial::Transparent);
+    }
+
+    #[test]
+    fn test_emissive_colors() {
+        let dimensions = ChunkDimensions {
+            width: 1,
+            height: 1,
+            depth: 1,
+        };
+
+        let voxels = vec![Voxel::new_block(
+            BlockType::Glowstone,
+            Color::rgb(0.4, 0.2, 0.1),
+            f16::ONE,
+        )];
+
+        let settings = MeshSettings {
+            palette: false,
+            ..Default::default()
+        };
+
+        let meshes = mesh(
+            &voxels,
+            &[],
+            &[],
+            0,
+            settings,
+            &BlockRegistry::default(),
+            &dimensions,
+        )
+        .unwrap();
+
+        assert!(meshes.opaque.is_none());
+        assert_eq!(meshes.submeshes[0].0, BlockMaterial::Emissive);
+
+        let Some(VertexAttributeValues::Float32x4(colors)) =
+            meshes.submeshes[0].1.attribute(Mesh::ATTRIBUTE_COLOR)
+        else {
+            panic!("the mesh doesn't have any colors");
+        };
+
+        // emissive voxels aren't shaded, they're boosted past 1.0 by their emission instead; the
+        // alpha is left alone.
+        let boost = lighting::emissive_brightness(BlockType::Glowstone.emission());
+        assert!(boost > 1.0);
+
+        assert_eq!(colors.len(), 6 * 6);
+
+        for [r, g, b, a] in colors {
+            assert!((r - 0.4 * boost).abs() < 1e-5, "{r}");
+            assert!((g - 0.2 * boost).abs() < 1e-5, "{g}");
+            assert!((b - 0.1 * boost).abs() < 1e-5, "{b}");
+            assert_eq!(*a, 1.0);
+        }
+    }
 }
@@ -1,68 +1,221 @@
-use bevy::{input::common_conditions::input_toggle_active, prelude::*, utils::HashSet};
-use noise::OpenSimplex;
+use bevy::{ecs::schedule::common_conditions::resource_equals, prelude::*, utils::HashSet};
+use bevy_tweening::{asset_animator_system, TweeningPlugin};
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::input::bindings::{action_toggle_active, InputAction, InputBindings};
 
 use self::{
+    block::BlockRegistry,
+    chunk::ChunkDimensions,
+    edit::{EditHistory, VoxelEditEvent},
     event::ChunkCreateEvent,
     events::{
         discovery::{BusyLocations, ChunkDiscoveryEvent},
-        draw::ChunkDrawEvent,
-        gen::ChunkGenerateEvent,
+        draw::{ChunkDrawEvent, ChunkMaterials},
+        export::ChunkExportEvent,
+        gen::{ChunkGenerateEvent, GenerationQueue, GenerationRevision},
         mesh::ChunkMeshEvent,
     },
+    inspect::InspectedChunk,
+    noise_source::{NoiseSource, NoiseType},
+    origin::FloatingOrigin,
+    palette::PaletteMaterial,
+    pool::VoxelBufferPool,
     registry::{ChunkRegistry, Coordinates},
+    timings::ChunkTimings,
 };
 
+pub mod block;
 pub mod chunk;
+pub mod collider;
 pub mod discovery;
+pub mod edit;
 pub mod event;
 pub mod events;
 pub mod generation;
+pub mod inspect;
+pub mod lighting;
 pub mod mesh;
+pub mod noise_source;
+pub mod origin;
+pub mod palette;
+pub mod pool;
+pub mod raycast;
 pub mod registry;
+pub mod storage;
+pub mod surface_nets;
+pub mod timings;
 pub mod voxel;
+pub mod wireframe;
+
+/// Generates, meshes and draws the chunks around the camera.
+///
+/// The settings are inserted as resources when the plugin is built, these can still be changed
+/// afterwards (which is what the inspector does). Everything that isn't configured through the
+/// builder methods uses the `Default` of its settings.
+///
+/// ```ignore
+/// app.add_plugins(
+///     ChunkPlugin::new()
+///         .with_seed(1234)
+///         .with_discovery_radius(8, 4),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct ChunkPlugin {
+    mesh: MeshSettings,
+    discovery: DiscoverySettings,
+    generation: GenerationSettings,
+    // a random seed is picked when the plugin is built if this isn't set.
+    seed: Option<u32>,
+}
+
+impl ChunkPlugin {
+    pub fn new() -> Self {
+        Self {
+            mesh: MeshSettings::default(),
+            discovery: DiscoverySettings::default(),
+            generation: GenerationSettings::default(),
+            seed: None,
+        }
+    }
 
-pub struct ChunkPlugin;
+    /// Sets the discovery radius, in chunks; see `DiscoverySettings`.
+    pub fn with_discovery_radius(mut self, radius: i8, height_radius: i8) -> Self {
+        self.discovery.discovery_radius = radius;
+        self.discovery.discovery_radius_height = height_radius;
+        self
+    }
+
+    pub fn with_discovery(mut self, discovery: DiscoverySettings) -> Self {
+        self.discovery = discovery;
+        self
+    }
+
+    pub fn with_generation(mut self, generation: GenerationSettings) -> Self {
+        self.generation = generation;
+        self
+    }
+
+    pub fn with_mesh(mut self, mesh: MeshSettings) -> Self {
+        self.mesh = mesh;
+        self
+    }
+
+    /// Whether the faces between two voxels are culled while meshing; see `MeshSettings`.
+    pub fn with_occlusion_culling(mut self, enabled: bool) -> Self {
+        self.mesh.occlusion_culling = enabled;
+        self
+    }
+
+    /// The seed of the noise the terrain is generated with.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+}
+
+impl Default for ChunkPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Plugin for ChunkPlugin {
     fn build(&self, app: &mut App) {
+        let seed = self
+            .seed
+            .unwrap_or_else(|| rand::thread_rng().gen_range(0..=50000));
+
+        // the level of detail transitions are faded out through tweens, and the chunks pop in
+        // through them as well.
+        if !app.is_plugin_added::<TweeningPlugin>() {
+            app.add_plugins(TweeningPlugin);
+        }
+
+        app.add_plugins(MaterialPlugin::<PaletteMaterial>::default());
+
         app.insert_resource(ChunkRegistry::new())
-            .insert_resource(OpenSimplexResource(OpenSimplex::new(
-                rand::thread_rng().gen_range(0..=50000),
-            )))
-            .insert_resource(MeshSettings {
-                occlusion_culling: true,
-            })
-            .insert_resource(DiscoverySettings {
-                discovery_radius: 6,
-                discovery_radius_height: 6,
-                // we'll disable this by default, as it's kinda broken.
-                // turning this on makes testing relatively hard due to the absence of proper face/occlusion culling
-                lod: false,
-            })
-            .insert_resource(GenerationSettings {
-                frequency_scale: 0.03,
-                amplitude_scale: 20.0,
-                threshold: 0.4,
-                octaves: 2,
-                persistence: 0.5,
+            .insert_resource(NoiseSource::new(self.generation.noise, seed))
+            .insert_resource(self.mesh.clone())
+            .insert_resource(self.discovery.clone())
+            .insert_resource(self.generation.clone())
+            .insert_resource(PerformanceSettings {
+                // one full detail chunk per core seems to be a sane default; the generation tasks
+                // run on the async compute pool either way, so going over the amount of cores
+                // doesn't gain anything.
+                generation_budget: std::thread::available_parallelism()
+                    .map(|amount| amount.get())
+                    .unwrap_or(4) as u64
+                    * generation::generation_cost(
+                        &ChunkDimensions {
+                            width: ChunkRegistry::CHUNK_SIZE as u32,
+                            height: ChunkRegistry::CHUNK_HEIGHT as u32,
+                            depth: ChunkRegistry::CHUNK_SIZE as u32,
+                        },
+                        0,
+                        &self.generation,
+                    ),
+                target_fps: 60.0,
             })
             .insert_resource(BusyLocations(HashSet::new()))
+            // a buffer takes up roughly 1 MB, so we'll only keep a handful of them around.
+            .insert_resource(VoxelBufferPool::new(16))
+            .insert_resource(ChunkStats::default())
+            .init_resource::<ChunkMaterials>()
+            .init_resource::<ChunkTimings>()
+            .init_resource::<FloatingOrigin>()
+            .init_resource::<BlockRegistry>()
+            .insert_resource(SimulationState::Running)
+            .init_resource::<EditHistory>()
+            .init_resource::<GenerationRevision>()
+            .init_resource::<GenerationQueue>()
+            .init_resource::<InspectedChunk>()
+            // the chunk systems read the bindings too, which shouldn't require the input plugin.
+            .init_resource::<InputBindings>()
             .add_event::<ChunkCreateEvent>()
             .add_event::<ChunkMeshEvent>()
             .add_event::<ChunkDiscoveryEvent>()
             .add_event::<ChunkGenerateEvent>()
             .add_event::<ChunkDrawEvent>()
+            .add_event::<ChunkExportEvent>()
+            .add_event::<VoxelEditEvent>()
             .add_systems(
                 PreUpdate,
                 (
                     event::create_chunk.run_if(on_event::<ChunkCreateEvent>()),
                     events::draw::draw_chunks.run_if(on_event::<ChunkDrawEvent>()),
-                    events::gen::generate_chunk.run_if(on_event::<ChunkGenerateEvent>()),
+                    // this has to run every frame, as it keeps draining its queue even if there
+                    // are no new events.
+                    events::gen::generate_chunk,
                     events::mesh::mesh_chunk.run_if(on_event::<ChunkMeshEvent>()),
-                    discovery::unload_distant_chunks.run_if(input_toggle_active(true, KeyCode::M)),
+                    discovery::unload_distant_chunks
+                        .run_if(action_toggle_active(true, InputAction::ToggleUnloading)),
                 )
-                    .chain(),
+                    .chain()
+                    .run_if(resource_equals(SimulationState::Running)),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_simulation,
+                    origin::rebase_origin,
+                    edit::handle_voxel_edits,
+                    discovery::count_view_culled,
+                    discovery::apply_frustum_culling,
+                    noise_source::update_noise_source
+                        .run_if(resource_changed::<GenerationSettings>()),
+                    events::gen::regenerate_outdated_chunks,
+                    block::remesh_changed_blocks.run_if(resource_changed::<BlockRegistry>()),
+                    events::export::export_chunks.run_if(on_event::<ChunkExportEvent>()),
+                    (wireframe::toggle_wireframe, wireframe::apply_wireframe).chain(),
+                    events::draw::apply_material_settings,
+                    asset_animator_system::<StandardMaterial>,
+                    events::fade::finish_lod_fades,
+                    events::fade::finish_pop_ins,
+                    (inspect::inspect_chunk, inspect::draw_inspected_chunk).chain(),
+                ),
             )
             .add_systems(
                 Last,
@@ -71,38 +224,323 @@ impl Plugin for ChunkPlugin {
                     events::discovery::processing::process_discovery_tasks,
                     events::gen::process_chunk_generation,
                     events::discovery::query::handle_chunk_discovery
-                        .run_if(input_toggle_active(true, KeyCode::L)),
+                        .run_if(action_toggle_active(true, InputAction::ToggleDiscovery)),
+                    events::busy::release_stale_busy_chunks,
                 )
-                    .chain(),
+                    .chain()
+                    .run_if(resource_equals(SimulationState::Running)),
             );
     }
 }
 
+/// Whether the world is being simulated.
+///
+/// While paused, none of the chunk systems run: no chunks get discovered, generated, meshed,
+/// drawn or unloaded, which freezes the world exactly as it is. Everything else, such as moving
+/// the camera around and the UI, keeps working.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimulationState {
+    Running,
+    Paused,
+}
+
+/// Toggles the `SimulationState` whenever `InputAction::ToggleSimulation` is pressed.
+pub fn toggle_simulation(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut state: ResMut<SimulationState>,
+) {
+    if bindings.just_pressed(InputAction::ToggleSimulation, &keys) {
+        *state = match *state {
+            SimulationState::Running => SimulationState::Paused,
+            SimulationState::Paused => SimulationState::Running,
+        };
+    }
+}
+
 #[derive(Component)]
 pub struct ChunkEntity {
     pub position: Coordinates,
 }
 
-#[derive(Resource, Clone)]
-pub struct OpenSimplexResource(OpenSimplex);
-
-#[derive(Resource, Clone)]
+#[derive(Resource, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MeshSettings {
     pub occlusion_culling: bool,
+    // whether to build the collision boxes of a chunk alongside its mesh, see `collider::greedy_boxes`.
+    pub colliders: bool,
+    // renders every chunk as a wireframe, on top of its regular material. requires the
+    // `WireframePlugin`.
+    pub wireframe: bool,
+    // renders both sides of every face, rather than only the front. faces that are missing because
+    // they're wound the wrong way show up again with this enabled, see
+    // `events::draw::apply_material_settings`.
+    pub double_sided: bool,
+    // how long (in seconds) the previous mesh of a chunk is faded out for once its level of detail
+    // changes, see `events::fade::spawn_lod_fade`. the mesh is swapped right away at 0.
+    pub lod_fade: f32,
+    // how long (in seconds) a newly drawn chunk takes to grow into place, see
+    // `events::fade::pop_in_animator`. chunks appear right away at 0.
+    pub pop_in: f32,
+    // what the voxels are meshed as, see `MeshTopology`.
+    pub topology: MeshTopology,
+    // stores the colors of the faces as an index into a palette, rather than a full color for
+    // every vertex; see `palette::PaletteMaterial`. this saves memory and upload bandwidth, at the
+    // cost of a material per mesh.
+    pub palette: bool,
 }
 
-#[derive(Resource, Clone)]
+/// What the voxels of a chunk are meshed as.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MeshTopology {
+    // the faces of every voxel, which is what the world is normally rendered as.
+    #[default]
+    Faces,
+    // a single point at the center of every visible voxel. nothing is culled or lit, so this shows
+    // the voxels exactly as they are; useful to tell whether something is wrong with the voxels
+    // themselves or with their faces.
+    Points,
+    // a smooth surface through the terrain density of the voxels, see `surface_nets`. only the
+    // solid voxels are part of it, anything else (such as water) is still meshed as faces.
+    Smooth,
+}
+
+impl MeshTopology {
+    pub const ALL: [MeshTopology; 3] = [
+        MeshTopology::Faces,
+        MeshTopology::Points,
+        MeshTopology::Smooth,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MeshTopology::Faces => "Faces",
+            MeshTopology::Points => "Points",
+            MeshTopology::Smooth => "Smooth",
+        }
+    }
+}
+
+impl Default for MeshSettings {
+    fn default() -> Self {
+        Self {
+            occlusion_culling: true,
+            // nothing consumes these yet, so there's no point in building them by default.
+            colliders: false,
+            wireframe: false,
+            double_sided: false,
+            lod_fade: 0.3,
+            pop_in: 0.25,
+            topology: MeshTopology::Faces,
+            palette: false,
+        }
+    }
+}
+
+#[derive(Resource, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DiscoverySettings {
     pub discovery_radius: i8,
     pub discovery_radius_height: i8,
     pub lod: bool,
+    // the distance (in chunks) is multiplied by this before turning it into a level of detail;
+    // higher values make the level of detail drop off closer to the camera.
+    pub lod_bias: f32,
+    // let bevy skip the chunks that are outside of the view frustum of the camera while rendering,
+    // see `discovery::apply_frustum_culling`. every chunk within the discovery radius is loaded
+    // regardless, the ones in view are only loaded first.
+    pub frustum_cull: bool,
+    // hide the chunks that are behind an entirely solid chunk, as seen from the camera. this is
+    // only a coarse test, but it does cost some time on the main thread; see
+    // `discovery::is_occluded`.
+    pub occlusion_cull: bool,
+    // the shape of the area around the camera in which chunks are loaded, see
+    // `discovery::is_within_discovery`.
+    pub shape: DiscoveryShape,
+    // limits the world to a finite area; chunks outside of it are never loaded at all.
+    pub bounds: Option<WorldBounds>,
+}
+
+/// The chunks a finite world consists of, in chunk coordinates; both corners are inclusive.
+///
+/// Nothing outside of the bounds gets discovered, so it's never created or generated. The chunks
+/// on the edge have nothing to cull their outer faces against, so the world ends in a wall.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorldBounds {
+    pub min: Coordinates,
+    pub max: Coordinates,
+}
+
+impl WorldBounds {
+    pub fn contains(&self, chunk: Coordinates) -> bool {
+        chunk.cmpge(self.min).all() && chunk.cmple(self.max).all()
+    }
+}
+
+/// The shape of the area in which chunks are discovered; the horizontal size is set by
+/// `DiscoverySettings::discovery_radius`, the vertical size by
+/// `DiscoverySettings::discovery_radius_height`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiscoveryShape {
+    Box,
+    Cylinder,
+    // an ellipsoid rather, unless both radii are the same.
+    Sphere,
+}
+
+impl DiscoveryShape {
+    pub const ALL: [DiscoveryShape; 3] = [
+        DiscoveryShape::Box,
+        DiscoveryShape::Cylinder,
+        DiscoveryShape::Sphere,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DiscoveryShape::Box => "Box",
+            DiscoveryShape::Cylinder => "Cylinder",
+            DiscoveryShape::Sphere => "Sphere",
+        }
+    }
+}
+
+impl Default for DiscoverySettings {
+    fn default() -> Self {
+        Self {
+            discovery_radius: 6,
+            discovery_radius_height: 6,
+            // we'll disable this by default; the LOD transitions are still rather visible, which
+            // makes testing relatively hard.
+            lod: false,
+            lod_bias: 3.0,
+            frustum_cull: true,
+            occlusion_cull: false,
+            shape: DiscoveryShape::Cylinder,
+            bounds: None,
+        }
+    }
+}
+
+/// Combinations of the `DiscoverySettings` that go well together, from cheap to expensive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderDistance {
+    Near,
+    Medium,
+    Far,
+    Extreme,
+}
+
+impl RenderDistance {
+    pub const ALL: [RenderDistance; 4] = [
+        RenderDistance::Near,
+        RenderDistance::Medium,
+        RenderDistance::Far,
+        RenderDistance::Extreme,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RenderDistance::Near => "Near",
+            RenderDistance::Medium => "Medium",
+            RenderDistance::Far => "Far",
+            RenderDistance::Extreme => "Extreme",
+        }
+    }
+
+    /// Applies the preset to the given settings; the settings that aren't part of the preset are
+    /// left untouched.
+    pub fn apply(&self, settings: &mut DiscoverySettings) {
+        // (radius, height radius, level of detail, lod bias)
+        let (discovery_radius, discovery_radius_height, lod, lod_bias) = match self {
+            RenderDistance::Near => (4, 3, false, 3.0),
+            // the defaults of the `ChunkPlugin`.
+            RenderDistance::Medium => (6, 6, false, 3.0),
+            // the level of detail is pretty much required past this point, otherwise meshing
+            // the distant chunks takes far too long.
+            RenderDistance::Far => (12, 6, true, 3.0),
+            RenderDistance::Extreme => (24, 8, true, 2.0),
+        };
+
+        settings.discovery_radius = discovery_radius;
+        settings.discovery_radius_height = discovery_radius_height;
+        settings.lod = lod;
+        settings.lod_bias = lod_bias;
+    }
 }
 
 #[derive(Resource, Clone)]
+pub struct PerformanceSettings {
+    // how much work (in voxel operations, see `generation::generation_cost`) the chunks that are
+    // being generated at the same time may add up to. higher values load the world faster, lower
+    // values leave more room for everything else (and keep the frame times more stable).
+    pub generation_budget: u64,
+    // the frame rate we're trying to stay above; the amount of chunks processed per frame is
+    // lowered whenever the frame rate drops below it. see `events::discovery::QueueLimit`.
+    pub target_fps: f64,
+}
+
+/// Statistics of the currently loaded chunks, mostly meant for debugging purposes.
+#[derive(Resource, Default, Clone, Debug)]
+pub struct ChunkStats {
+    // the amount of loaded chunks that are visible.
+    pub kept: usize,
+    // the amount of visible chunks that bevy didn't render last frame, as their bounding box is
+    // outside of the view frustum. this is always 0 if frustum culling is disabled, see
+    // `discovery::count_view_culled`.
+    pub view_culled: usize,
+    // the amount of loaded chunks that are hidden, since they are behind
+    // an entirely solid chunk. this is always 0 if occlusion culling is disabled.
+    pub occluded: usize,
+    // the amount of chunks within the discovery area, as of the last discovery scan.
+    pub discovered: usize,
+    // the amount of those chunks that are done loading; these are either drawn, or turned out to
+    // be empty and don't need to be drawn at all. see `processing::is_settled`.
+    pub settled: usize,
+}
+
+#[derive(Resource, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GenerationSettings {
     pub frequency_scale: f64,
     pub amplitude_scale: f64,
     pub threshold: f64,
     pub octaves: i32,
     pub persistence: f64,
+    // every non-solid voxel below this (world) y level gets filled with water.
+    pub sea_level: f64,
+    // the (world) y level the terrain is biased towards; voxels below it are more likely to be
+    // solid, voxels above it are more likely to be air.
+    pub surface_level: f64,
+    // how quickly the bias grows with the distance to the surface level, in voxels. lower values
+    // give flatter terrain, higher values let the noise stick out further from the surface.
+    pub height_falloff: f64,
+    // the noise function the terrain is generated with, see `noise_source::NoiseSource`.
+    pub noise: NoiseType,
+    // the colors of the terrain by height, these are checked in order. see
+    // `generation::HeightBand`.
+    pub height_bands: Vec<generation::HeightBand>,
+    // interpolate the noise from a coarse lattice, rather than sampling it for every voxel. this is
+    // a lot faster, at the cost of smoothing out the finer details; see `generation::NoiseLattice`.
+    pub interpolated_noise: bool,
+    // the distance (in voxels) between the points of the lattice.
+    pub lattice_resolution: u32,
+}
+
+impl Default for GenerationSettings {
+    fn default() -> Self {
+        Self {
+            frequency_scale: 0.03,
+            amplitude_scale: 20.0,
+            threshold: 0.4,
+            octaves: 2,
+            persistence: 0.5,
+            sea_level: 8.0,
+            surface_level: 16.0,
+            height_falloff: 8.0,
+            noise: NoiseType::OpenSimplex,
+            height_bands: generation::default_height_bands(),
+            interpolated_noise: false,
+            lattice_resolution: 4,
+        }
+    }
 }
@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use noise::{Billow, NoiseFn, OpenSimplex, Perlin, RidgedMulti, SuperSimplex};
+use serde::{Deserialize, Serialize};
+
+use super::GenerationSettings;
+
+/// Any noise function the terrain can be generated with.
+///
+/// The voxels of a chunk are sampled in parallel, so the noise has to be shareable between
+/// threads. this rules out `Worley` for now, as it keeps its distance function in an `Rc`.
+pub type DynNoise = dyn NoiseFn<f64, 3> + Send + Sync;
+
+/// The noise functions that can be picked from the UI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoiseType {
+    OpenSimplex,
+    SuperSimplex,
+    Perlin,
+    // sharp ridges, gives mountain-like terrain.
+    RidgedMulti,
+    // rounded, puffy shapes.
+    Billow,
+}
+
+impl NoiseType {
+    pub const ALL: [NoiseType; 5] = [
+        NoiseType::OpenSimplex,
+        NoiseType::SuperSimplex,
+        NoiseType::Perlin,
+        NoiseType::RidgedMulti,
+        NoiseType::Billow,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NoiseType::OpenSimplex => "OpenSimplex",
+            NoiseType::SuperSimplex => "SuperSimplex",
+            NoiseType::Perlin => "Perlin",
+            NoiseType::RidgedMulti => "Ridged Multifractal",
+            NoiseType::Billow => "Billow",
+        }
+    }
+
+    fn build(&self, seed: u32) -> Arc<DynNoise> {
+        match self {
+            NoiseType::OpenSimplex => Arc::new(OpenSimplex::new(seed)),
+            NoiseType::SuperSimplex => Arc::new(SuperSimplex::new(seed)),
+            NoiseType::Perlin => Arc::new(Perlin::new(seed)),
+            NoiseType::RidgedMulti => Arc::new(RidgedMulti::<Perlin>::new(seed)),
+            NoiseType::Billow => Arc::new(Billow::<Perlin>::new(seed)),
+        }
+    }
+}
+
+/// The noise function the terrain is currently being generated with.
+///
+/// This gets rebuilt by [`update_noise_source()`] whenever `GenerationSettings::noise` changes; the
+/// seed is kept, so switching back and forth gives the same terrain.
+#[derive(Resource, Clone)]
+pub struct NoiseSource {
+    pub seed: u32,
+    noise_type: NoiseType,
+    noise: Arc<DynNoise>,
+}
+
+impl NoiseSource {
+    pub fn new(noise_type: NoiseType, seed: u32) -> Self {
+        Self {
+            seed,
+            noise_type,
+            noise: noise_type.build(seed),
+        }
+    }
+
+    #[inline]
+    pub fn noise_type(&self) -> NoiseType {
+        self.noise_type
+    }
+
+    /// Gets the noise function itself. the `Arc` can be cloned cheaply to move the noise into a
+    /// generation task.
+    #[inline]
+    pub fn noise(&self) -> &Arc<DynNoise> {
+        &self.noise
+    }
+}
+
+pub fn update_noise_source(settings: Res<GenerationSettings>, mut source: ResMut<NoiseSource>) {
+    if source.noise_type != settings.noise {
+        *source = NoiseSource::new(settings.noise, source.seed);
+    }
+}
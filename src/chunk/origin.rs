@@ -0,0 +1,122 @@
+use bevy::prelude::*;
+
+use crate::input::camera::PlayerController;
+
+use super::{
+    registry::{ChunkCoordinates, ChunkRegistry, Coordinates},
+    ChunkEntity,
+};
+
+// how far (in voxels, on any axis) the camera can get away from the origin before the world is
+// shifted back towards it. f32 is still precise to well below a millimeter at this distance.
+pub const REBASE_DISTANCE: f32 = 2048.0;
+
+/// The world position of the render origin.
+///
+/// Everything that's rendered (the camera and the chunk entities) is positioned relative to this
+/// origin, rather than to the origin of the world; the registry keeps using absolute world
+/// coordinates. This way the transforms stay close to zero, no matter how far the camera travels,
+/// as `f32` loses precision rather quickly the further it gets from zero.
+///
+/// The offset is always aligned to the chunk grid, so converting between both spaces never
+/// changes the position of something within its chunk.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FloatingOrigin {
+    pub offset: Coordinates,
+}
+
+impl FloatingOrigin {
+    /// Converts a world position into a position relative to the render origin, such as the
+    /// translation of a chunk entity.
+    pub fn to_render(&self, coordinates: Coordinates) -> Vec3 {
+        // the subtraction happens on the integers, so there's no precision lost here.
+        (coordinates - self.offset).as_vec3()
+    }
+
+    /// Converts a position relative to the render origin (such as the translation of the camera)
+    /// into a world position.
+    pub fn to_world(&self, position: Vec3) -> Vec3 {
+        position + self.offset.as_vec3()
+    }
+
+    /// Converts a position relative to the render origin into the world position of the origin of
+    /// the chunk containing it.
+    pub fn to_world_origin(&self, position: Vec3) -> Coordinates {
+        self.offset + position.to_world_origin()
+    }
+
+    /// Same as `ChunkRegistry::world_to_chunk_space()`, but for a position relative to the render
+    /// origin.
+    pub fn to_chunk_space(&self, position: Vec3) -> Vec3 {
+        ChunkRegistry::world_to_chunk_space(position) + self.offset.to_chunk_coords().as_vec3()
+    }
+
+    /// Same as `ChunkCoordinates::to_chunk_coords()`, but for a position relative to the render
+    /// origin.
+    pub fn to_chunk_coords(&self, position: Vec3) -> Coordinates {
+        self.offset.to_chunk_coords() + position.to_chunk_coords()
+    }
+}
+
+/// Moves the render origin to the chunk the camera is in, once it's more than `REBASE_DISTANCE`
+/// away from it.
+///
+/// The camera and every chunk entity are moved along with it, so nothing visibly changes.
+pub fn rebase_origin(
+    mut origin: ResMut<FloatingOrigin>,
+    mut camera: Query<&mut Transform, With<PlayerController>>,
+    chunks: Query<(Entity, &ChunkEntity)>,
+    mut transforms: Query<&mut Transform, Without<PlayerController>>,
+) {
+    let Ok(mut camera) = camera.get_single_mut() else {
+        return;
+    };
+
+    if camera.translation.abs().max_element() < REBASE_DISTANCE {
+        return;
+    }
+
+    let shift = camera.translation.to_world_origin();
+
+    origin.offset += shift;
+    camera.translation -= shift.as_vec3();
+
+    // the submeshes are children of the chunk entities, these are moved along with them.
+    for (entity, ChunkEntity { position }) in chunks.iter() {
+        if let Ok(mut transform) = transforms.get_mut(entity) {
+            transform.translation = origin.to_render(*position);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::Vec3;
+
+    use super::FloatingOrigin;
+    use crate::chunk::registry::{ChunkCoordinates, ChunkRegistry, Coordinates};
+
+    #[test]
+    fn test_matches_world_space() {
+        let size = ChunkRegistry::CHUNK_SIZE;
+        let origin = FloatingOrigin {
+            offset: Coordinates::new(size * 1000, -size, size * -3),
+        };
+
+        let position = Vec3::new(10.5, 3.0, -40.0);
+        let world = origin.to_world(position);
+
+        assert_eq!(origin.to_chunk_coords(position), world.to_chunk_coords());
+        assert_eq!(origin.to_world_origin(position), world.to_world_origin());
+        assert_eq!(
+            origin.to_chunk_space(position),
+            ChunkRegistry::world_to_chunk_space(world)
+        );
+
+        // the chunk containing the camera is rendered at the origin of the camera's chunk.
+        assert_eq!(
+            origin.to_render(world.to_world_origin()),
+            position.to_world_origin().as_vec3()
+        );
+    }
+}
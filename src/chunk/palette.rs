@@ -0,0 +1,211 @@
+use bevy::{
+    pbr::{MaterialPipeline, MaterialPipelineKey},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    render::{
+        mesh::{MeshVertexAttribute, MeshVertexBufferLayout},
+        render_resource::{
+            AsBindGroup, Face, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+            VertexFormat,
+        },
+    },
+    utils::HashMap,
+};
+
+use super::{voxel::BlockMaterial, MeshSettings};
+
+/// The most colors a single palette can hold. The palette of a mesh only takes up as much memory
+/// as the colors it actually holds (see `PaletteMaterial`), this only keeps it from growing
+/// without bounds; a couple hundred colors is the most a chunk usually has.
+pub const PALETTE_SIZE: usize = 1024;
+
+/// The index of the color of every vertex within the palette of its mesh, this takes the place of
+/// `Mesh::ATTRIBUTE_COLOR` for the meshes that have a palette; see [`compress_colors()`].
+pub const ATTRIBUTE_COLOR_INDEX: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_ColorIndex", 1_297_040_202, VertexFormat::Uint32);
+
+/// Splits the vertex colors of a mesh into a palette of the distinct colors, and the index of
+/// every color within it. A color index takes up a quarter of the memory of a full color, and
+/// most vertices of a chunk share a couple hundred colors at most.
+///
+/// The colors are compared at 8 bits per channel, which is as precise as they end up on screen
+/// anyway. `None` is returned if there are more than [`PALETTE_SIZE`] distinct colors; the mesh
+/// has to keep its full colors in that case.
+pub fn compress_colors(colors: &[[f32; 4]]) -> Option<(Vec<Vec4>, Vec<u32>)> {
+    let mut palette = Vec::new();
+    let mut lookup = HashMap::new();
+
+    let indices = colors
+        .iter()
+        .map(|color| {
+            // emissive voxels are brighter than white, so the channels aren't clamped to 255.
+            let key = color.map(|channel| (channel * 255.0).round() as u16);
+
+            let index = *lookup.entry(key).or_insert_with(|| {
+                palette.push(Vec4::from_array(*color));
+                palette.len() - 1
+            });
+
+            (index < PALETTE_SIZE).then_some(index as u32)
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some((palette, indices))
+}
+
+/// The material of the chunk meshes that have a palette, see `MeshSettings::palette`. The color
+/// of every vertex is looked up within the palette by `palette.wgsl`, which is lit the same way
+/// as a `StandardMaterial` would be.
+///
+/// Every mesh has a palette of its own, so the colors are kept in a storage buffer that's only as
+/// large as the palette itself; a uniform would have to be sized for the largest palette there
+/// is, for every single mesh.
+#[derive(AsBindGroup, TypeUuid, TypePath, Debug, Clone)]
+#[uuid = "2f6f0b63-5d8e-4a53-9c0e-5b7b4c1de0a1"]
+#[bind_group_data(PaletteMaterialKey)]
+pub struct PaletteMaterial {
+    // at most `PALETTE_SIZE` colors.
+    #[storage(0, read_only)]
+    pub colors: Vec<Vec4>,
+    pub alpha_mode: AlphaMode,
+    pub unlit: bool,
+    pub cull_mode: Option<Face>,
+}
+
+impl PaletteMaterial {
+    /// Creates the material for a mesh of the given `BlockMaterial`, which behaves the same as the
+    /// `StandardMaterial` it would otherwise be drawn with; see `standard_material()`.
+    pub fn new(colors: Vec<Vec4>, material: BlockMaterial, settings: &MeshSettings) -> Self {
+        let mut palette = Self {
+            colors,
+            alpha_mode: match material {
+                BlockMaterial::Transparent => AlphaMode::Blend,
+                BlockMaterial::Opaque | BlockMaterial::Emissive => AlphaMode::Opaque,
+            },
+            unlit: material == BlockMaterial::Emissive,
+            cull_mode: None,
+        };
+
+        palette.set_cull_mode(settings);
+        palette
+    }
+
+    pub fn set_cull_mode(&mut self, settings: &MeshSettings) {
+        self.cull_mode = match settings.double_sided {
+            true => None,
+            false => Some(Face::Back),
+        };
+    }
+}
+
+/// Everything about a `PaletteMaterial` that needs a different pipeline.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct PaletteMaterialKey {
+    unlit: bool,
+    blend: bool,
+    cull_mode: Option<Face>,
+}
+
+impl From<&PaletteMaterial> for PaletteMaterialKey {
+    fn from(material: &PaletteMaterial) -> Self {
+        Self {
+            unlit: material.unlit,
+            blend: material.alpha_mode == AlphaMode::Blend,
+            cull_mode: material.cull_mode,
+        }
+    }
+}
+
+impl Material for PaletteMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "palette.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "palette.wgsl".into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            ATTRIBUTE_COLOR_INDEX.at_shader_location(2),
+        ])?;
+
+        descriptor.vertex.buffers = vec![vertex_layout];
+        descriptor.primitive.cull_mode = key.bind_group_data.cull_mode;
+
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            if key.bind_group_data.unlit {
+                fragment.shader_defs.push("PALETTE_UNLIT".into());
+            }
+
+            // the alpha of the opaque materials is ignored, just like it is for the standard ones.
+            if key.bind_group_data.blend {
+                fragment.shader_defs.push("PALETTE_BLEND".into());
+            }
+
+            // the back faces are lit as if they were facing the other way otherwise, see
+            // `set_cull_mode()` of the standard materials.
+            if key.bind_group_data.cull_mode.is_none() {
+                fragment.shader_defs.push("PALETTE_DOUBLE_SIDED".into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::{AlphaMode, Vec4};
+
+    use super::{compress_colors, PaletteMaterial, PALETTE_SIZE};
+    use crate::chunk::{voxel::BlockMaterial, MeshSettings};
+
+    #[test]
+    fn test_compress_colors() {
+        let (red, green) = ([1.0, 0.0, 0.0, 1.0], [0.0, 0.5, 0.0, 1.0]);
+        let (palette, indices) = compress_colors(&[red, green, red, red, green]).unwrap();
+
+        assert_eq!(palette.len(), 2);
+        assert_eq!(indices, [0, 1, 0, 0, 1]);
+        assert_eq!(palette[indices[1] as usize].to_array(), green);
+
+        // one color too many doesn't fit in a palette anymore.
+        let colors = (0..=PALETTE_SIZE)
+            .map(|index| [index as f32 / 255.0, 0.0, 0.0, 1.0])
+            .collect::<Vec<_>>();
+
+        assert!(compress_colors(&colors[..PALETTE_SIZE]).is_some());
+        assert!(compress_colors(&colors).is_none());
+    }
+
+    #[test]
+    fn test_palette_material() {
+        let (palette, _) = compress_colors(&[[1.0, 0.0, 0.0, 1.0], [0.0, 0.0, 1.0, 0.5]]).unwrap();
+        let settings = MeshSettings::default();
+
+        // the palette isn't padded, the buffer only holds the colors the mesh actually uses.
+        let material = PaletteMaterial::new(palette.clone(), BlockMaterial::Transparent, &settings);
+
+        assert_eq!(material.colors, palette);
+        assert_eq!(material.alpha_mode, AlphaMode::Blend);
+        assert!(!material.unlit);
+
+        let material = PaletteMaterial::new(vec![Vec4::ONE], BlockMaterial::Emissive, &settings);
+
+        assert_eq!(material.colors.len(), 1);
+        assert_eq!(material.alpha_mode, AlphaMode::Opaque);
+        assert!(material.unlit);
+    }
+}
@@ -0,0 +1,90 @@
+use bevy::prelude::*;
+
+use super::voxel::Voxel;
+
+/// A pool of voxel buffers, used to avoid reallocating the voxels of a chunk over and over again.
+///
+/// Every chunk starts out with a buffer from this pool, and whenever a chunk gets generated (again)
+/// the buffer it was using goes back into the pool. Unloaded chunks are kept in the registry, so
+/// their buffers stay in use until they're regenerated, e.g. at a different level of detail.
+///
+/// The pool never holds on to more than `capacity` buffers; anything beyond that is dropped.
+#[derive(Resource, Debug)]
+pub struct VoxelBufferPool {
+    buffers: Vec<Vec<Voxel>>,
+    capacity: usize,
+    // the amount of buffers that had to be allocated, since there were none left in the pool.
+    pub allocated: usize,
+    // the amount of buffers that were taken from the pool, rather than being allocated.
+    pub reused: usize,
+}
+
+impl VoxelBufferPool {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buffers: Vec::with_capacity(capacity),
+            capacity,
+            allocated: 0,
+            reused: 0,
+        }
+    }
+
+    /// Takes a buffer of `len` empty voxels out of the pool, or allocates one if the pool is empty.
+    pub fn take(&mut self, len: usize) -> Vec<Voxel> {
+        match self.buffers.pop() {
+            Some(mut buffer) => {
+                self.reused += 1;
+
+                buffer.clear();
+                buffer.resize(len, Voxel::default());
+                buffer
+            }
+            None => {
+                self.allocated += 1;
+
+                vec![Voxel::default(); len]
+            }
+        }
+    }
+
+    /// Puts a buffer back into the pool, so it can be reused later on.
+    pub fn give(&mut self, buffer: Vec<Voxel>) {
+        if self.buffers.len() < self.capacity {
+            self.buffers.push(buffer);
+        }
+    }
+
+    /// The amount of buffers that are currently waiting in the pool.
+    pub fn available(&self) -> usize {
+        self.buffers.len()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::Color;
+    use half::f16;
+
+    use super::VoxelBufferPool;
+    use crate::chunk::voxel::Voxel;
+
+    #[test]
+    fn test_take_and_give() {
+        let mut pool = VoxelBufferPool::new(1);
+
+        let mut buffer = pool.take(8);
+        buffer[3] = Voxel::new_solid(Color::GRAY, f16::ONE);
+
+        pool.give(buffer);
+        // the pool is full, so this one gets dropped.
+        pool.give(Vec::new());
+
+        assert_eq!(pool.available(), 1);
+
+        let buffer = pool.take(16);
+
+        // reused buffers have to be cleared, the previous voxels must not leak into a new chunk.
+        assert_eq!(buffer, vec![Voxel::default(); 16]);
+        assert_eq!((pool.allocated, pool.reused), (1, 1));
+    }
+}
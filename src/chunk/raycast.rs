@@ -0,0 +1,155 @@
+use bevy::{math::Ray, prelude::*};
+
+use super::{
+    origin::FloatingOrigin,
+    registry::{ChunkCoordinates, ChunkRegistry, Coordinates},
+};
+
+/// The first visible voxel along a ray, see [`raycast()`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RaycastHit {
+    // the world position of the voxel that was hit.
+    pub position: Coordinates,
+    // the normal of the face the ray entered the voxel through; this is zero if the ray started
+    // out inside of it.
+    pub normal: IVec3,
+    // how far along the ray the voxel was hit, in voxels.
+    pub distance: f32,
+}
+
+impl RaycastHit {
+    /// The world position of the chunk containing the voxel that was hit.
+    pub fn chunk(&self) -> Coordinates {
+        self.position.to_world_origin()
+    }
+}
+
+/// Walks along a ray (relative to the render origin, like the camera) one voxel at a time, until
+/// it hits a visible voxel or has travelled `max_distance` voxels.
+///
+/// The voxels are visited in the exact order the ray passes through them, so a ray can't slip
+/// through a gap between two voxels. Chunks that aren't loaded are treated as if they're empty.
+pub fn raycast(
+    registry: &ChunkRegistry,
+    origin: &FloatingOrigin,
+    ray: Ray,
+    max_distance: f32,
+) -> Option<RaycastHit> {
+    let direction = ray.direction.normalize_or_zero();
+
+    if direction == Vec3::ZERO {
+        return None;
+    }
+
+    let mut cell = ray.origin.floor().as_ivec3();
+    let step = direction.signum().as_ivec3();
+
+    // how far along the ray the next voxel boundary is on every axis, and how far apart the
+    // boundaries are. an axis the ray doesn't move along never reaches its next boundary.
+    let delta = direction.recip().abs();
+    let next_boundary = cell.as_vec3() + step.max(IVec3::ZERO).as_vec3();
+    let mut boundary = Vec3::select(
+        direction.cmpne(Vec3::ZERO),
+        ((next_boundary - ray.origin) / direction).abs(),
+        Vec3::INFINITY,
+    );
+
+    let mut normal = IVec3::ZERO;
+    let mut distance = 0.0;
+
+    while distance <= max_distance {
+        let position = origin.offset + cell;
+        let visible = registry
+            .get_chunk_at(position.to_world_origin())
+            .and_then(|chunk| chunk.get_voxel(position.chunk_local_offset()).copied())
+            .is_some_and(|voxel| voxel.is_visible());
+
+        if visible {
+            return Some(RaycastHit {
+                position,
+                normal,
+                distance,
+            });
+        }
+
+        // steps into the neighbor along the axis with the closest boundary.
+        let axis = match boundary.min_element() {
+            min if min == boundary.x => 0,
+            min if min == boundary.y => 1,
+            _ => 2,
+        };
+
+        distance = boundary[axis];
+        boundary[axis] += delta[axis];
+        cell[axis] += step[axis];
+
+        normal = IVec3::ZERO;
+        normal[axis] = -step[axis];
+    }
+
+    None
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::{
+        math::Ray,
+        prelude::{Color, IVec3, Vec3},
+    };
+    use half::f16;
+
+    use super::raycast;
+    use crate::chunk::{
+        chunk::Chunk,
+        origin::FloatingOrigin,
+        registry::{ChunkRegistry, Coordinates},
+        voxel::Voxel,
+    };
+
+    #[test]
+    fn test_raycast() {
+        let registry = ChunkRegistry::new();
+        let size = ChunkRegistry::CHUNK_SIZE;
+
+        // two chunks next to each other, with a single voxel in the second one.
+        for x in [0, size] {
+            let position = Coordinates::new(x, 0, 0);
+            let chunk = Chunk::new(
+                size as u32,
+                ChunkRegistry::CHUNK_HEIGHT as u32,
+                size as u32,
+                position,
+            );
+
+            registry.push_chunk_at(position, chunk);
+        }
+
+        let target = Coordinates::new(size + 3, 5, 7);
+        registry
+            .get_chunk_at_mut(Coordinates::new(size, 0, 0))
+            .unwrap()
+            .set_voxel([3, 5, 7], Voxel::new_solid(Color::GRAY, f16::ONE));
+
+        // the render origin is a chunk further along, which the hit has to account for.
+        let origin = FloatingOrigin {
+            offset: Coordinates::new(size, 0, 0),
+        };
+        let ray = |from: Vec3| Ray {
+            origin: from,
+            direction: (Vec3::new(3.5, 5.5, 7.5) - from).normalize(),
+        };
+
+        let hit = raycast(&registry, &origin, ray(Vec3::new(-20.5, 2.25, 1.75)), 64.0).unwrap();
+
+        assert_eq!(hit.position, target);
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert_eq!(hit.chunk(), Coordinates::new(size, 0, 0));
+
+        // straight down onto the top of the voxel, and too short to reach it.
+        let hit = raycast(&registry, &origin, ray(Vec3::new(3.5, 20.5, 7.5)), 64.0).unwrap();
+
+        assert_eq!(hit.normal, IVec3::Y);
+        assert_eq!(hit.distance, 14.5);
+        assert!(raycast(&registry, &origin, ray(Vec3::new(3.5, 20.5, 7.5)), 10.0).is_none());
+    }
+}
@@ -1,123 +1,405 @@
-use super::chunk::Chunk;
+use super::chunk::{Chunk, VoxelFace};
 use bevy::{
-    prelude::{IVec3, Resource},
+    prelude::{IVec3, Resource, UVec3, Vec3},
     utils::HashMap,
 };
+use parking_lot::{
+    MappedRwLockReadGuard, MappedRwLockWriteGuard, RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
+
+/// The amount of shards the chunks are spread over. more shards means less contention between
+/// systems accessing different chunks at the same time.
+const SHARD_COUNT: usize = 16;
+
+type Shard = RwLock<HashMap<ChunkId, Chunk>>;
 
 /// A registry for managing and accessing chunks within a 3D environment.
 ///
 /// This struct provides functionality to create and retrieve chunks based on their coordinates,
 /// as well as convert between chunk coordinates and IDs for storage and indexing.
-#[derive(Debug, Clone, Resource)]
+///
+/// The chunks are spread over a set of shards, each behind their own `RwLock`. This means the
+/// registry never has to be accessed through `ResMut`; systems which only access the registry
+/// through `Res` can run in parallel, only blocking each other when they access chunks within the
+/// same shard at the same time.
+///
+/// # Deadlocks
+///
+/// The guards returned by [`get_chunk_at()`](Self::get_chunk_at) and
+/// [`get_chunk_at_mut()`](Self::get_chunk_at_mut) lock the entire shard the chunk lives in. Never
+/// hold on to a write guard while accessing another chunk, as that chunk may very well live in
+/// the same shard. Holding on to multiple read guards at the same time is fine.
+#[derive(Debug, Resource)]
 pub struct ChunkRegistry {
-    chunks: HashMap<i32, Chunk>,
+    shards: [Shard; SHARD_COUNT],
+    // the lowest and highest chunk y (in chunks) of all chunks within the registry, used to know
+    // where to start looking when scanning a column of chunks.
+    vertical_range: RwLock<Option<(i32, i32)>>,
 }
 
 pub type Coordinates = IVec3;
 
+/// The key chunks are stored under within the [`ChunkRegistry`]; this is the position of the
+/// chunk in chunks, rather than in voxels.
+///
+/// We used to pack the coordinates into a single `i32`, but that overflowed only a couple of
+/// chunks away from the origin. Keying by the coordinates themselves can't collide for any
+/// position within the range of `Coordinates`.
+pub type ChunkId = IVec3;
+
+/// Conversions between world space and chunk space.
+///
+/// All conversions round toward negative infinity, rather than toward zero like regular integer
+/// division does. Without this, the chunks on either side of the origin would overlap; both world
+/// x `-5` and `5` would end up in chunk x `0`.
+pub trait ChunkCoordinates {
+    /// Converts a world position into the position of the chunk containing it, in chunks.
+    fn to_chunk_coords(self) -> Coordinates;
+
+    /// Gets the world position of the origin (the minimum corner) of the chunk containing this
+    /// world position.
+    fn to_world_origin(self) -> Coordinates;
+
+    /// Gets the position of this world position within its chunk, in voxels.
+    fn chunk_local_offset(self) -> UVec3;
+}
+
+impl ChunkCoordinates for Coordinates {
+    #[inline]
+    fn to_chunk_coords(self) -> Coordinates {
+        Coordinates::new(
+            self.x.div_euclid(ChunkRegistry::CHUNK_SIZE),
+            self.y.div_euclid(ChunkRegistry::CHUNK_HEIGHT),
+            self.z.div_euclid(ChunkRegistry::CHUNK_SIZE),
+        )
+    }
+
+    #[inline]
+    fn to_world_origin(self) -> Coordinates {
+        ChunkRegistry::chunk_to_world(self.to_chunk_coords())
+    }
+
+    #[inline]
+    fn chunk_local_offset(self) -> UVec3 {
+        (self - self.to_world_origin()).as_uvec3()
+    }
+}
+
+impl ChunkCoordinates for Vec3 {
+    #[inline]
+    fn to_chunk_coords(self) -> Coordinates {
+        self.floor().as_ivec3().to_chunk_coords()
+    }
+
+    #[inline]
+    fn to_world_origin(self) -> Coordinates {
+        self.floor().as_ivec3().to_world_origin()
+    }
+
+    #[inline]
+    fn chunk_local_offset(self) -> UVec3 {
+        self.floor().as_ivec3().chunk_local_offset()
+    }
+}
+
 impl ChunkRegistry {
     pub const CHUNK_SIZE: i32 = 32;
     pub const CHUNK_HEIGHT: i32 = 32;
 
-    pub const CHUNK_GRID_SIZE: i32 = (i32::MAX / 48000) - 5;
-
     pub fn new() -> Self {
         Self {
-            chunks: HashMap::new(),
+            shards: std::array::from_fn(|_| RwLock::new(HashMap::new())),
+            vertical_range: RwLock::new(None),
         }
     }
 
-    pub fn get_adjacent_chunks(&self, Coordinates { x, y, z }: Coordinates) -> [Option<&Chunk>; 6] {
-        [
-            self.get_chunk_at(Coordinates::new(x + 1, y, z)),
-            self.get_chunk_at(Coordinates::new(x - 1, y, z)),
-            self.get_chunk_at(Coordinates::new(x, y, z + 1)),
-            self.get_chunk_at(Coordinates::new(x, y, z - 1)),
-            self.get_chunk_at(Coordinates::new(x, y + 1, z)),
-            self.get_chunk_at(Coordinates::new(x, y - 1, z)),
-        ]
+    #[inline]
+    fn shard(&self, chunk_id: ChunkId) -> &Shard {
+        // spread neighboring chunks over different shards, as these are the most likely to be
+        // accessed at the same time.
+        let hash = chunk_id.x.wrapping_mul(73856093)
+            ^ chunk_id.y.wrapping_mul(19349663)
+            ^ chunk_id.z.wrapping_mul(83492791);
+
+        &self.shards[hash.rem_euclid(SHARD_COUNT as i32) as usize]
     }
 
+    /// Gets the six chunks sharing a face with the chunk at the given world position.
+    pub fn get_adjacent_chunks(
+        &self,
+        coordinates: Coordinates,
+    ) -> [Option<MappedRwLockReadGuard<'_, Chunk>>; 6] {
+        Self::adjacent_coordinates(coordinates).map(|neighbor| self.get_chunk_at(neighbor))
+    }
+
+    /// The world positions of the six chunks sharing a face with the chunk at the given world
+    /// position, in the same order as `get_adjacent_chunks()`.
+    pub fn adjacent_coordinates(coordinates: Coordinates) -> [Coordinates; 6] {
+        let size = Coordinates::new(Self::CHUNK_SIZE, Self::CHUNK_HEIGHT, Self::CHUNK_SIZE);
+
+        VoxelFace::ALL.map(|face| coordinates + face.offset() * size)
+    }
+
+    /// The world positions of the 26 chunks around the chunk at the given world position, the
+    /// ones sharing an edge or a corner with it included; see `surface_nets::Neighborhood`.
+    pub fn surrounding_coordinates(coordinates: Coordinates) -> Vec<Coordinates> {
+        let size = Self::chunk_dimensions();
+
+        (-1..=1)
+            .flat_map(|z| (-1..=1).flat_map(move |y| (-1..=1).map(move |x| IVec3::new(x, y, z))))
+            .filter(|offset| *offset != IVec3::ZERO)
+            .map(|offset| coordinates + offset * size)
+            .collect()
+    }
+
+    /// Marks the chunk containing the voxel at the given world position as edited, along with the
+    /// chunks around it that the voxel is right next to; see `Chunk::mark_edited()`. This is what
+    /// any change to a single voxel should use, as it can hide (or expose) the faces of the voxels
+    /// around it. The chunks sharing only an edge or a corner with it are included, as a smooth
+    /// surface reaches across these as well (see `MeshTopology::Smooth`).
+    ///
+    /// Only chunks that exist are marked, the world positions of these are returned so the caller
+    /// knows which chunks have to be meshed again.
+    pub fn mark_edited_with_neighbors(&self, position: Coordinates) -> Vec<Coordinates> {
+        let coordinates = position.to_world_origin();
+
+        // every chunk is locked on its own, as the neighbors could share a shard with the chunk.
+        std::iter::once(coordinates)
+            .chain(Self::surrounding_coordinates(coordinates))
+            .filter(|chunk| {
+                let local = position - *chunk;
+
+                local.cmpge(IVec3::NEG_ONE).all() && local.cmple(Self::chunk_dimensions()).all()
+            })
+            .filter(|chunk| {
+                self.get_chunk_at_mut(*chunk)
+                    .map(|mut neighbor| neighbor.mark_edited(position - *chunk))
+                    .is_some()
+            })
+            .collect()
+    }
+
+    /// Gets a read guard to the chunk at the given coordinates; see the deadlock notes on
+    /// [`ChunkRegistry`].
     #[inline]
-    pub fn get_chunk_at(&self, coordinates: impl Into<Coordinates>) -> Option<&Chunk> {
+    pub fn get_chunk_at(
+        &self,
+        coordinates: impl Into<Coordinates>,
+    ) -> Option<MappedRwLockReadGuard<'_, Chunk>> {
         let coordinates = coordinates.into();
         let chunk_id = Self::domain_to_id(coordinates);
 
-        return self.chunks.get(&chunk_id);
+        // a recursive read, as a system could already be holding a read guard to another chunk
+        // within the same shard; a regular read could deadlock if a writer is waiting in between.
+        RwLockReadGuard::try_map(self.shard(chunk_id).read_recursive(), |chunks| {
+            chunks.get(&chunk_id)
+        })
+        .ok()
     }
 
+    /// Gets a write guard to the chunk at the given coordinates; see the deadlock notes on
+    /// [`ChunkRegistry`].
     #[inline]
-    pub fn get_chunk_at_mut(&mut self, coordinates: impl Into<Coordinates>) -> Option<&mut Chunk> {
+    pub fn get_chunk_at_mut(
+        &self,
+        coordinates: impl Into<Coordinates>,
+    ) -> Option<MappedRwLockWriteGuard<'_, Chunk>> {
         let coordinates = coordinates.into();
         let chunk_id = Self::domain_to_id(coordinates);
 
-        return self.chunks.get_mut(&chunk_id);
+        RwLockWriteGuard::try_map(self.shard(chunk_id).write(), |chunks| {
+            chunks.get_mut(&chunk_id)
+        })
+        .ok()
     }
 
-    pub fn push_chunk_at(&mut self, coordinates: impl Into<Coordinates>, chunk: Chunk) {
+    pub fn push_chunk_at(&self, coordinates: impl Into<Coordinates>, chunk: Chunk) {
         let coordinates = coordinates.into();
         let chunk_id = Self::domain_to_id(coordinates);
 
-        self.chunks.entry(chunk_id).or_insert(chunk);
+        {
+            let mut vertical_range = self.vertical_range.write();
+
+            *vertical_range = Some(match *vertical_range {
+                Some((min, max)) => (min.min(chunk_id.y), max.max(chunk_id.y)),
+                None => (chunk_id.y, chunk_id.y),
+            });
+        }
+
+        self.shard(chunk_id)
+            .write()
+            .entry(chunk_id)
+            .or_insert(chunk);
+    }
+
+    /// Gets the world y of the topmost solid voxel at the given world x and z.
+    ///
+    /// The column is scanned from the highest chunk in the registry downwards, which may span
+    /// multiple chunks. If any chunk above the topmost solid voxel isn't loaded or generated yet,
+    /// we can't know whether it contains anything solid, so `None` is returned; the same goes for
+    /// columns that don't contain any solid voxels at all.
+    pub fn highest_solid_voxel(&self, x: i32, z: i32) -> Option<i32> {
+        let (min, max) = (*self.vertical_range.read())?;
+
+        let column = Coordinates::new(x, 0, z);
+        let chunk = column.to_chunk_coords();
+        let UVec3 {
+            x: local_x,
+            z: local_z,
+            ..
+        } = column.chunk_local_offset();
+
+        for chunk_y in (min..=max).rev() {
+            let chunk = self.get_chunk_at(Self::chunk_to_world(ChunkId::new(
+                chunk.x, chunk_y, chunk.z,
+            )))?;
+
+            if !chunk.is_generated() {
+                return None;
+            }
+
+            if chunk.is_empty() {
+                continue;
+            }
+
+            for y in (0..chunk.get_dimensions().height).rev() {
+                if chunk
+                    .get_voxel([local_x, y, local_z])
+                    .is_some_and(|voxel| voxel.is_solid())
+                {
+                    return Some(chunk_y * Self::CHUNK_HEIGHT + y as i32);
+                }
+            }
+        }
+
+        None
     }
 
-    pub fn reserve_chunks(&mut self, chunks: usize) {
-        // is reserving needed on a HashMap? not sure
-        self.chunks.reserve(chunks);
+    /// Reserves room for at least the given amount of chunks to be added, so adding a lot of
+    /// chunks at once (such as after a discovery scan) doesn't have to grow the shards over and
+    /// over again.
+    ///
+    /// The chunks are spread evenly over the shards on average, but never exactly; a shard can
+    /// still grow if more than its share of the chunks ends up in it.
+    pub fn reserve_chunks(&self, chunks: usize) {
+        if chunks == 0 {
+            return;
+        }
+
+        for shard in &self.shards {
+            shard.write().reserve(chunks.div_ceil(SHARD_COUNT));
+        }
     }
 
-    pub fn get_all_chunks(
-        &mut self,
-    ) -> bevy::utils::hashbrown::hash_map::ValuesMut<'_, i32, Chunk> {
-        return self.chunks.values_mut();
+    /// The amount of chunks the registry can hold without growing any of its shards.
+    pub fn capacity(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.read().capacity())
+            .sum()
     }
 
-    #[inline]
-    pub fn domain_to_id(coordinates: impl Into<Coordinates>) -> i32 {
-        let IVec3 { x, y, z } = coordinates.into();
+    /// The amount of chunks within the registry.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.read().len()).sum()
+    }
 
-        let linear_x = x / Self::CHUNK_SIZE;
-        let linear_y = y / Self::CHUNK_SIZE;
-        let linear_z = z / Self::CHUNK_SIZE;
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
-        // Calculate the single index for the 3D coordinates
-        (linear_x * Self::CHUNK_GRID_SIZE * Self::CHUNK_GRID_SIZE)
-            + (linear_y * Self::CHUNK_GRID_SIZE)
-            + linear_z
+    /// Gets a read guard to every chunk overlapping the box between the given world positions,
+    /// both inclusive; see the deadlock notes on [`ChunkRegistry`].
+    ///
+    /// The chunks within the box are looked up one by one, which is far cheaper than scanning the
+    /// whole registry for small boxes. Boxes spanning more chunks than there are within the
+    /// registry scan the registry instead, so a huge box costs no more than visiting every chunk.
+    pub fn chunks_in_region(
+        &self,
+        min: Coordinates,
+        max: Coordinates,
+    ) -> impl Iterator<Item = MappedRwLockReadGuard<'_, Chunk>> + '_ {
+        let (min, max) = (min.to_chunk_coords(), max.to_chunk_coords());
+        let size = (max - min + IVec3::ONE).max(IVec3::ZERO).as_i64vec3();
+
+        let ids = if (size.x * size.y * size.z) as usize > self.len() {
+            self.shards
+                .iter()
+                .flat_map(|shard| {
+                    shard
+                        .read()
+                        .keys()
+                        .filter(|id| id.cmpge(min).all() && id.cmple(max).all())
+                        .copied()
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        } else {
+            (min.x..=max.x)
+                .flat_map(|x| {
+                    (min.y..=max.y)
+                        .flat_map(move |y| (min.z..=max.z).map(move |z| ChunkId::new(x, y, z)))
+                })
+                .collect()
+        };
+
+        ids.into_iter()
+            .filter_map(|id| self.get_chunk_at(Self::chunk_to_world(id)))
+    }
+
+    /// Calls the given function for every chunk within the registry. every shard is locked for
+    /// writing while its chunks are being visited, so the function must not access the registry.
+    pub fn for_each_chunk_mut(&self, mut function: impl FnMut(&mut Chunk)) {
+        for shard in &self.shards {
+            shard.write().values_mut().for_each(&mut function);
+        }
     }
 
     #[inline]
-    pub fn id_to_domain(id: i32) -> Coordinates {
-        let linear_x = id / (Self::CHUNK_GRID_SIZE * Self::CHUNK_GRID_SIZE);
-        let linear_y =
-            (id % (Self::CHUNK_GRID_SIZE * Self::CHUNK_GRID_SIZE)) / Self::CHUNK_GRID_SIZE;
-        let linear_z =
-            (id % (Self::CHUNK_GRID_SIZE * Self::CHUNK_GRID_SIZE)) % Self::CHUNK_GRID_SIZE;
+    pub fn domain_to_id(coordinates: impl Into<Coordinates>) -> ChunkId {
+        // this has to be a floor division, otherwise the chunks on both sides of the origin
+        // would end up with the same id.
+        coordinates.into().to_chunk_coords()
+    }
 
-        Coordinates::new(
-            linear_x * Self::CHUNK_SIZE,
-            linear_y * Self::CHUNK_SIZE,
-            linear_z * Self::CHUNK_SIZE,
-        )
+    #[inline]
+    pub fn id_to_domain(id: ChunkId) -> Coordinates {
+        Self::chunk_to_world(id)
     }
 
     #[inline]
     pub fn get_chunk_center(coordinates: impl Into<Coordinates>) -> Coordinates {
-        let chunk_id = Self::domain_to_id(coordinates);
-        let chunk_domain = Self::id_to_domain(chunk_id);
+        coordinates.into().to_world_origin() + Self::chunk_dimensions() / 2
+    }
+
+    /// Converts a position in chunks back into the world position of the origin of that chunk.
+    /// This is the inverse of [`ChunkCoordinates::to_chunk_coords`].
+    #[inline]
+    pub fn chunk_to_world(chunk: Coordinates) -> Coordinates {
+        chunk * Self::chunk_dimensions()
+    }
 
-        let center_x = chunk_domain.x + (Self::CHUNK_SIZE / 2);
-        let center_y = chunk_domain.y + (Self::CHUNK_SIZE / 2);
-        let center_z = chunk_domain.z + (Self::CHUNK_SIZE / 2);
+    /// Converts a world position into chunk space, without rounding it to a whole chunk. This is
+    /// mainly useful for measuring distances in chunks.
+    #[inline]
+    pub fn world_to_chunk_space(position: Vec3) -> Vec3 {
+        position / Self::chunk_dimensions().as_vec3()
+    }
 
-        Coordinates::new(center_x, center_y, center_z)
+    /// The dimensions of a single chunk, in voxels.
+    #[inline]
+    pub fn chunk_dimensions() -> IVec3 {
+        IVec3::new(Self::CHUNK_SIZE, Self::CHUNK_HEIGHT, Self::CHUNK_SIZE)
     }
 }
 
 #[cfg(test)]
 pub mod test {
+    use bevy::prelude::{Color, IVec3};
+    use half::f16;
+
     use super::ChunkRegistry;
-    use crate::chunk::registry::Coordinates;
+    use crate::chunk::{chunk::Chunk, registry::Coordinates, voxel::Voxel};
 
     #[test]
     fn test_domain() {
@@ -127,8 +409,210 @@ pub mod test {
         );
 
         assert_ne!(
-            ChunkRegistry::domain_to_id(Coordinates::new(17, 0, 15)),
+            ChunkRegistry::domain_to_id(Coordinates::new(ChunkRegistry::CHUNK_SIZE + 1, 0, 15)),
             ChunkRegistry::domain_to_id(Coordinates::new(15, 0, 15))
         );
     }
+
+    #[test]
+    fn test_domain_across_origin() {
+        for axis in [Coordinates::X, Coordinates::Y, Coordinates::Z] {
+            let extent = (axis * ChunkRegistry::chunk_dimensions()).max_element();
+
+            assert_ne!(
+                ChunkRegistry::domain_to_id(axis * -1),
+                ChunkRegistry::domain_to_id(Coordinates::ZERO)
+            );
+
+            assert_ne!(
+                ChunkRegistry::domain_to_id(axis * -15),
+                ChunkRegistry::domain_to_id(axis * 15)
+            );
+
+            // everything from -extent up to -1 belongs to the chunk right below the origin.
+            assert_eq!(
+                ChunkRegistry::domain_to_id(axis * -1),
+                ChunkRegistry::domain_to_id(axis * -extent)
+            );
+
+            assert_ne!(
+                ChunkRegistry::domain_to_id(axis * -extent),
+                ChunkRegistry::domain_to_id(axis * (-extent - 1))
+            );
+
+            // and everything from 0 up to extent - 1 to the chunk right above it.
+            assert_eq!(
+                ChunkRegistry::domain_to_id(Coordinates::ZERO),
+                ChunkRegistry::domain_to_id(axis * (extent - 1))
+            );
+        }
+    }
+
+    #[test]
+    fn test_id_round_trip() {
+        // sweep both the chunks around the origin, and some of the chunks very far out. the far
+        // out ones are as far as we can go without the world position overflowing an i32.
+        let limit = i32::MAX / ChunkRegistry::CHUNK_SIZE.max(ChunkRegistry::CHUNK_HEIGHT) - 1;
+        let offsets = (-4..=4).chain([-limit, -limit / 2, -1000, 1000, limit / 2, limit]);
+
+        for x in offsets.clone() {
+            for y in offsets.clone() {
+                for z in offsets.clone() {
+                    let origin = ChunkRegistry::chunk_to_world(Coordinates::new(x, y, z));
+                    let id = ChunkRegistry::domain_to_id(origin);
+
+                    assert_eq!(id, Coordinates::new(x, y, z));
+                    assert_eq!(ChunkRegistry::id_to_domain(id), origin);
+
+                    // every voxel within the chunk belongs to the same id
+                    assert_eq!(
+                        ChunkRegistry::domain_to_id(
+                            origin + ChunkRegistry::chunk_dimensions() - Coordinates::ONE
+                        ),
+                        id
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_highest_solid_voxel() {
+        let registry = ChunkRegistry::new();
+
+        let size = ChunkRegistry::CHUNK_SIZE as u32;
+        let height = ChunkRegistry::CHUNK_HEIGHT as u32;
+
+        // a chunk below the origin, with a single pillar reaching up to y -27.
+        let origin = Coordinates::new(0, -ChunkRegistry::CHUNK_HEIGHT, 0);
+        let mut chunk = Chunk::new(size, height, size, origin);
+
+        for y in 0..6 {
+            chunk.set_voxel([3, y, 7], Voxel::new_solid(Color::GRAY, f16::ONE));
+        }
+
+        chunk.set_generated(true);
+        registry.push_chunk_at(origin, chunk);
+
+        assert_eq!(registry.highest_solid_voxel(3, 7), Some(-27));
+        assert_eq!(registry.highest_solid_voxel(4, 7), None);
+
+        // the chunk above isn't generated yet, so it could still contain something solid.
+        registry.push_chunk_at(
+            Coordinates::ZERO,
+            Chunk::new(size, height, size, Coordinates::ZERO),
+        );
+
+        assert_eq!(registry.highest_solid_voxel(3, 7), None);
+
+        registry
+            .get_chunk_at_mut(Coordinates::ZERO)
+            .unwrap()
+            .set_generated(true);
+
+        assert_eq!(registry.highest_solid_voxel(3, 7), Some(-27));
+    }
+
+    #[test]
+    fn test_reserve_chunks() {
+        let registry = ChunkRegistry::new();
+        let size = ChunkRegistry::CHUNK_SIZE;
+
+        registry.reserve_chunks(1024);
+
+        let capacity = registry.capacity();
+        assert!(capacity >= 1024);
+
+        // far fewer chunks than were reserved, so none of the shards should have had to grow.
+        for x in 0..64 {
+            let position = Coordinates::new(x * size, 0, 0);
+            let chunk = Chunk::new(1, 1, 1, position);
+
+            registry.push_chunk_at(position, chunk);
+        }
+
+        assert_eq!(registry.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_mark_edited_with_neighbors() {
+        let registry = ChunkRegistry::new();
+        let (size, height) = (ChunkRegistry::CHUNK_SIZE, ChunkRegistry::CHUNK_HEIGHT);
+
+        let target = Coordinates::new(0, 0, 0);
+        let neighbor = Coordinates::new(0, height, 0);
+        // only shares an edge with the target.
+        let diagonal = Coordinates::new(size, height, 0);
+        // not adjacent to the target, so this one should be left alone.
+        let distant = Coordinates::new(size * 2, 0, 0);
+
+        for position in [target, neighbor, diagonal, distant] {
+            registry.push_chunk_at(position, Chunk::new(1, 1, 1, position));
+        }
+
+        // a voxel in the middle of the chunk doesn't touch any of its neighbors.
+        assert_eq!(
+            registry.mark_edited_with_neighbors(Coordinates::new(4, 4, 4)),
+            vec![target]
+        );
+        assert!(!registry.get_chunk_at(neighbor).unwrap().is_dirty());
+
+        // one on the top layer is right below the neighbor though.
+        let marked = registry.mark_edited_with_neighbors(Coordinates::new(4, height - 1, 4));
+
+        assert_eq!(marked, vec![target, neighbor]);
+        assert_eq!(
+            registry.get_chunk_at(neighbor).unwrap().edits,
+            vec![IVec3::new(4, -1, 4)]
+        );
+        assert_eq!(registry.get_chunk_at(target).unwrap().edits.len(), 2);
+        assert!(!registry.get_chunk_at(diagonal).unwrap().is_dirty());
+
+        // and one in the top corner touches the edge it shares with the diagonal chunk as well.
+        let marked = registry.mark_edited_with_neighbors(Coordinates::new(size - 1, height - 1, 4));
+
+        assert_eq!(marked, vec![target, neighbor, diagonal]);
+        assert_eq!(
+            registry.get_chunk_at(diagonal).unwrap().edits,
+            vec![IVec3::new(-1, -1, 4)]
+        );
+        assert!(!registry.get_chunk_at(distant).unwrap().is_dirty());
+    }
+
+    #[test]
+    fn test_chunks_in_region() {
+        let registry = ChunkRegistry::new();
+        let size = ChunkRegistry::CHUNK_SIZE;
+
+        for x in -4..4 {
+            let position = Coordinates::new(x * size, 0, 0);
+            registry.push_chunk_at(position, Chunk::new(1, 1, 1, position));
+        }
+
+        let region = |min: Coordinates, max: Coordinates| {
+            let mut positions = registry
+                .chunks_in_region(min, max)
+                .map(|chunk| chunk.world_position)
+                .collect::<Vec<_>>();
+
+            positions.sort_by_key(|position| position.x);
+            positions
+        };
+
+        // a box crossing the origin only touches the chunks it overlaps, even partially.
+        assert_eq!(
+            region(Coordinates::new(-1, 0, 0), Coordinates::new(size, 5, 5)),
+            vec![
+                Coordinates::new(-size, 0, 0),
+                Coordinates::new(0, 0, 0),
+                Coordinates::new(size, 0, 0),
+            ]
+        );
+
+        // a box far larger than the registry scans it instead, with the same result.
+        let huge = Coordinates::splat(size * 1000);
+        assert_eq!(region(-huge, huge).len(), 8);
+        // a box with its corners swapped doesn't overlap anything.
+        assert!(region(Coordinates::new(size, 0, 0), Coordinates::ZERO).is_empty());
+    }
 }
@@ -0,0 +1,381 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+use bevy::prelude::Color;
+use half::f16;
+
+use super::{
+    chunk::{Chunk, ChunkDimensions},
+    registry::{ChunkRegistry, Coordinates},
+    voxel::{BlockType, Voxel},
+};
+
+/// The bytes every saved chunk starts with, used to tell chunk files apart from anything else.
+pub const CHUNK_MAGIC: [u8; 4] = *b"VXCH";
+
+/// The current version of the on-disk chunk format. this has to be bumped whenever the layout of
+/// the format changes, alongside adding a migration for the previous version to [`load_chunk()`].
+pub const CHUNK_FORMAT_VERSION: u8 = 2;
+
+// the amount of bytes every voxel takes up: the block, solidity, emission, size and color.
+const VOXEL_BYTES: usize = 3 + 2 + 4 * 4;
+
+/// Everything that can go wrong while reading a chunk, or anything else that's saved alongside the
+/// chunks (such as the player, see `input::persistence`).
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    // the data doesn't start with the expected magic (such as `CHUNK_MAGIC`), so it's most likely
+    // something else entirely.
+    InvalidMagic([u8; 4]),
+    // the data was saved with a version we don't know how to read; most likely a newer one.
+    UnsupportedVersion(u8),
+    InvalidBlock(u8),
+    // the dimensions of a chunk are zero, or larger than a chunk can be; the header is most likely
+    // corrupt. these are checked before anything is allocated for the voxels.
+    InvalidDimensions([u32; 3]),
+    // the voxels don't match the checksum they were saved with, e.g. because the write got cut
+    // off halfway through.
+    ChecksumMismatch { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(error) => write!(f, "io error: {error}"),
+            StorageError::InvalidMagic(magic) => write!(f, "invalid magic: {magic:?}"),
+            StorageError::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version: {version}")
+            }
+            StorageError::InvalidBlock(id) => write!(f, "invalid block id: {id}"),
+            StorageError::InvalidDimensions([width, height, depth]) => {
+                write!(f, "invalid chunk dimensions: {width}x{height}x{depth}")
+            }
+            StorageError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch: expected {expected:#010x}, got {actual:#010x}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<io::Error> for StorageError {
+    fn from(error: io::Error) -> Self {
+        StorageError::Io(error)
+    }
+}
+
+/// Writes the voxels of a chunk using the current chunk format.
+///
+/// The format starts with [`CHUNK_MAGIC`] and [`CHUNK_FORMAT_VERSION`], followed by the
+/// dimensions of the chunk, a CRC32 checksum of the voxels and the voxels themselves. Everything
+/// else (such as the light levels and the mesh) can be derived from the voxels, so it's not saved.
+pub fn save_chunk(chunk: &Chunk, writer: &mut impl Write) -> Result<(), StorageError> {
+    let ChunkDimensions {
+        width,
+        height,
+        depth,
+    } = *chunk.get_dimensions();
+
+    writer.write_all(&CHUNK_MAGIC)?;
+    writer.write_all(&[CHUNK_FORMAT_VERSION])?;
+
+    for value in [width, height, depth] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+
+    // the voxels are written to a buffer first, as the checksum has to come before them.
+    let dense = chunk.get_voxels();
+    let mut voxels = Vec::with_capacity(dense.len() * VOXEL_BYTES);
+
+    for voxel in dense.iter() {
+        voxels.extend_from_slice(&[
+            block_to_id(voxel.block),
+            voxel.is_solid as u8,
+            voxel.emission,
+        ]);
+        voxels.extend_from_slice(&voxel.size.to_bits().to_le_bytes());
+
+        for channel in voxel.color.as_rgba_f32() {
+            voxels.extend_from_slice(&channel.to_le_bytes());
+        }
+    }
+
+    writer.write_all(&crc32(&voxels).to_le_bytes())?;
+    writer.write_all(&voxels)?;
+
+    Ok(())
+}
+
+/// Reads a chunk that was written by [`save_chunk()`].
+///
+/// Chunks saved with an older version of the format are migrated to the current version while
+/// they're being read; unknown versions are rejected rather than being misread.
+pub fn load_chunk(
+    reader: &mut impl Read,
+    world_position: Coordinates,
+) -> Result<Chunk, StorageError> {
+    let magic = read_array::<4>(reader)?;
+
+    if magic != CHUNK_MAGIC {
+        return Err(StorageError::InvalidMagic(magic));
+    }
+
+    let [version] = read_array::<1>(reader)?;
+
+    match version {
+        CHUNK_FORMAT_VERSION => read_v2(reader, world_position),
+        // version 1 is the exact same, except that it doesn't have a checksum.
+        1 => read_v1(reader, world_position),
+        // this is where migrations of older versions go, e.g. `0 => migrate_v0(reader, ..)`.
+        version => Err(StorageError::UnsupportedVersion(version)),
+    }
+}
+
+fn read_v2(reader: &mut impl Read, world_position: Coordinates) -> Result<Chunk, StorageError> {
+    let [width, height, depth] = read_dimensions(reader)?;
+    let volume = checked_volume([width, height, depth])?;
+    let expected = u32::from_le_bytes(read_array(reader)?);
+
+    let mut bytes = vec![0; volume * VOXEL_BYTES];
+    reader.read_exact(&mut bytes)?;
+
+    let actual = crc32(&bytes);
+
+    if actual != expected {
+        return Err(StorageError::ChecksumMismatch { expected, actual });
+    }
+
+    let voxels = read_voxels(&mut bytes.as_slice(), volume)?;
+
+    Ok(loaded_chunk(width, height, depth, world_position, voxels))
+}
+
+fn read_v1(reader: &mut impl Read, world_position: Coordinates) -> Result<Chunk, StorageError> {
+    let [width, height, depth] = read_dimensions(reader)?;
+    let volume = checked_volume([width, height, depth])?;
+    let voxels = read_voxels(reader, volume)?;
+
+    Ok(loaded_chunk(width, height, depth, world_position, voxels))
+}
+
+fn read_dimensions(reader: &mut impl Read) -> Result<[u32; 3], StorageError> {
+    let width = u32::from_le_bytes(read_array(reader)?);
+    let height = u32::from_le_bytes(read_array(reader)?);
+    let depth = u32::from_le_bytes(read_array(reader)?);
+
+    Ok([width, height, depth])
+}
+
+// the amount of voxels of a chunk with the given dimensions, which can't be any larger than the
+// chunks of the registry; a corrupt header would otherwise make us allocate (or overflow) before
+// the checksum ever gets checked.
+fn checked_volume(dimensions: [u32; 3]) -> Result<usize, StorageError> {
+    let max = ChunkRegistry::chunk_dimensions().as_uvec3().to_array();
+    let valid = dimensions
+        .iter()
+        .zip(max)
+        .all(|(dimension, max)| (1..=max).contains(dimension));
+
+    let [width, height, depth] = dimensions;
+
+    width
+        .checked_mul(height)
+        .and_then(|area| area.checked_mul(depth))
+        .filter(|_| valid)
+        .map(|volume| volume as usize)
+        .ok_or(StorageError::InvalidDimensions(dimensions))
+}
+
+fn read_voxels(reader: &mut impl Read, amount: usize) -> Result<Vec<Voxel>, StorageError> {
+    let mut voxels = Vec::with_capacity(amount);
+
+    for _ in 0..amount {
+        let [block, is_solid, emission] = read_array::<3>(reader)?;
+        let size = f16::from_bits(u16::from_le_bytes(read_array(reader)?));
+
+        let mut color = [0.0; 4];
+
+        for channel in &mut color {
+            *channel = f32::from_le_bytes(read_array(reader)?);
+        }
+
+        voxels.push(Voxel {
+            color: Color::rgba(color[0], color[1], color[2], color[3]),
+            is_solid: is_solid != 0,
+            size,
+            block: block_from_id(block)?,
+            emission,
+        });
+    }
+
+    Ok(voxels)
+}
+
+fn loaded_chunk(
+    width: u32,
+    height: u32,
+    depth: u32,
+    world_position: Coordinates,
+    voxels: Vec<Voxel>,
+) -> Chunk {
+    let mut chunk = Chunk::new(width, height, depth, world_position);
+
+    chunk.set_voxels(voxels);
+    chunk.set_generated(true);
+    chunk.set_dirty(true);
+    // only chunks that can't be generated again have to be saved in the first place.
+    chunk.set_modified(true);
+
+    chunk
+}
+
+pub(crate) fn read_array<const SIZE: usize>(
+    reader: &mut impl Read,
+) -> Result<[u8; SIZE], StorageError> {
+    let mut buffer = [0; SIZE];
+    reader.read_exact(&mut buffer)?;
+
+    Ok(buffer)
+}
+
+// the lookup table of the (IEEE) CRC32 checksum, generated at compile time.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut index = 0;
+
+    while index < 256 {
+        let mut value = index as u32;
+        let mut bit = 0;
+
+        while bit < 8 {
+            value = match value & 1 {
+                1 => (value >> 1) ^ 0xEDB8_8320,
+                _ => value >> 1,
+            };
+            bit += 1;
+        }
+
+        table[index] = value;
+        index += 1;
+    }
+
+    table
+};
+
+/// The CRC32 checksum of the given bytes, using the same polynomial as zlib and png.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, byte| {
+        CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+// the ids are part of the format; these must never change, only new ones may be added.
+fn block_to_id(block: BlockType) -> u8 {
+    match block {
+        BlockType::Air => 0,
+        BlockType::Stone => 1,
+        BlockType::Glowstone => 2,
+        BlockType::Lava => 3,
+        BlockType::Water => 4,
+    }
+}
+
+fn block_from_id(id: u8) -> Result<BlockType, StorageError> {
+    match id {
+        0 => Ok(BlockType::Air),
+        1 => Ok(BlockType::Stone),
+        2 => Ok(BlockType::Glowstone),
+        3 => Ok(BlockType::Lava),
+        4 => Ok(BlockType::Water),
+        id => Err(StorageError::InvalidBlock(id)),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::Color;
+    use half::f16;
+
+    use super::{crc32, load_chunk, save_chunk, StorageError, CHUNK_FORMAT_VERSION};
+    use crate::chunk::{
+        chunk::Chunk,
+        registry::Coordinates,
+        voxel::{BlockType, Voxel},
+    };
+
+    #[test]
+    fn test_round_trip() {
+        let position = Coordinates::new(16, -32, 48);
+        let mut chunk = Chunk::new(4, 4, 4, position);
+
+        chunk.set_voxel([1, 2, 3], Voxel::new_solid(Color::RED, f16::ONE));
+        chunk.set_voxel(
+            [0, 0, 0],
+            Voxel::new_block(BlockType::Water, Color::BLUE, f16::ONE),
+        );
+
+        let mut bytes = Vec::new();
+        save_chunk(&chunk, &mut bytes).unwrap();
+
+        assert_eq!(bytes[4], CHUNK_FORMAT_VERSION);
+
+        let loaded = load_chunk(&mut bytes.as_slice(), position).unwrap();
+
+        assert_eq!(loaded.get_voxels(), chunk.get_voxels());
+        assert_eq!(loaded.world_position, position);
+
+        // unknown versions have to be rejected, rather than being misread.
+        bytes[4] = CHUNK_FORMAT_VERSION + 1;
+
+        assert!(matches!(
+            load_chunk(&mut bytes.as_slice(), position),
+            Err(StorageError::UnsupportedVersion(_))
+        ));
+
+        // version 1 doesn't have a checksum, but its dimensions are checked all the same.
+        bytes[4] = 1;
+        bytes[9..13].copy_from_slice(&0u32.to_le_bytes());
+
+        assert!(matches!(
+            load_chunk(&mut bytes.as_slice(), position),
+            Err(StorageError::InvalidDimensions([4, 0, 4]))
+        ));
+    }
+
+    #[test]
+    fn test_checksum() {
+        // the well-known check value of CRC32.
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let position = Coordinates::new(0, 0, 0);
+        let mut chunk = Chunk::new(4, 4, 4, position);
+
+        chunk.set_voxel([2, 2, 2], Voxel::new_solid(Color::GREEN, f16::ONE));
+
+        let mut bytes = Vec::new();
+        save_chunk(&chunk, &mut bytes).unwrap();
+
+        // flipping a single bit of the last voxel is enough to reject the chunk.
+        *bytes.last_mut().unwrap() ^= 1;
+
+        assert!(matches!(
+            load_chunk(&mut bytes.as_slice(), position),
+            Err(StorageError::ChecksumMismatch { .. })
+        ));
+
+        // a corrupt header is rejected before anything gets allocated for the voxels, this one
+        // would overflow the amount of voxels.
+        *bytes.last_mut().unwrap() ^= 1;
+        bytes[5..9].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert!(matches!(
+            load_chunk(&mut bytes.as_slice(), position),
+            Err(StorageError::InvalidDimensions([u32::MAX, 4, 4]))
+        ));
+    }
+}
@@ -0,0 +1,457 @@
+use std::sync::Arc;
+
+use bevy::prelude::{IVec3, UVec3, Vec3};
+use half::f16;
+
+use super::{
+    chunk::{fallback_density, Chunk, ChunkDimensions, VoxelStorage},
+    mesh::max_lod,
+    registry::{ChunkRegistry, Coordinates},
+    voxel::Voxel,
+};
+
+/// The voxels and densities of a single chunk, shared with the chunk itself (through an Arc<T>)
+/// so these can be sampled off the main thread.
+#[derive(Clone, Debug)]
+pub struct DensitySource {
+    pub voxels: VoxelStorage,
+    // empty if the chunk doesn't keep the density of its voxels, see `Chunk::density_at()`.
+    pub density: Arc<Vec<f16>>,
+}
+
+impl DensitySource {
+    pub fn new(chunk: &Chunk) -> Self {
+        Self {
+            voxels: chunk.voxels.clone(),
+            density: chunk.density.clone(),
+        }
+    }
+}
+
+/// The chunk that's being meshed along with the 26 chunks around it; a smooth surface has to be
+/// built from the densities on both sides of a chunk boundary to line up with the surface of the
+/// neighbor, see [`surface_quads()`].
+///
+/// Every chunk is expected to have the same dimensions. The chunks that aren't there (or aren't
+/// generated yet) are treated as air.
+#[derive(Clone, Debug, Default)]
+pub struct Neighborhood {
+    // indexed by the offset of every chunk (in chunks), see `slot()`.
+    chunks: [Option<DensitySource>; 27],
+}
+
+impl Neighborhood {
+    /// Shares the voxels of every generated chunk around the chunk at the given world position.
+    /// The chunk itself is left out, see [`set()`](Self::set).
+    ///
+    /// Every chunk is locked on its own, so this can't be called while holding on to the chunk at
+    /// the given position (or any of the chunks around it).
+    pub fn gather(registry: &ChunkRegistry, coordinates: Coordinates) -> Self {
+        let size = ChunkRegistry::chunk_dimensions();
+        let mut neighborhood = Self::default();
+
+        for neighbor in ChunkRegistry::surrounding_coordinates(coordinates) {
+            let source = registry
+                .get_chunk_at(neighbor)
+                .filter(|chunk| chunk.is_generated())
+                .map(|chunk| DensitySource::new(&chunk));
+
+            neighborhood.set((neighbor - coordinates) / size, source);
+        }
+
+        neighborhood
+    }
+
+    /// Sets the chunk at the given offset (in chunks) from the chunk being meshed, which is at
+    /// `IVec3::ZERO`.
+    pub fn set(&mut self, offset: IVec3, source: Option<DensitySource>) {
+        self.chunks[slot(offset)] = source;
+    }
+
+    /// Whether every chunk around the chunk being meshed is there, see
+    /// `ChunkFlags::MissingNeighbors`.
+    pub fn is_complete(&self) -> bool {
+        self.chunks
+            .iter()
+            .enumerate()
+            .all(|(index, chunk)| index == slot(IVec3::ZERO) || chunk.is_some())
+    }
+
+    // the voxel at the given position relative to the chunk being meshed, along with its density.
+    fn sample(&self, position: IVec3, dimensions: &ChunkDimensions) -> (Voxel, f32) {
+        let size = UVec3::new(dimensions.width, dimensions.height, dimensions.depth).as_ivec3();
+        let offset = position.div_euclid(size);
+
+        let source = match offset.abs().max_element() {
+            0 | 1 => self.chunks[slot(offset)].as_ref(),
+            _ => None,
+        };
+
+        let Some(DensitySource { voxels, density }) = source else {
+            return (Voxel::default(), -1.0);
+        };
+
+        let index = dimensions.local_to_index(position.rem_euclid(size).as_uvec3());
+        let voxel = voxels
+            .get(index, dimensions.volume())
+            .copied()
+            .unwrap_or_default();
+
+        let density = match density.get(index) {
+            Some(density) => *density,
+            None => fallback_density(&voxel),
+        };
+
+        (voxel, density.to_f32())
+    }
+}
+
+fn slot(offset: IVec3) -> usize {
+    let IVec3 { x, y, z } = offset + IVec3::ONE;
+
+    (x + y * 3 + z * 9) as usize
+}
+
+/// A single quad of a smooth surface, see [`surface_quads()`].
+#[derive(Clone, Debug)]
+pub struct SurfaceQuad {
+    // wound counter-clockwise when looking at the front of the quad, like the faces of a voxel.
+    pub vertices: [Vec3; 4],
+    pub normals: [Vec3; 4],
+    // the solid voxel the quad is the surface of.
+    pub voxel: Voxel,
+    // the axis the quad roughly faces along, pointing away from the solid voxel.
+    pub direction: IVec3,
+    // the position of the voxel the quad is facing, relative to the chunk; this can be just
+    // outside of it.
+    pub facing: IVec3,
+}
+
+// the offset of every corner of a cell, indexed by the bits of the corner (x, y and z).
+fn corner(index: usize) -> IVec3 {
+    IVec3::new(
+        index as i32 & 1,
+        (index as i32 >> 1) & 1,
+        (index as i32 >> 2) & 1,
+    )
+}
+
+/// Builds a smooth surface through the densities of a chunk using surface nets, which is what
+/// `MeshTopology::Smooth` draws rather than the faces of the solid voxels.
+///
+/// The densities are sampled at the center of every voxel (of the given level of detail), making
+/// up a grid of cells between them. Every cell the surface passes through gets a single vertex,
+/// at the average of the points where the surface crosses the edges of that cell. Every edge the
+/// surface crosses is then covered by a quad, joining the vertices of the four cells around it.
+///
+/// A chunk only covers the edges starting within it, while the cells around those reach one voxel
+/// into its neighbors. The neighbors see the exact same densities on their side, so the surfaces
+/// of two chunks line up without any seams; as long as both are meshed at the same level of
+/// detail. The normals follow the gradient of the densities, rather than the quads themselves.
+pub fn surface_quads(
+    neighborhood: &Neighborhood,
+    dimensions: &ChunkDimensions,
+    lod: u32,
+) -> Vec<SurfaceQuad> {
+    let lod = lod.min(max_lod(dimensions));
+    let step = 1 << lod;
+
+    let size = UVec3::new(dimensions.width, dimensions.height, dimensions.depth).as_ivec3() >> lod;
+    // the samples reach one voxel past the chunk on every side, for the cells on its edges.
+    let padded = size + 2;
+
+    let sample_index = |position: IVec3| {
+        let IVec3 { x, y, z } = position + IVec3::ONE;
+
+        (x + y * padded.x + z * padded.x * padded.y) as usize
+    };
+
+    let mut samples = Vec::with_capacity((padded.x * padded.y * padded.z) as usize);
+
+    for z in -1..=size.z {
+        for y in -1..=size.y {
+            for x in -1..=size.x {
+                samples.push(neighborhood.sample(IVec3::new(x, y, z) * step, dimensions));
+            }
+        }
+    }
+
+    let is_inside = |density: f32| density > 0.0;
+
+    // a surface has to have both sides within the samples, anything else is either buried or
+    // entirely outside of the terrain.
+    let inside = samples
+        .iter()
+        .filter(|(_, density)| is_inside(*density))
+        .count();
+
+    if inside == 0 || inside == samples.len() {
+        return Vec::new();
+    }
+
+    let density = |position: IVec3| samples[sample_index(position)].1;
+
+    // the vertex of every cell the surface passes through, along with its normal. a cell sits
+    // between the samples at its position and the ones right after it.
+    let cells = size + 1;
+    let cell_index = |position: IVec3| {
+        let IVec3 { x, y, z } = position + IVec3::ONE;
+
+        (x + y * cells.x + z * cells.x * cells.y) as usize
+    };
+
+    let mut vertices = Vec::with_capacity((cells.x * cells.y * cells.z) as usize);
+
+    for z in -1..size.z {
+        for y in -1..size.y {
+            for x in -1..size.x {
+                let cell = IVec3::new(x, y, z);
+                let corners: [f32; 8] = std::array::from_fn(|index| density(cell + corner(index)));
+
+                let mut crossings = Vec3::ZERO;
+                let mut count = 0;
+
+                for (from, bit) in (0..8).flat_map(|from| [1, 2, 4].map(|bit| (from, bit))) {
+                    let to = from | bit;
+
+                    if from & bit != 0 || is_inside(corners[from]) == is_inside(corners[to]) {
+                        continue;
+                    }
+
+                    let t = corners[from] / (corners[from] - corners[to]);
+
+                    crossings += corner(from)
+                        .as_vec3()
+                        .lerp(corner(to).as_vec3(), t.clamp(0.0, 1.0));
+                    count += 1;
+                }
+
+                if count == 0 {
+                    vertices.push(None);
+                    continue;
+                }
+
+                // the densities grow towards the inside of the terrain, so the normal points the
+                // other way.
+                let gradient = (0..8).fold(Vec3::ZERO, |gradient, index| {
+                    gradient + (corner(index).as_vec3() * 2.0 - 1.0) * corners[index]
+                });
+
+                // the samples sit at the center of their voxels.
+                let position = (cell.as_vec3() + crossings / count as f32 + 0.5) * step as f32;
+
+                vertices.push(Some((position, -gradient.normalize_or_zero())));
+            }
+        }
+    }
+
+    let mut quads = Vec::new();
+
+    for z in 0..size.z {
+        for y in 0..size.y {
+            for x in 0..size.x {
+                let from = IVec3::new(x, y, z);
+
+                for axis in 0..3 {
+                    let [along, u, v] = [axis, (axis + 1) % 3, (axis + 2) % 3].map(|axis| {
+                        let mut offset = IVec3::ZERO;
+                        offset[axis] = 1;
+                        offset
+                    });
+
+                    let to = from + along;
+                    let solid = is_inside(density(from));
+
+                    if solid == is_inside(density(to)) {
+                        continue;
+                    }
+
+                    // the cells around the edge, counter-clockwise around the axis it's on; these
+                    // have to be the other way around if the quad faces along the negative axis.
+                    let mut around = [from, from - u, from - u - v, from - v];
+
+                    if !solid {
+                        around.reverse();
+                    }
+
+                    let Some(corners) = around
+                        .map(|cell| vertices[cell_index(cell)])
+                        .into_iter()
+                        .collect::<Option<Vec<_>>>()
+                    else {
+                        continue;
+                    };
+
+                    let (inner, outer, direction) = match solid {
+                        true => (from, to, along),
+                        false => (to, from, -along),
+                    };
+
+                    quads.push(SurfaceQuad {
+                        vertices: std::array::from_fn(|index| corners[index].0),
+                        // a flat spot between the samples doesn't have a gradient to go by.
+                        normals: std::array::from_fn(|index| match corners[index].1 {
+                            Vec3::ZERO => direction.as_vec3(),
+                            normal => normal,
+                        }),
+                        voxel: samples[sample_index(inner)].0,
+                        direction,
+                        facing: outer * step,
+                    });
+                }
+            }
+        }
+    }
+
+    quads
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::sync::Arc;
+
+    use bevy::prelude::{Color, IVec3, Vec3};
+    use half::f16;
+
+    use super::{surface_quads, DensitySource, Neighborhood, SurfaceQuad};
+    use crate::chunk::{
+        chunk::{ChunkDimensions, VoxelStorage},
+        voxel::Voxel,
+    };
+
+    #[test]
+    fn test_surface_quads() {
+        let dimensions = ChunkDimensions {
+            width: 4,
+            height: 4,
+            depth: 4,
+        };
+
+        // a flat floor, two voxels deep. the density grows by one every voxel further down, which
+        // puts the surface right in between the second and the third layer.
+        let solid = Voxel::new_solid(Color::GRAY, f16::ONE);
+        let source = |offset_y: i32| {
+            let mut voxels = Vec::new();
+            let mut density = Vec::new();
+
+            for index in 0..dimensions.volume() {
+                let y = (index / 4 % 4) as i32 + offset_y * 4;
+                let value = 1.5 - y as f32;
+
+                voxels.push(match value > 0.0 {
+                    true => solid,
+                    false => Voxel::default(),
+                });
+                density.push(f16::from_f32(value));
+            }
+
+            Some(DensitySource {
+                voxels: VoxelStorage::Dense(Arc::new(voxels)),
+                density: Arc::new(density),
+            })
+        };
+
+        let min_y = |quads: &[SurfaceQuad]| {
+            quads
+                .iter()
+                .flat_map(|quad| quad.vertices)
+                .map(|vertex| vertex.y)
+                .fold(f32::MAX, f32::min)
+        };
+
+        // the floor continues into every neighbor, so it stays flat right up to the edges.
+        let mut neighborhood = Neighborhood::default();
+
+        for z in -1..=1 {
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    neighborhood.set(IVec3::new(x, y, z), source(y));
+                }
+            }
+        }
+
+        assert!(neighborhood.is_complete());
+
+        let quads = surface_quads(&neighborhood, &dimensions, 0);
+
+        // one quad for every column of the chunk, all of them facing straight up.
+        assert_eq!(quads.len(), 16);
+        assert_eq!(min_y(&quads), 2.0);
+
+        for quad in &quads {
+            assert_eq!(quad.direction, IVec3::Y);
+            assert_eq!(quad.facing.y, 2);
+            assert!(quad.normals.iter().all(|normal| *normal == Vec3::Y));
+
+            let [a, b, c, _] = quad.vertices;
+
+            assert!((b - a).cross(c - a).normalize().dot(Vec3::Y) > 0.99);
+            assert!(quad.vertices.iter().all(|vertex| vertex.y == 2.0));
+        }
+
+        // without any neighbors, the chunk is surrounded by air. the sides of the floor on the
+        // positive axes are part of the chunk now, and the floor bends down towards its edges.
+        let mut alone = Neighborhood::default();
+        alone.set(IVec3::ZERO, source(0));
+
+        assert!(!alone.is_complete());
+
+        let quads = surface_quads(&alone, &dimensions, 0);
+
+        assert_eq!(quads.len(), 16 + 8 + 8);
+        assert!(min_y(&quads) < 2.0);
+
+        for direction in [IVec3::X, IVec3::Z] {
+            assert_eq!(
+                quads
+                    .iter()
+                    .filter(|quad| quad.direction == direction)
+                    .count(),
+                8
+            );
+        }
+    }
+
+    #[test]
+    fn test_surface_above_air() {
+        let dimensions = ChunkDimensions {
+            width: 4,
+            height: 4,
+            depth: 4,
+        };
+
+        let uniform = |voxel: Voxel| {
+            Some(DensitySource {
+                voxels: VoxelStorage::Uniform(voxel),
+                density: Arc::new(Vec::new()),
+            })
+        };
+
+        // a chunk of nothing but air, right below a layer of solid chunks.
+        let mut neighborhood = Neighborhood::default();
+
+        for z in -1..=1 {
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    let voxel = match y {
+                        1 => Voxel::new_solid(Color::GRAY, f16::ONE),
+                        _ => Voxel::default(),
+                    };
+
+                    neighborhood.set(IVec3::new(x, y, z), uniform(voxel));
+                }
+            }
+        }
+
+        let quads = surface_quads(&neighborhood, &dimensions, 0);
+
+        // the edges crossing into the solid chunks start within the air, so the air is what
+        // closes the surface between them; one quad for every column, right on the border.
+        assert_eq!(quads.len(), 16);
+
+        for quad in &quads {
+            assert_eq!(quad.direction, IVec3::NEG_Y);
+            assert!(quad.vertices.iter().all(|vertex| vertex.y == 4.0));
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::prelude::*;
+
+// the amount of samples that are kept per operation, older samples are dropped first.
+pub const TIMING_SAMPLES: usize = 128;
+
+/// A rolling window of the most recent durations of a single operation.
+#[derive(Default, Clone, Debug)]
+pub struct TimingHistory {
+    samples: VecDeque<Duration>,
+}
+
+impl TimingHistory {
+    pub fn record(&mut self, duration: Duration) {
+        if self.samples.len() >= TIMING_SAMPLES {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(duration);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.iter().min().copied()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.samples.iter().max().copied()
+    }
+
+    pub fn average(&self) -> Option<Duration> {
+        match self.samples.len() {
+            0 => None,
+            len => Some(self.samples.iter().sum::<Duration>() / len as u32),
+        }
+    }
+}
+
+/// How long the generation and meshing tasks of the most recent chunks took.
+///
+/// The tasks are only timed in debug builds, see `measure()`. In release builds, this resource
+/// stays empty.
+#[derive(Resource, Default, Clone, Debug)]
+pub struct ChunkTimings {
+    pub generation: TimingHistory,
+    pub meshing: TimingHistory,
+}
+
+/// Runs the given function, returning its result along with how long it took.
+///
+/// The duration is always `None` in release builds, these skip the instrumentation entirely.
+pub fn measure<T>(f: impl FnOnce() -> T) -> (T, Option<Duration>) {
+    #[cfg(debug_assertions)]
+    {
+        let start = bevy::utils::Instant::now();
+        let result = f();
+
+        (result, Some(start.elapsed()))
+    }
+
+    #[cfg(not(debug_assertions))]
+    {
+        (f(), None)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::time::Duration;
+
+    use super::{TimingHistory, TIMING_SAMPLES};
+
+    #[test]
+    fn test_rolling_window() {
+        let mut history = TimingHistory::default();
+
+        assert_eq!(history.average(), None);
+
+        // the first sample is pushed out by the last one.
+        for millis in 0..=TIMING_SAMPLES as u64 {
+            history.record(Duration::from_millis(millis));
+        }
+
+        assert_eq!(history.len(), TIMING_SAMPLES);
+        assert_eq!(history.min(), Some(Duration::from_millis(1)));
+        assert_eq!(
+            history.max(),
+            Some(Duration::from_millis(TIMING_SAMPLES as u64))
+        );
+        assert_eq!(
+            history.average(),
+            Some(Duration::from_micros(
+                (TIMING_SAMPLES as u64 + 1) * 1000 / 2
+            ))
+        );
+    }
+}
@@ -1,11 +1,100 @@
 use bevy::prelude::Color;
 use half::f16;
 
+/// The type of block a voxel represents.
+///
+/// The block type decides the behavior of a voxel which isn't related to its appearance, such as
+/// the amount of light it emits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BlockType {
+    #[default]
+    Air,
+    Stone,
+    Glowstone,
+    Lava,
+    Water,
+}
+
+impl BlockType {
+    pub const ALL: [BlockType; 5] = [
+        BlockType::Air,
+        BlockType::Stone,
+        BlockType::Glowstone,
+        BlockType::Lava,
+        BlockType::Water,
+    ];
+
+    /// The light level emitted by this block type, ranging from `0` to
+    /// [`MAX_LIGHT`](../lighting/constant.MAX_LIGHT.html).
+    pub fn emission(&self) -> u8 {
+        match self {
+            BlockType::Glowstone => 15,
+            BlockType::Lava => 12,
+            _ => 0,
+        }
+    }
+
+    /// Whether this block type is solid; solid blocks occlude the faces of their neighbors and
+    /// block light.
+    pub fn is_solid(&self) -> bool {
+        !matches!(self, BlockType::Air | BlockType::Water)
+    }
+
+    /// Whether this block type is rendered in the transparent pass.
+    pub fn is_transparent(&self) -> bool {
+        matches!(self, BlockType::Water)
+    }
+
+    /// The material this block type is rendered with.
+    pub fn material(&self) -> BlockMaterial {
+        match self {
+            BlockType::Water => BlockMaterial::Transparent,
+            BlockType::Glowstone | BlockType::Lava => BlockMaterial::Emissive,
+            _ => BlockMaterial::Opaque,
+        }
+    }
+}
+
+/// The materials the voxels of a chunk are rendered with; the voxels of every material end up in a
+/// separate mesh, see `mesh::ChunkMeshes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockMaterial {
+    Opaque,
+    // rendered in the transparent pass, such as water.
+    Transparent,
+    // not affected by the lighting of the scene, as these voxels are a light source themselves.
+    Emissive,
+}
+
+impl BlockMaterial {
+    pub const ALL: [BlockMaterial; 3] = [
+        BlockMaterial::Opaque,
+        BlockMaterial::Transparent,
+        BlockMaterial::Emissive,
+    ];
+
+    pub fn index(&self) -> usize {
+        match self {
+            BlockMaterial::Opaque => 0,
+            BlockMaterial::Transparent => 1,
+            BlockMaterial::Emissive => 2,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Voxel {
     pub color: Color,
     pub is_solid: bool,
+    // the size of the cube that's rendered, relative to the cell the voxel is in. this has to be
+    // within `(0.0, 1.0]`; smaller voxels sit in the minimum corner of their cell, rather than
+    // being centered in it. this only affects the mesh, everything else (such as lighting and the
+    // colliders) still treats the voxel as if it fills its entire cell.
     pub size: f16,
+    pub block: BlockType,
+    // the light level this voxel emits, this is used as a block light source when propagating the
+    // light within a chunk.
+    pub emission: u8,
 }
 
 pub struct VoxelMeshData {
@@ -19,14 +108,25 @@ impl Voxel {
             color,
             is_solid,
             size,
+            block: match is_solid {
+                true => BlockType::Stone,
+                false => BlockType::Air,
+            },
+            emission: 0,
         }
     }
 
     pub fn new_solid(color: Color, size: f16) -> Self {
+        Self::new(color, true, size)
+    }
+
+    pub fn new_block(block: BlockType, color: Color, size: f16) -> Self {
         Self {
             color,
-            is_solid: true,
+            is_solid: block.is_solid(),
             size,
+            block,
+            emission: block.emission(),
         }
     }
 
@@ -35,6 +135,28 @@ impl Voxel {
         return self.is_solid;
     }
 
+    /// Whether this voxel is rendered at all; this is the case for every voxel which isn't air.
+    #[inline]
+    pub fn is_visible(&self) -> bool {
+        return self.block != BlockType::Air;
+    }
+
+    #[inline]
+    pub fn is_transparent(&self) -> bool {
+        return self.block.is_transparent();
+    }
+
+    /// Whether this voxel fills its entire cell, see `Voxel::size`.
+    #[inline]
+    pub fn is_full_size(&self) -> bool {
+        return self.size >= f16::ONE;
+    }
+
+    #[inline]
+    pub fn is_emissive(&self) -> bool {
+        return self.emission > 0;
+    }
+
     pub fn mesh(&self, [x, y, z]: [f16; 3], size: f16) -> VoxelMeshData {
         let [x, y, z] = [x.to_f32(), y.to_f32(), z.to_f32()];
         let size = size.to_f32();
@@ -66,6 +188,8 @@ impl Default for Voxel {
             size: f16::from_f32(1.0),
             is_solid: false,
             color: Color::rgba(0.0, 0.0, 0.0, 0.0),
+            block: BlockType::Air,
+            emission: 0,
         }
     }
 }
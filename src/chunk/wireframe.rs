@@ -0,0 +1,47 @@
+use bevy::{pbr::wireframe::Wireframe, prelude::*};
+
+use crate::input::bindings::{InputAction, InputBindings};
+
+use super::{registry::ChunkRegistry, MeshSettings};
+
+/// Toggles `MeshSettings::wireframe` whenever `InputAction::ToggleWireframe` is pressed.
+pub fn toggle_wireframe(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut settings: ResMut<MeshSettings>,
+) {
+    if bindings.just_pressed(InputAction::ToggleWireframe, &keys) {
+        settings.wireframe = !settings.wireframe;
+    }
+}
+
+/// Adds (or removes) the `Wireframe` component to (or from) every chunk entity whenever
+/// `MeshSettings::wireframe` changes.
+///
+/// The wireframe is drawn on top of the existing materials, so these are never touched; disabling
+/// the wireframe again simply removes the component. Entities that get spawned while the wireframe
+/// is enabled receive the component in `draw_chunks`.
+pub fn apply_wireframe(
+    mut commands: Commands,
+    registry: Res<ChunkRegistry>,
+    settings: Res<MeshSettings>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    let mut entities = Vec::new();
+
+    registry.for_each_chunk_mut(|chunk| {
+        entities.extend(chunk.entity.into_iter().chain(chunk.submesh_entities()));
+    });
+
+    for entity in entities {
+        let mut entity = commands.entity(entity);
+
+        match settings.wireframe {
+            true => entity.insert(Wireframe),
+            false => entity.remove::<Wireframe>(),
+        };
+    }
+}
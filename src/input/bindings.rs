@@ -0,0 +1,222 @@
+use std::fmt;
+
+use bevy::{prelude::*, utils::HashMap};
+
+/// Everything that can be triggered through the keyboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InputAction {
+    MoveForward,
+    MoveBackward,
+    MoveRight,
+    MoveLeft,
+    MoveUp,
+    MoveDown,
+    Sprint,
+    ToggleCursor,
+    Undo,
+    Redo,
+    ToggleWireframe,
+    ToggleSimulation,
+    ToggleUnloading,
+    ToggleDiscovery,
+    InspectChunk,
+    CycleMovementLock,
+}
+
+impl InputAction {
+    pub const ALL: [InputAction; 16] = [
+        InputAction::MoveForward,
+        InputAction::MoveBackward,
+        InputAction::MoveRight,
+        InputAction::MoveLeft,
+        InputAction::MoveUp,
+        InputAction::MoveDown,
+        InputAction::Sprint,
+        InputAction::ToggleCursor,
+        InputAction::Undo,
+        InputAction::Redo,
+        InputAction::ToggleWireframe,
+        InputAction::ToggleSimulation,
+        InputAction::ToggleUnloading,
+        InputAction::ToggleDiscovery,
+        InputAction::InspectChunk,
+        InputAction::CycleMovementLock,
+    ];
+
+    fn default_binding(&self) -> Binding {
+        match self {
+            InputAction::MoveForward => Binding::key(KeyCode::W),
+            InputAction::MoveBackward => Binding::key(KeyCode::S),
+            InputAction::MoveRight => Binding::key(KeyCode::D),
+            InputAction::MoveLeft => Binding::key(KeyCode::A),
+            InputAction::MoveUp => Binding::key(KeyCode::Space),
+            InputAction::MoveDown => Binding::key(KeyCode::ShiftLeft),
+            InputAction::Sprint => Binding::key(KeyCode::ControlLeft),
+            InputAction::ToggleCursor => Binding::key(KeyCode::AltLeft),
+            InputAction::Undo => Binding::chord(KeyCode::ControlLeft, KeyCode::Z),
+            InputAction::Redo => Binding::chord(KeyCode::ControlLeft, KeyCode::Y),
+            InputAction::ToggleWireframe => Binding::key(KeyCode::X),
+            InputAction::ToggleSimulation => Binding::key(KeyCode::P),
+            InputAction::ToggleUnloading => Binding::key(KeyCode::M),
+            InputAction::ToggleDiscovery => Binding::key(KeyCode::L),
+            InputAction::InspectChunk => Binding::key(KeyCode::I),
+            InputAction::CycleMovementLock => Binding::key(KeyCode::K),
+        }
+    }
+}
+
+/// A key, optionally combined with a modifier that has to be held down along with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Binding {
+    pub modifier: Option<KeyCode>,
+    pub key: KeyCode,
+}
+
+impl Binding {
+    pub fn key(key: KeyCode) -> Self {
+        Self {
+            modifier: None,
+            key,
+        }
+    }
+
+    pub fn chord(modifier: KeyCode, key: KeyCode) -> Self {
+        Self {
+            modifier: Some(modifier),
+            key,
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.modifier {
+            Some(modifier) => write!(f, "{modifier:?} + {:?}", self.key),
+            None => write!(f, "{:?}", self.key),
+        }
+    }
+}
+
+/// The key every `InputAction` is bound to.
+///
+/// Actions are always resolved through this resource, rather than by checking the keys directly;
+/// this way every action can be rebound, and actions sharing a key don't trigger each other. A
+/// binding without a modifier doesn't trigger while the modifier of another binding on the same
+/// key is held down, so `Ctrl + Z` never counts as just `Z` as well. Holding a modifier on its
+/// own (such as sprinting with `Ctrl`) still works as usual.
+#[derive(Resource, Clone, Debug)]
+pub struct InputBindings {
+    bindings: HashMap<InputAction, Binding>,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        Self {
+            bindings: InputAction::ALL
+                .into_iter()
+                .map(|action| (action, action.default_binding()))
+                .collect(),
+        }
+    }
+}
+
+impl InputBindings {
+    pub fn get(&self, action: InputAction) -> Binding {
+        self.bindings
+            .get(&action)
+            .copied()
+            .unwrap_or_else(|| action.default_binding())
+    }
+
+    /// Binds an action to a different key.
+    ///
+    /// No two actions can share the exact same binding, so if another action was already bound to
+    /// it, that action takes over the previous binding of this action; the displaced action is
+    /// returned.
+    pub fn rebind(&mut self, action: InputAction, binding: Binding) -> Option<InputAction> {
+        let previous = self.get(action);
+
+        let displaced = InputAction::ALL
+            .into_iter()
+            .find(|other| *other != action && self.get(*other) == binding);
+
+        if let Some(other) = displaced {
+            self.bindings.insert(other, previous);
+        }
+
+        self.bindings.insert(action, binding);
+
+        displaced
+    }
+
+    pub fn pressed(&self, action: InputAction, keys: &Input<KeyCode>) -> bool {
+        let binding = self.get(action);
+
+        keys.pressed(binding.key) && self.is_active(binding, keys)
+    }
+
+    pub fn just_pressed(&self, action: InputAction, keys: &Input<KeyCode>) -> bool {
+        let binding = self.get(action);
+
+        keys.just_pressed(binding.key) && self.is_active(binding, keys)
+    }
+
+    // whether the modifiers of a binding allow it to trigger, see `InputBindings`.
+    fn is_active(&self, binding: Binding, keys: &Input<KeyCode>) -> bool {
+        match binding.modifier {
+            Some(modifier) => keys.pressed(modifier),
+            None => !self.bindings.values().any(|other| {
+                other.key == binding.key
+                    && other
+                        .modifier
+                        .is_some_and(|modifier| keys.pressed(modifier))
+            }),
+        }
+    }
+}
+
+/// A run condition which toggles between `true` and `false` every time the given action is
+/// pressed, starting out as `default`. This is `input_toggle_active`, but for an `InputAction`.
+pub fn action_toggle_active(
+    default: bool,
+    action: InputAction,
+) -> impl FnMut(Res<InputBindings>, Res<Input<KeyCode>>) -> bool + Clone {
+    let mut active = default;
+
+    move |bindings, keys| {
+        active ^= bindings.just_pressed(action, &keys);
+        active
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::{Input, KeyCode};
+
+    use super::{Binding, InputAction, InputBindings};
+
+    #[test]
+    fn test_bindings() {
+        let mut bindings = InputBindings::default();
+        let mut keys = Input::<KeyCode>::default();
+
+        // `Ctrl + Z` undoes, without also triggering whatever is bound to `Z` on its own.
+        bindings.rebind(InputAction::ToggleWireframe, Binding::key(KeyCode::Z));
+
+        keys.press(KeyCode::ControlLeft);
+        keys.press(KeyCode::Z);
+
+        assert!(bindings.just_pressed(InputAction::Undo, &keys));
+        assert!(bindings.pressed(InputAction::Sprint, &keys));
+        assert!(!bindings.just_pressed(InputAction::ToggleWireframe, &keys));
+
+        // binding an action to a key that's already taken swaps both bindings.
+        let displaced = bindings.rebind(InputAction::MoveUp, Binding::key(KeyCode::Z));
+
+        assert_eq!(displaced, Some(InputAction::ToggleWireframe));
+        assert_eq!(
+            bindings.get(InputAction::ToggleWireframe),
+            Binding::key(KeyCode::Space)
+        );
+    }
+}
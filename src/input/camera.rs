@@ -1,10 +1,96 @@
-use std::f32::consts::FRAC_PI_2;
+use std::f32::consts::{FRAC_PI_2, TAU};
 
 use bevy::prelude::*;
 use bevy::{input::mouse::MouseMotion, prelude::EventReader};
+use serde::{Deserialize, Serialize};
+
+use super::bindings::{InputAction, InputBindings};
 
 pub const DEFAULT_CAMERA_SENS: f32 = 0.005;
 
+// how far (in radians) the camera can look up or down. this stays just short of straight up or
+// down; at exactly a quarter turn the forward vector would point along the y axis, leaving the
+// horizontal movement directions to rounding errors.
+pub const MAX_PITCH: f32 = FRAC_PI_2 - 0.001;
+
+/// How quickly the camera turns and moves.
+#[derive(Resource, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+    // how far (in radians) the camera turns for every pixel the mouse moves.
+    pub sensitivity: f32,
+    // how far (in voxels) the camera moves every frame.
+    pub speed: f32,
+    // the speed is multiplied by this while `InputAction::Sprint` is held down.
+    pub sprint_multiplier: f32,
+    // this is only meant for the current session, so a settings file never locks the camera.
+    #[serde(skip)]
+    pub lock: MovementLock,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            sensitivity: DEFAULT_CAMERA_SENS,
+            speed: 0.05,
+            sprint_multiplier: 8.0,
+            lock: MovementLock::Free,
+        }
+    }
+}
+
+/// Restricts the directions the camera can move in, which makes it easier to survey the terrain.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MovementLock {
+    #[default]
+    Free,
+    // stays at the current altitude: moving up or down is ignored, and moving forward follows the
+    // yaw of the camera but not its pitch.
+    Horizontal,
+    // only moves up or down.
+    Vertical,
+}
+
+impl MovementLock {
+    pub const ALL: [MovementLock; 3] = [
+        MovementLock::Free,
+        MovementLock::Horizontal,
+        MovementLock::Vertical,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MovementLock::Free => "Free",
+            MovementLock::Horizontal => "Horizontal",
+            MovementLock::Vertical => "Vertical",
+        }
+    }
+
+    /// The lock that comes after this one within [`ALL`](Self::ALL), wrapping around.
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|lock| lock == self).unwrap_or(0);
+
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// Turns the movement input (x to the right, y up and z forward) into a direction in world
+    /// space, leaving out whatever the lock doesn't allow.
+    pub fn apply(&self, input: Vec3, forward: Vec3, right: Vec3) -> Vec3 {
+        match self {
+            MovementLock::Free => input.x * right + input.z * forward + input.y * Vec3::Y,
+            // the forward vector gets shorter the further the camera looks up or down, it's
+            // stretched back out so the speed doesn't depend on the pitch. the right vector is
+            // level already, other than for rounding errors which would add up over time.
+            MovementLock::Horizontal => {
+                let level = |vector: Vec3| Vec3::new(vector.x, 0.0, vector.z).normalize_or_zero();
+
+                input.x * level(right) + input.z * level(forward)
+            }
+            MovementLock::Vertical => input.y * Vec3::Y,
+        }
+    }
+}
+
 #[derive(Default, Component)]
 pub struct PlayerController {
     yaw: f32,
@@ -12,71 +98,169 @@ pub struct PlayerController {
     pub locked: bool,
 }
 
+impl PlayerController {
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
+    /// Points the camera in the given direction, the pitch is clamped to `MAX_PITCH` so the camera
+    /// can't flip over.
+    ///
+    /// The rotation is built from scratch out of both angles every time, rather than being
+    /// accumulated; the yaw turns around the world's y axis and the pitch around the camera's own
+    /// x axis, so the camera never rolls.
+    pub fn look(&mut self, yaw: f32, pitch: f32, transform: &mut Transform) {
+        // the yaw would otherwise keep growing while turning around, losing precision over time.
+        self.yaw = yaw.rem_euclid(TAU);
+        self.pitch = pitch.clamp(-MAX_PITCH, MAX_PITCH);
+
+        transform.rotation =
+            Quat::from_axis_angle(Vec3::Y, self.yaw) * Quat::from_axis_angle(-Vec3::X, self.pitch);
+    }
+}
+
 pub fn handle_mouse(
     mut query: Query<(&mut PlayerController, &mut Transform)>,
     mut reader: EventReader<MouseMotion>,
+    settings: Res<CameraSettings>,
 ) {
-    let (mut controller, mut transform) = query.single_mut();
-    let mut delta = Vec2::ZERO;
-
-    if controller.locked {
-        for mouse_move in reader.iter() {
-            delta += mouse_move.delta;
-        }
-    }
+    let Ok((mut controller, mut transform)) = query.get_single_mut() else {
+        return;
+    };
+    // the motion is read regardless of whether the cursor is grabbed, otherwise it would be
+    // applied all at once as soon as the cursor is grabbed again.
+    let delta = reader
+        .iter()
+        .fold(Vec2::ZERO, |delta, mouse_move| delta + mouse_move.delta);
 
-    if delta == Vec2::ZERO {
+    if !controller.locked || delta == Vec2::ZERO {
         return;
     }
 
-    let mut new_pitch = delta.y.mul_add(DEFAULT_CAMERA_SENS, controller.pitch);
-    let new_yaw = delta.x.mul_add(-DEFAULT_CAMERA_SENS, controller.yaw);
-
-    new_pitch = new_pitch.clamp(-FRAC_PI_2, FRAC_PI_2);
-
-    controller.yaw = new_yaw;
-    controller.pitch = new_pitch;
+    let new_pitch = delta.y.mul_add(settings.sensitivity, controller.pitch);
+    let new_yaw = delta.x.mul_add(-settings.sensitivity, controller.yaw);
 
-    transform.rotation =
-        Quat::from_axis_angle(Vec3::Y, new_yaw) * Quat::from_axis_angle(-Vec3::X, new_pitch);
+    controller.look(new_yaw, new_pitch, &mut transform);
 }
 
 pub fn handle_move(
     mut query: Query<&mut Transform, With<PlayerController>>,
     keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    settings: Res<CameraSettings>,
 ) {
-    let mut transform = query.single_mut();
+    let Ok(mut transform) = query.get_single_mut() else {
+        return;
+    };
     let mut direction = Vec3::ZERO;
 
     let forward = transform.forward();
     let right = transform.right();
 
-    let mut acceleration = 0.05f32;
+    let mut acceleration = settings.speed;
 
     {
         let movement_bindings = [
-            (KeyCode::W, Vec3::new(0.0, 0.0, 1.0)),
-            (KeyCode::S, Vec3::new(0.0, 0.0, -1.0)),
-            (KeyCode::D, Vec3::new(1.0, 0.0, 0.0)),
-            (KeyCode::A, Vec3::new(-1.0, 0.0, 0.0)),
-            (KeyCode::Space, Vec3::new(0.0, 1.0, 0.0)),
-            (KeyCode::ShiftLeft, Vec3::new(0.0, -1.0, 0.0)),
+            (InputAction::MoveForward, Vec3::new(0.0, 0.0, 1.0)),
+            (InputAction::MoveBackward, Vec3::new(0.0, 0.0, -1.0)),
+            (InputAction::MoveRight, Vec3::new(1.0, 0.0, 0.0)),
+            (InputAction::MoveLeft, Vec3::new(-1.0, 0.0, 0.0)),
+            (InputAction::MoveUp, Vec3::new(0.0, 1.0, 0.0)),
+            (InputAction::MoveDown, Vec3::new(0.0, -1.0, 0.0)),
         ];
 
-        for (keycode, dir) in movement_bindings.into_iter() {
-            if keys.pressed(keycode) {
+        for (action, dir) in movement_bindings.into_iter() {
+            if bindings.pressed(action, &keys) {
                 direction += dir;
             }
         }
     }
 
-    if keys.pressed(KeyCode::ControlLeft) {
-        acceleration *= 8.0;
+    if bindings.pressed(InputAction::Sprint, &keys) {
+        acceleration *= settings.sprint_multiplier;
     }
 
     if direction != Vec3::ZERO {
-        transform.translation += direction.x * right * acceleration
-            + direction.z * forward * acceleration
-            + direction.y * Vec3::Y * acceleration;
+        transform.translation += settings.lock.apply(direction, forward, right) * acceleration;
+    }
+}
+
+/// Switches to the next `MovementLock` whenever `InputAction::CycleMovementLock` is pressed.
+pub fn cycle_movement_lock(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut settings: ResMut<CameraSettings>,
+) {
+    if bindings.just_pressed(InputAction::CycleMovementLock, &keys) {
+        settings.lock = settings.lock.next();
+
+        info!("camera movement: {}", settings.lock.name());
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::{Transform, Vec3};
+
+    use super::{MovementLock, PlayerController, MAX_PITCH};
+
+    #[test]
+    fn test_no_roll() {
+        let mut controller = PlayerController::default();
+        let mut transform = Transform::default();
+
+        // a few full turns, as well as looking past straight up and down.
+        for yaw_step in -40..=40 {
+            for pitch_step in -20..=20 {
+                let (yaw, pitch) = (yaw_step as f32 * 0.3, pitch_step as f32 * 0.1);
+
+                controller.look(yaw, pitch, &mut transform);
+
+                // without any roll, the right vector stays level and the up vector never points
+                // down; the camera never ends up upside down.
+                assert!(transform.right().y.abs() < 1e-5, "{yaw}, {pitch}");
+                assert!(transform.up().y > 0.0, "{yaw}, {pitch}");
+
+                let expected = -pitch.clamp(-MAX_PITCH, MAX_PITCH).sin();
+
+                assert!(
+                    (transform.forward().y - expected).abs() < 1e-5,
+                    "{yaw}, {pitch}"
+                );
+                assert!((0.0..std::f32::consts::TAU).contains(&controller.yaw()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_movement_lock() {
+        let mut controller = PlayerController::default();
+        let mut transform = Transform::default();
+
+        // looking down at an angle, while moving forward, to the right and up all at once.
+        controller.look(1.0, 0.8, &mut transform);
+
+        let input = Vec3::ONE;
+        let (forward, right) = (transform.forward(), transform.right());
+
+        let free = MovementLock::Free.apply(input, forward, right);
+        let horizontal = MovementLock::Horizontal.apply(input, forward, right);
+        let vertical = MovementLock::Vertical.apply(input, forward, right);
+
+        assert!(free.y < 1.0);
+        assert_eq!(horizontal.y, 0.0);
+        assert_eq!(vertical, Vec3::Y);
+
+        // moving forward is just as fast at a constant altitude, in the same horizontal direction.
+        let flat = MovementLock::Horizontal.apply(Vec3::Z, forward, right);
+
+        assert!((flat.length() - 1.0).abs() < 1e-5);
+        assert!(flat.dot(Vec3::new(forward.x, 0.0, forward.z)) > 0.0);
+
+        assert_eq!(MovementLock::Vertical.next(), MovementLock::Free);
     }
 }
@@ -1,23 +1,110 @@
+use bevy::input::mouse::MouseMotion;
 use bevy::prelude::*;
-use bevy::window::CursorGrabMode;
+use bevy::window::{CursorGrabMode, PrimaryWindow, WindowFocused};
 
-use super::camera::PlayerController;
+use super::{
+    bindings::{InputAction, InputBindings},
+    camera::PlayerController,
+};
 
 pub fn grab_mouse(
-    mut windows: Query<&mut Window>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
     mut camera: Query<&mut PlayerController>,
     key: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut motion: ResMut<Events<MouseMotion>>,
 ) {
-    let mut window = windows.single_mut();
-    let mut controller = camera.single_mut();
+    let (Ok(mut window), Ok(mut controller)) = (windows.get_single_mut(), camera.get_single_mut())
+    else {
+        return;
+    };
 
-    if key.just_pressed(KeyCode::AltLeft) {
-        window.cursor.visible = controller.locked;
-        controller.locked = !controller.locked;
+    if bindings.just_pressed(InputAction::ToggleCursor, &key) {
+        let grabbed = !controller.locked;
 
-        window.cursor.grab_mode = match window.cursor.visible {
-            true => CursorGrabMode::None,
-            false => CursorGrabMode::Locked,
-        };
+        // whatever the mouse did while it was released shouldn't turn the camera once it's grabbed
+        // again.
+        if grabbed {
+            motion.clear();
+        }
+
+        set_grabbed(&mut window, &mut controller, grabbed);
+    }
+}
+
+/// Releases the cursor whenever the window loses focus (e.g. when alt-tabbing out of it), it's
+/// only grabbed again through `InputAction::ToggleCursor`.
+pub fn release_on_focus_loss(
+    mut reader: EventReader<WindowFocused>,
+    mut windows: Query<(Entity, &mut Window), With<PrimaryWindow>>,
+    mut camera: Query<&mut PlayerController>,
+) {
+    let (Ok((entity, mut window)), Ok(mut controller)) =
+        (windows.get_single_mut(), camera.get_single_mut())
+    else {
+        return;
+    };
+
+    // the window could have gotten its focus back within the same frame, the grab is gone either
+    // way by then.
+    let lost_focus = reader
+        .iter()
+        .any(|event| event.window == entity && !event.focused);
+
+    if lost_focus && controller.locked {
+        set_grabbed(&mut window, &mut controller, false);
+    }
+}
+
+fn set_grabbed(window: &mut Window, controller: &mut PlayerController, grabbed: bool) {
+    controller.locked = grabbed;
+
+    window.cursor.visible = !grabbed;
+    window.cursor.grab_mode = match grabbed {
+        true => CursorGrabMode::Locked,
+        false => CursorGrabMode::None,
+    };
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::{
+        prelude::*,
+        window::{CursorGrabMode, PrimaryWindow, WindowFocused},
+    };
+
+    use super::release_on_focus_loss;
+    use crate::input::camera::PlayerController;
+
+    #[test]
+    fn test_release_on_focus_loss() {
+        let mut app = App::new();
+
+        app.add_plugins(MinimalPlugins)
+            .add_event::<WindowFocused>()
+            .add_systems(Update, release_on_focus_loss);
+
+        let mut window = Window::default();
+        window.cursor.visible = false;
+        window.cursor.grab_mode = CursorGrabMode::Locked;
+
+        let window = app.world.spawn((window, PrimaryWindow)).id();
+        let mut controller = PlayerController::default();
+        controller.locked = true;
+
+        let camera = app.world.spawn(controller).id();
+
+        // alt-tabbing out and straight back in still releases the cursor.
+        for focused in [false, true] {
+            app.world.send_event(WindowFocused { window, focused });
+        }
+
+        app.update();
+
+        let window = app.world.get::<Window>(window).unwrap();
+
+        assert!(!app.world.get::<PlayerController>(camera).unwrap().locked);
+        assert!(window.cursor.visible);
+        assert_eq!(window.cursor.grab_mode, CursorGrabMode::None);
     }
 }
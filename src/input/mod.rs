@@ -1,18 +1,31 @@
 use bevy::prelude::*;
 
+pub mod bindings;
 pub mod camera;
 pub mod cursor;
+pub mod persistence;
 
 pub struct InputPlugin;
 impl Plugin for InputPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            Update,
-            (
-                cursor::grab_mouse,
-                camera::handle_mouse,
-                camera::handle_move,
-            ),
-        );
+        app.init_resource::<bindings::InputBindings>()
+            .init_resource::<camera::CameraSettings>()
+            .init_resource::<persistence::PlayerSaveFile>()
+            .add_systems(
+                Update,
+                (
+                    // the camera shouldn't turn in the same frame the cursor got released.
+                    (
+                        cursor::grab_mouse,
+                        cursor::release_on_focus_loss,
+                        camera::handle_mouse,
+                    )
+                        .chain(),
+                    (camera::cycle_movement_lock, camera::handle_move).chain(),
+                ),
+            )
+            // the camera is spawned during `Startup`, so it can only be restored afterwards.
+            .add_systems(PostStartup, persistence::restore_player)
+            .add_systems(Last, persistence::save_player_periodically);
     }
 }
@@ -0,0 +1,241 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::PathBuf,
+};
+
+use bevy::{app::AppExit, math::DVec3, prelude::*};
+
+use crate::chunk::{
+    origin::FloatingOrigin,
+    registry::ChunkCoordinates,
+    storage::{crc32, read_array, StorageError},
+};
+
+use super::camera::PlayerController;
+
+/// The bytes the player file starts with, see [`CHUNK_MAGIC`](crate::chunk::storage::CHUNK_MAGIC).
+pub const PLAYER_MAGIC: [u8; 4] = *b"VXPL";
+
+/// The current version of the player file format, this has to be bumped whenever its layout
+/// changes.
+pub const PLAYER_FORMAT_VERSION: u8 = 1;
+
+// how often (in seconds) the player is saved while playing, on top of saving it on exit. this way
+// a crash only loses the last bit of movement.
+const PLAYER_SAVE_INTERVAL: f64 = 30.0;
+
+// the position (as three f64s), followed by the yaw and pitch.
+const PLAYER_BYTES: usize = 3 * 8 + 2 * 4;
+
+/// Where the player is saved to, and restored from on startup.
+#[derive(Resource, Clone, Debug)]
+pub struct PlayerSaveFile(pub PathBuf);
+
+impl Default for PlayerSaveFile {
+    fn default() -> Self {
+        Self(PathBuf::from("saves/player.dat"))
+    }
+}
+
+/// Everything about the player that's kept between sessions.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlayerState {
+    // the position in world space, rather than relative to the render origin. these are f64s, so
+    // the position is restored exactly no matter how far away it is.
+    pub position: DVec3,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+/// Writes the player using the current player file format.
+///
+/// Just like the chunks, the file starts with [`PLAYER_MAGIC`] and [`PLAYER_FORMAT_VERSION`],
+/// followed by a CRC32 checksum of the rest of the data; a file that got cut off halfway through is
+/// rejected rather than misread.
+pub fn save_player(state: &PlayerState, writer: &mut impl Write) -> Result<(), StorageError> {
+    let mut bytes = Vec::with_capacity(PLAYER_BYTES);
+
+    for value in state.position.to_array() {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    bytes.extend_from_slice(&state.yaw.to_le_bytes());
+    bytes.extend_from_slice(&state.pitch.to_le_bytes());
+
+    writer.write_all(&PLAYER_MAGIC)?;
+    writer.write_all(&[PLAYER_FORMAT_VERSION])?;
+    writer.write_all(&crc32(&bytes).to_le_bytes())?;
+    writer.write_all(&bytes)?;
+
+    Ok(())
+}
+
+/// Reads a player that was written by [`save_player()`].
+pub fn load_player(reader: &mut impl Read) -> Result<PlayerState, StorageError> {
+    let magic = read_array::<4>(reader)?;
+
+    if magic != PLAYER_MAGIC {
+        return Err(StorageError::InvalidMagic(magic));
+    }
+
+    let [version] = read_array::<1>(reader)?;
+
+    if version != PLAYER_FORMAT_VERSION {
+        return Err(StorageError::UnsupportedVersion(version));
+    }
+
+    let expected = u32::from_le_bytes(read_array(reader)?);
+    let bytes = read_array::<PLAYER_BYTES>(reader)?;
+    let actual = crc32(&bytes);
+
+    if actual != expected {
+        return Err(StorageError::ChecksumMismatch { expected, actual });
+    }
+
+    let f64_at = |offset: usize| f64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+    let f32_at = |offset: usize| f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+
+    Ok(PlayerState {
+        position: DVec3::new(f64_at(0), f64_at(8), f64_at(16)),
+        yaw: f32_at(24),
+        pitch: f32_at(28),
+    })
+}
+
+/// Moves the camera to where the player was when it was last saved.
+///
+/// A missing file simply means there's nothing to restore yet; a file that can't be read is
+/// reported and ignored, so the camera starts at the origin either way.
+pub fn restore_player(
+    file: Res<PlayerSaveFile>,
+    mut origin: ResMut<FloatingOrigin>,
+    mut camera: Query<(&mut PlayerController, &mut Transform)>,
+) {
+    let Ok((mut controller, mut transform)) = camera.get_single_mut() else {
+        return;
+    };
+
+    let state = match File::open(&file.0).map_err(StorageError::from) {
+        Ok(mut reader) => load_player(&mut reader),
+        Err(StorageError::Io(error)) if error.kind() == io::ErrorKind::NotFound => return,
+        Err(error) => Err(error),
+    };
+
+    let state = match state {
+        Ok(state) if is_valid(&state) => state,
+        Ok(_) => {
+            warn!(
+                "ignoring the saved player at {}: not a number",
+                file.0.display()
+            );
+            return;
+        }
+        Err(error) => {
+            warn!("ignoring the saved player at {}: {error}", file.0.display());
+            return;
+        }
+    };
+
+    // nothing has been drawn yet, so the render origin can be moved right away; this way the
+    // camera starts out close to it, even if the player is far away from the world origin.
+    origin.offset = state.position.as_vec3().to_world_origin();
+    transform.translation = (state.position - origin.offset.as_dvec3()).as_vec3();
+
+    controller.look(state.yaw, state.pitch, &mut transform);
+
+    info!("restored the player at {}", state.position);
+}
+
+/// Saves the player every `PLAYER_SAVE_INTERVAL` seconds, as well as right before the app exits.
+pub fn save_player_periodically(
+    file: Res<PlayerSaveFile>,
+    origin: Res<FloatingOrigin>,
+    camera: Query<(&PlayerController, &Transform)>,
+    mut exit: EventReader<AppExit>,
+    mut last_save: Local<f64>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds_f64();
+    let exiting = exit.iter().count() > 0;
+
+    if !exiting && now - *last_save < PLAYER_SAVE_INTERVAL {
+        return;
+    }
+
+    *last_save = now;
+
+    let Ok((controller, transform)) = camera.get_single() else {
+        return;
+    };
+
+    let state = PlayerState {
+        position: origin.offset.as_dvec3() + transform.translation.as_dvec3(),
+        yaw: controller.yaw(),
+        pitch: controller.pitch(),
+    };
+
+    if let Err(error) = write_player_file(&file.0, &state) {
+        error!("failed to save the player to {}: {error}", file.0.display());
+    }
+}
+
+// the checksum only catches corrupted files, not a player that got saved in a broken state.
+fn is_valid(state: &PlayerState) -> bool {
+    state.position.is_finite() && state.yaw.is_finite() && state.pitch.is_finite()
+}
+
+// writes to a temporary file first, which then replaces the previous one; if the app dies halfway
+// through writing, the previous file is still intact.
+fn write_player_file(path: &PathBuf, state: &PlayerState) -> Result<(), StorageError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let temporary = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&temporary)?);
+
+    save_player(state, &mut writer)?;
+    writer.flush()?;
+    drop(writer);
+
+    fs::rename(temporary, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::math::DVec3;
+
+    use super::{load_player, save_player, PlayerState};
+    use crate::chunk::storage::StorageError;
+
+    #[test]
+    fn test_roundtrip() {
+        let state = PlayerState {
+            // far enough away that an f32 couldn't hold on to the fraction.
+            position: DVec3::new(123_456_789.25, -64.5, 0.125),
+            yaw: 1.5,
+            pitch: -0.25,
+        };
+
+        let mut bytes = Vec::new();
+        save_player(&state, &mut bytes).unwrap();
+
+        assert_eq!(load_player(&mut bytes.as_slice()).unwrap(), state);
+
+        // a flipped bit is caught by the checksum, a truncated file by running out of bytes.
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+
+        assert!(matches!(
+            load_player(&mut bytes.as_slice()),
+            Err(StorageError::ChecksumMismatch { .. })
+        ));
+        assert!(matches!(
+            load_player(&mut &bytes[..last]),
+            Err(StorageError::Io(_))
+        ));
+    }
+}
@@ -1,10 +1,16 @@
 use bevy::{
-    core_pipeline::fxaa::Fxaa,
+    core::TaskPoolThreadAssignmentPolicy,
     diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    pbr::wireframe::WireframePlugin,
     prelude::*,
-    window::PresentMode,
+    render::{
+        settings::{WgpuFeatures, WgpuSettings},
+        RenderPlugin,
+    },
 };
+use bevy_egui::EguiPlugin;
 use input::{camera::PlayerController, InputPlugin};
+use world::display::PresentMethod;
 
 pub mod chunk;
 pub mod input;
@@ -12,27 +18,78 @@ pub mod ui;
 pub mod util;
 pub mod world;
 
+/// The share of the cores that goes to the async compute pool, which is where chunks get
+/// discovered, generated and meshed. can be overridden with an exact amount of threads through the
+/// `VOXELS_COMPUTE_THREADS` environment variable.
+///
+/// Bevy only hands 25% of the cores (and at most 4 threads) to this pool by default, as most apps
+/// barely use it. we're the exact opposite, nearly all of our work happens in there. giving it too
+/// many threads does take them away from the compute pool, which runs the systems themselves and
+/// the rendering; if the frame times start to suffer while chunks are loading, this is too high.
+const ASYNC_COMPUTE_PERCENT: f32 = 0.5;
+
 fn main() {
     App::new()
         .add_plugins((
-            DefaultPlugins.set(WindowPlugin {
-                primary_window: Some(Window {
-                    title: String::from("bevy voxels"),
-                    present_mode: PresentMode::AutoNoVsync,
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: Some(Window {
+                        title: String::from("bevy voxels"),
+                        // this can be changed at runtime, see `DisplaySettings`.
+                        present_mode: PresentMethod::default().present_mode(),
+                        ..default()
+                    }),
                     ..default()
+                })
+                .set(TaskPoolPlugin {
+                    task_pool_options: task_pool_options(),
+                })
+                .set(RenderPlugin {
+                    // required to render wireframes, see `MeshSettings::wireframe`.
+                    wgpu_settings: WgpuSettings {
+                        features: WgpuFeatures::POLYGON_MODE_LINE,
+                        ..default()
+                    },
                 }),
-                ..default()
-            }),
-            chunk::ChunkPlugin,
+            WireframePlugin,
+            chunk::ChunkPlugin::new(),
             world::WorldPlugin,
             InputPlugin,
             FrameTimeDiagnosticsPlugin::default(),
+            // only needed for the loading progress for now, see `loading_progress_ui`.
+            EguiPlugin,
         ))
         .add_systems(Startup, setup)
-        .add_systems(Update, update_fps_text_sys)
+        .add_systems(
+            Update,
+            (update_fps_text_sys, ui::progress::loading_progress_ui),
+        )
         .run();
 }
 
+fn task_pool_options() -> TaskPoolOptions {
+    let async_compute = match std::env::var("VOXELS_COMPUTE_THREADS")
+        .ok()
+        .and_then(|threads| threads.parse::<usize>().ok())
+    {
+        Some(threads) => TaskPoolThreadAssignmentPolicy {
+            min_threads: threads,
+            max_threads: threads,
+            percent: 1.0,
+        },
+        None => TaskPoolThreadAssignmentPolicy {
+            min_threads: 1,
+            max_threads: usize::MAX,
+            percent: ASYNC_COMPUTE_PERCENT,
+        },
+    };
+
+    TaskPoolOptions {
+        async_compute,
+        ..default()
+    }
+}
+
 fn setup(mut commands: Commands) {
     commands.spawn((
         Camera3dBundle {
@@ -42,7 +99,7 @@ fn setup(mut commands: Commands) {
             }),
             ..Default::default()
         },
-        Fxaa::default(),
+        // the anti-aliasing is added by `apply_anti_aliasing`, see `AntiAliasingSettings`.
         PlayerController::default(),
     ));
 
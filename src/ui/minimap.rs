@@ -0,0 +1,116 @@
+use bevy::prelude::*;
+
+use bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+use egui::{Align2, Color32, Rect, Sense, Stroke, Vec2 as EguiVec2};
+
+use crate::{
+    chunk::{
+        chunk::{Chunk, ChunkFlags},
+        origin::FloatingOrigin,
+        registry::{ChunkCoordinates, ChunkRegistry, Coordinates},
+    },
+    input::camera::PlayerController,
+};
+
+// the amount of chunks shown in every direction around the camera. every chunk within this radius
+// gets looked up every frame, so this should stay rather small.
+const MINIMAP_RADIUS: i32 = 12;
+const CELL_SIZE: f32 = 6.0;
+
+/// Draws a top-down map of the chunks around the camera, in the top right corner of the screen.
+///
+/// Only the layer of chunks the camera is in is shown, every chunk is colored by its state (see
+/// [`chunk_color()`]). The camera itself is drawn in the center, with a line pointing in the
+/// direction it's facing.
+pub fn minimap_ui(
+    mut context: Query<&mut EguiContext, With<PrimaryWindow>>,
+    camera: Query<&Transform, With<PlayerController>>,
+    registry: Res<ChunkRegistry>,
+    origin: Res<FloatingOrigin>,
+) {
+    let (Ok(mut ctx), Ok(transform)) = (context.get_single_mut(), camera.get_single()) else {
+        return;
+    };
+
+    let center = origin.to_world_origin(transform.translation);
+    let cells = (MINIMAP_RADIUS * 2 + 1) as f32;
+
+    egui::Area::new("minimap")
+        .anchor(Align2::RIGHT_TOP, EguiVec2::new(-5.0, 5.0))
+        .show(ctx.get_mut(), |ui| {
+            let (response, painter) =
+                ui.allocate_painter(EguiVec2::splat(cells * CELL_SIZE), Sense::hover());
+            let origin = response.rect.min;
+
+            painter.rect_filled(response.rect, 0.0, Color32::from_black_alpha(150));
+
+            for x in -MINIMAP_RADIUS..=MINIMAP_RADIUS {
+                for z in -MINIMAP_RADIUS..=MINIMAP_RADIUS {
+                    let coordinates = center
+                        + Coordinates::new(
+                            x * ChunkRegistry::CHUNK_SIZE,
+                            0,
+                            z * ChunkRegistry::CHUNK_SIZE,
+                        );
+
+                    let Some(color) = registry.get_chunk_at(coordinates).map(|chunk| chunk_color(&chunk))
+                    else {
+                        continue;
+                    };
+
+                    let min = origin
+                        + EguiVec2::new(
+                            (x + MINIMAP_RADIUS) as f32,
+                            (z + MINIMAP_RADIUS) as f32,
+                        ) * CELL_SIZE;
+
+                    painter.rect_filled(
+                        Rect::from_min_size(min, EguiVec2::splat(CELL_SIZE - 1.0)),
+                        0.0,
+                        color,
+                    );
+                }
+            }
+
+            // the camera is somewhere within the center chunk, rather than always in its center.
+            let offset = transform.translation.chunk_local_offset().as_vec3()
+                / ChunkRegistry::CHUNK_SIZE as f32;
+            let player = origin
+                + EguiVec2::new(
+                    MINIMAP_RADIUS as f32 + offset.x,
+                    MINIMAP_RADIUS as f32 + offset.z,
+                ) * CELL_SIZE;
+
+            let forward = transform.forward();
+            let facing = EguiVec2::new(forward.x, forward.z).normalized() * CELL_SIZE * 2.0;
+
+            painter.circle_filled(player, CELL_SIZE / 2.0, Color32::WHITE);
+
+            // looking straight up or down doesn't have a direction on the map.
+            if !facing.any_nan() {
+                painter.line_segment([player, player + facing], Stroke::new(2.0, Color32::WHITE));
+            }
+
+            response.on_hover_text(
+                "Chunks around the camera.\nGray: created, blue: generated, purple: meshed, green: drawn, yellow: busy.",
+            );
+        });
+}
+
+/// Gets the color of a chunk on the minimap, based on how far along the chunk is.
+fn chunk_color(chunk: &Chunk) -> Color32 {
+    let flags = chunk.get_flags();
+
+    if flags.contains(ChunkFlags::Busy) {
+        Color32::YELLOW
+    } else if flags.contains(ChunkFlags::Drawn) {
+        Color32::from_rgb(60, 180, 75)
+    } else if flags.contains(ChunkFlags::Meshed) {
+        Color32::from_rgb(145, 80, 200)
+    } else if flags.contains(ChunkFlags::Generated) {
+        Color32::from_rgb(70, 110, 200)
+    } else {
+        Color32::GRAY
+    }
+}
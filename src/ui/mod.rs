@@ -1,22 +1,87 @@
-use bevy::prelude::*;
+use bevy::{
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+};
 
 use bevy_egui::EguiContext;
 use bevy_window::PrimaryWindow;
 use egui::{Color32, Slider};
 
-use crate::chunk::{registry::ChunkRegistry, DiscoverySettings, GenerationSettings, MeshSettings};
+use crate::{
+    chunk::{
+        block::{BlockAppearance, BlockRegistry},
+        events::{discovery::ChunkDiscoveryEvent, export::ChunkExportEvent},
+        generation::HeightBand,
+        noise_source::NoiseType,
+        origin::FloatingOrigin,
+        pool::VoxelBufferPool,
+        registry::{ChunkRegistry, Coordinates},
+        timings::ChunkTimings,
+        voxel::BlockType,
+        ChunkStats, DiscoverySettings, DiscoveryShape, GenerationSettings, MeshSettings,
+        MeshTopology, PerformanceSettings, RenderDistance, SimulationState, WorldBounds,
+    },
+    input::{
+        bindings::{InputAction, InputBindings},
+        camera::{CameraSettings, MovementLock, PlayerController},
+    },
+    world::{
+        antialiasing::{AntiAliasing, AntiAliasingBaseline, AntiAliasingSettings},
+        display::{DisplaySettings, PresentMethod, SurfacePresentModes},
+        fog::FogConfig,
+        settings::{SettingsEvent, SETTINGS_PATH},
+        sky::LightingSettings,
+    },
+};
 
+pub mod minimap;
+pub mod progress;
+
+/// The inspector, which edits most of the settings resources in place.
+///
+/// Every `ResMut` borrowed here is marked as changed on each frame the UI is shown, whether or not
+/// anything was actually edited; change detection alone can't tell these frames apart. The systems
+/// that apply these settings compare them against whatever they applied last instead.
 pub fn inspector_ui(
     mut commands: Commands,
     mut context: Query<&mut EguiContext, With<PrimaryWindow>>,
     mut meshing: ResMut<MeshSettings>,
     mut generation: ResMut<GenerationSettings>,
     mut discovery: ResMut<DiscoverySettings>,
-    directional_light_entities: Query<Entity, With<DirectionalLight>>,
-    pbr_entities: Query<Entity, With<Handle<StandardMaterial>>>,
-    mut chunk_registry: ResMut<ChunkRegistry>,
+    mut lighting: ResMut<LightingSettings>,
+    chunk_registry: Res<ChunkRegistry>,
+    mut discovery_writer: EventWriter<ChunkDiscoveryEvent>,
+    (stats, buffers, timings): (Res<ChunkStats>, Res<VoxelBufferPool>, Res<ChunkTimings>),
+    mut performance: ResMut<PerformanceSettings>,
+    (mut simulation, bindings): (ResMut<SimulationState>, Res<InputBindings>),
+    (mut export_writer, mut settings_writer): (
+        EventWriter<ChunkExportEvent>,
+        EventWriter<SettingsEvent>,
+    ),
+    (mut camera, origin, mut camera_settings): (
+        Query<&mut Transform, With<PlayerController>>,
+        Res<FloatingOrigin>,
+        ResMut<CameraSettings>,
+    ),
+    mut teleport_input: Local<String>,
+    (mut fog, mut clear_color, mut blocks): (
+        ResMut<FogConfig>,
+        ResMut<ClearColor>,
+        ResMut<BlockRegistry>,
+    ),
+    (mut anti_aliasing, baseline): (
+        ResMut<AntiAliasingSettings>,
+        Option<Res<AntiAliasingBaseline>>,
+    ),
+    (diagnostics, mut display, present_modes): (
+        Res<DiagnosticsStore>,
+        ResMut<DisplaySettings>,
+        Res<SurfacePresentModes>,
+    ),
 ) {
-    let mut ctx = context.single_mut();
+    let Ok(mut ctx) = context.get_single_mut() else {
+        return;
+    };
     ctx.get_mut().set_visuals(egui::Visuals {
         panel_fill: Color32::from_rgba_unmultiplied(0, 0, 0, 150),
         ..egui::Visuals::default()
@@ -28,8 +93,82 @@ pub fn inspector_ui(
             egui::SidePanel::left("chunk-settings").show_inside(ui, |ui| {
                 ui.heading("Chunk Settings");
                 ui.checkbox(&mut meshing.occlusion_culling, "Occlusion Culling");
+                ui.checkbox(
+                    &mut meshing.wireframe,
+                    format!("Wireframe ({})", bindings.get(InputAction::ToggleWireframe)),
+                );
+                ui.checkbox(&mut meshing.double_sided, "Double Sided")
+                    .on_hover_text("Renders the back of every face as well. \nFaces that only show up with this enabled are wound the wrong way.");
+                let topology = meshing.topology;
+
+                egui::ComboBox::from_label("Topology")
+                    .selected_text(meshing.topology.name())
+                    .show_ui(ui, |ui| {
+                        for topology in MeshTopology::ALL {
+                            ui.selectable_value(&mut meshing.topology, topology, topology.name());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Points draws a single point for every voxel, without any culling or lighting. \nSmooth draws a smooth surface through the terrain rather than cubes.");
+
+                let palette = meshing.palette;
+
+                ui.checkbox(&mut meshing.palette, "Palette Colors")
+                    .on_hover_text("Stores an index into a palette for every vertex, rather than a full color. \nSaves memory on large worlds, but every chunk needs a material of its own.");
+
+                // every chunk has to be meshed again using the new topology (or colors).
+                if meshing.topology != topology || meshing.palette != palette {
+                    chunk_registry.for_each_chunk_mut(|chunk| chunk.set_dirty(true));
+                    discovery_writer.send(ChunkDiscoveryEvent);
+                }
+
+                ui.checkbox(&mut meshing.colliders, "Colliders")
+                    .on_hover_text("Builds collision boxes for every chunk when it gets meshed. \nOnly applies to chunks that get (re)meshed after enabling this.");
                 ui.checkbox(&mut discovery.lod, "Level of Detail")
-                    .on_hover_text("Level of Detail is not recommended to be used. \nThere's a high chance it will break any kind of culling due to inproper coordinate calculations.");
+                    .on_hover_text("Meshes distant chunks at a lower resolution. \nFaces between chunks are not culled yet, so this mostly helps with dense chunks.");
+
+                ui.add(Slider::new(&mut discovery.lod_bias, 0.5..=10.0).text("LOD Bias"))
+                    .on_hover_text("Higher values lower the level of detail closer to the camera.");
+                ui.add(Slider::new(&mut meshing.lod_fade, 0.0..=2.0).text("LOD Fade (s)"))
+                    .on_hover_text("Fades out the previous mesh of a chunk when its level of detail changes. \nThe mesh is swapped right away at 0.");
+                ui.add(Slider::new(&mut meshing.pop_in, 0.0..=2.0).text("Pop In (s)"))
+                    .on_hover_text("Grows newly drawn chunks out of the ground. \nChunks appear right away at 0, which is useful for benchmarking.");
+                ui.checkbox(&mut discovery.frustum_cull, "Frustum Culling")
+                    .on_hover_text("Skips rendering the loaded chunks that are out of view. \nChunks within the discovery radius are always kept loaded, the ones in view are loaded first.");
+                ui.checkbox(&mut discovery.occlusion_cull, "Chunk Occlusion Culling")
+                    .on_hover_text("Hides the chunks that are behind entirely solid chunks. \nThis is a coarse test, which takes some time every frame.");
+                ui.label(format!(
+                    "Chunks visible: {}, occluded: {}, view culled: {}",
+                    stats.kept, stats.occluded, stats.view_culled
+                ));
+                ui.label(format!(
+                    "Voxel buffers allocated: {}, reused: {}",
+                    buffers.allocated, buffers.reused
+                ));
+
+                // the tasks are only timed in debug builds.
+                if cfg!(debug_assertions) {
+                    for (name, history) in [("Generation", &timings.generation), ("Meshing", &timings.meshing)] {
+                        let (Some(min), Some(average), Some(max)) = (history.min(), history.average(), history.max()) else {
+                            continue;
+                        };
+
+                        ui.label(format!(
+                            "{name} (last {}): min {:.2?}, avg {:.2?}, max {:.2?}",
+                            history.len(), min, average, max
+                        ));
+                    }
+                }
+
+                ui.horizontal(|ui| {
+                    for preset in RenderDistance::ALL {
+                        if ui.button(preset.name()).clicked() {
+                            preset.apply(&mut discovery);
+                        }
+                    }
+                })
+                .response
+                .on_hover_text("Render distance presets, these set the discovery radius and level of detail together.");
 
                 ui.add(
                     Slider::new(&mut discovery.discovery_radius, 1..=40).text("Discovery Radius"),
@@ -40,18 +179,95 @@ pub fn inspector_ui(
                         .text("Discovery Height Radius"),
                 );
 
+                egui::ComboBox::from_label("Discovery Shape")
+                    .selected_text(discovery.shape.name())
+                    .show_ui(ui, |ui| {
+                        for shape in DiscoveryShape::ALL {
+                            ui.selectable_value(&mut discovery.shape, shape, shape.name());
+                        }
+                    });
+
+                let mut finite = discovery.bounds.is_some();
+
+                if ui.checkbox(&mut finite, "Finite World").changed() {
+                    // starts out as the area around the camera that's currently loaded.
+                    let center = camera
+                        .get_single()
+                        .map(|transform| origin.to_chunk_coords(transform.translation))
+                        .unwrap_or_default();
+                    let radius = Coordinates::new(
+                        discovery.discovery_radius as i32,
+                        discovery.discovery_radius_height as i32,
+                        discovery.discovery_radius as i32,
+                    );
+
+                    discovery.bounds = finite.then_some(WorldBounds {
+                        min: center - radius,
+                        max: center + radius,
+                    });
+                }
+
+                if let Some(bounds) = discovery.bounds.as_mut() {
+                    for (label, corner) in [("Min", &mut bounds.min), ("Max", &mut bounds.max)] {
+                        ui.horizontal(|ui| {
+                            ui.label(label);
+                            ui.add(egui::DragValue::new(&mut corner.x).prefix("x: "));
+                            ui.add(egui::DragValue::new(&mut corner.y).prefix("y: "));
+                            ui.add(egui::DragValue::new(&mut corner.z).prefix("z: "));
+                        });
+                    }
+                }
+
+                let (label, toggled) = match *simulation {
+                    SimulationState::Running => ("Pause Simulation", SimulationState::Paused),
+                    SimulationState::Paused => ("Resume Simulation", SimulationState::Running),
+                };
+
+                let label = format!(
+                    "{label} ({})",
+                    bindings.get(InputAction::ToggleSimulation)
+                );
+
+                if ui.button(label).clicked() {
+                    *simulation = toggled;
+                }
+
                 if ui.button("Rebuild Chunks").clicked() {
                     // loop over all of the chunks to mark them as dirty
-                    chunk_registry
-                        .get_all_chunks()
-                        .into_iter()
-                        .for_each(|chunk| chunk.set_dirty(true));
+                    chunk_registry.for_each_chunk_mut(|chunk| chunk.set_dirty(true));
+
+                    discovery_writer.send(ChunkDiscoveryEvent);
                 }
 
-                if ui.button("Remove PBR Entities").clicked() {
-                    pbr_entities.into_iter().for_each(|entity| {
-                        commands.entity(entity).despawn();
-                    })
+                if ui.button("Export Chunks (world.obj)").clicked() {
+                    export_writer.send(ChunkExportEvent {
+                        path: "world.obj".into(),
+                    });
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.button("Save Settings").on_hover_text(SETTINGS_PATH).clicked() {
+                        settings_writer.send(SettingsEvent::Save(SETTINGS_PATH.into()));
+                    }
+
+                    if ui.button("Load Settings").on_hover_text(SETTINGS_PATH).clicked() {
+                        settings_writer.send(SettingsEvent::Load(SETTINGS_PATH.into()));
+                    }
+                });
+
+                if ui.button("Respawn Chunk Entities")
+                    .on_hover_text("Despawns the entities of every chunk, these get spawned again right away. \nThe meshes are kept, so nothing has to be meshed again.")
+                    .clicked()
+                {
+                    let mut entities = Vec::new();
+
+                    chunk_registry.for_each_chunk_mut(|chunk| entities.extend(chunk.clear_entity()));
+
+                    for entity in entities {
+                        commands.entity(entity).despawn_recursive();
+                    }
+
+                    discovery_writer.send(ChunkDiscoveryEvent);
                 }
             });
 
@@ -70,18 +286,250 @@ pub fn inspector_ui(
                 ui.add(Slider::new(&mut generation.threshold, 0.0..=40.0).text("Threshold"));
                 ui.add(Slider::new(&mut generation.octaves, 0..=40).text("Octaves"));
                 ui.add(Slider::new(&mut generation.persistence, 0.0..=40.0).text("Persistence"));
+                egui::ComboBox::from_label("Noise")
+                    .selected_text(generation.noise.name())
+                    .show_ui(ui, |ui| {
+                        for noise in NoiseType::ALL {
+                            ui.selectable_value(&mut generation.noise, noise, noise.name());
+                        }
+                    });
+                ui.checkbox(&mut generation.interpolated_noise, "Interpolated Noise")
+                    .on_hover_text("Samples the noise every few voxels and interpolates in between. \nMuch faster to generate, but smooths out the finer details.");
+                ui.add_enabled(
+                    generation.interpolated_noise,
+                    Slider::new(&mut generation.lattice_resolution, 1..=16).text("Lattice Resolution"),
+                );
+
+                ui.add(Slider::new(&mut generation.sea_level, -64.0..=64.0).text("Sea Level"));
+                ui.add(
+                    Slider::new(&mut generation.surface_level, -128.0..=128.0)
+                        .text("Surface Level"),
+                );
+                ui.add(
+                    Slider::new(&mut generation.height_falloff, 1.0..=64.0).text("Height Falloff"),
+                );
+                ui.collapsing("Height Bands", |ui| {
+                    let mut removed = None;
+
+                    for (index, band) in generation.height_bands.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            let mut color = band.color.as_rgba_f32();
+
+                            if ui.color_edit_button_rgba_unmultiplied(&mut color).changed() {
+                                band.color = Color::rgba(color[0], color[1], color[2], color[3]);
+                            }
+
+                            ui.add(egui::DragValue::new(&mut band.max_height).prefix("below y: "));
+
+                            if ui.small_button("x").clicked() {
+                                removed = Some(index);
+                            }
+                        });
+                    }
+
+                    if let Some(index) = removed {
+                        generation.height_bands.remove(index);
+                    }
+
+                    if ui.button("Add Band").clicked() {
+                        let max_height = generation
+                            .height_bands
+                            .iter()
+                            .map(|band| band.max_height)
+                            .filter(|height| *height < f64::MAX)
+                            .fold(0.0, f64::max)
+                            + 10.0;
+
+                        generation.height_bands.push(HeightBand {
+                            max_height,
+                            color: Color::WHITE,
+                        });
+                    }
+                })
+                .header_response
+                .on_hover_text("The colors of the terrain by height; the first band a voxel falls within is used.");
+
+                ui.add(
+                    Slider::new(&mut performance.generation_budget, 1 << 12..=1 << 28)
+                        .logarithmic(true)
+                        .text("Generation Budget"),
+                )
+                .on_hover_text("How much work the chunks that are generated at the same time may add up to. \nA chunk costs a voxel operation for every voxel it samples, for every octave.");
+                ui.add(Slider::new(&mut performance.target_fps, 15.0..=240.0).text("Target FPS"))
+                    .on_hover_text("Fewer chunks are processed per frame while the frame rate is below this.");
+            });
+
+            egui::SidePanel::left("teleport").show_inside(ui, |ui| {
+                ui.heading("Teleport");
+
+                egui::ComboBox::from_label(format!(
+                    "Movement ({})",
+                    bindings.get(InputAction::CycleMovementLock)
+                ))
+                .selected_text(camera_settings.lock.name())
+                .show_ui(ui, |ui| {
+                    for lock in MovementLock::ALL {
+                        ui.selectable_value(&mut camera_settings.lock, lock, lock.name());
+                    }
+                })
+                .response
+                .on_hover_text("Horizontal keeps the camera at its current altitude, Vertical only moves it up and down.");
+
+                let Ok(mut transform) = camera.get_single_mut() else {
+                    return;
+                };
+
+                // the camera is positioned relative to the render origin, we're showing (and
+                // teleporting to) world coordinates.
+                let Vec3 { x, y, z } = origin.to_world(transform.translation);
+
+                ui.label(format!("Position: {x:.1}, {y:.1}, {z:.1}"));
+                ui.text_edit_singleline(&mut *teleport_input)
+                    .on_hover_text("World coordinates to teleport to, e.g. \"100 40 -250\".");
+
+                let teleport = ui.button("Teleport").clicked();
+                let reset = ui.button("Reset to Origin").clicked();
+
+                let target = match (teleport, reset) {
+                    (true, _) => parse_coordinates(&teleport_input),
+                    (_, true) => Some(Vec3::ZERO),
+                    _ => None,
+                };
+
+                if let Some(target) = target {
+                    // the origin gets moved along by itself once we're far enough away from it.
+                    transform.translation = target - origin.offset.as_vec3();
+
+                    // discovery would pick up the new position by itself, but there's no reason to
+                    // wait for it. the chunks around the old position get unloaded by distance.
+                    discovery_writer.send(ChunkDiscoveryEvent);
+                }
             });
 
             egui::SidePanel::left("visual-settings").show_inside(ui, |ui| {
                 ui.heading("Visual Settings");
 
-                if ui.button("Disable Directional Light").clicked() {
-                    for entity in &directional_light_entities {
-                        commands.entity(entity).despawn();
-                    }
+                ui.checkbox(&mut fog.enabled, "Fog")
+                    .on_hover_text("Fades out the chunks towards the edge of the discovery radius.");
+                ui.add(Slider::new(&mut fog.density, 0.0..=1.0).text("Fog Density"));
+
+                color_edit(ui, "Background Color", &mut clear_color.0);
+
+                ui.checkbox(&mut fog.match_background, "Match Background")
+                    .on_hover_text("Gives the fog the background color, so the horizon blends into it.");
+
+                if !fog.match_background {
+                    color_edit(ui, "Fog Color", &mut fog.color);
                 }
+
+                ui.collapsing("Block Colors", |ui| {
+                    // air is never rendered, so there's nothing to color.
+                    for block in BlockType::ALL.into_iter().skip(1) {
+                        let mut color = blocks.get(block).faces[0].as_rgba_f32();
+
+                        ui.horizontal(|ui| {
+                            // changing the registry remeshes every chunk, so this only happens
+                            // when the color actually changes.
+                            if ui.color_edit_button_rgba_unmultiplied(&mut color).changed() {
+                                let [r, g, b, a] = color;
+
+                                blocks.set(block, BlockAppearance::uniform(Color::rgba(r, g, b, a)));
+                            }
+
+                            ui.label(format!("{block:?}"));
+
+                            // the block type is drawn in the colors it was generated with again.
+                            if blocks.contains(block) && ui.button("Reset").clicked() {
+                                blocks.reset(block);
+                            }
+                        });
+                    }
+                });
+
+                ui.add(
+                    Slider::new(&mut lighting.ambient_brightness, 0.0..=2.0)
+                        .text("Ambient Brightness"),
+                );
+                color_edit(ui, "Ambient Color", &mut lighting.ambient_color);
+
+                ui.checkbox(&mut lighting.directional_enabled, "Directional Light");
+                ui.add(
+                    Slider::new(&mut lighting.directional_illuminance, 0.0..=150_000.0)
+                        .text("Directional Illuminance"),
+                );
+                color_edit(ui, "Directional Color", &mut lighting.directional_color);
+
+                egui::ComboBox::from_label("Anti-Aliasing")
+                    .selected_text(anti_aliasing.mode.name())
+                    .show_ui(ui, |ui| {
+                        for mode in AntiAliasing::ALL {
+                            ui.selectable_value(&mut anti_aliasing.mode, mode, mode.name());
+                        }
+                    })
+                    .response
+                    .on_hover_text("MSAA 2x and 8x aren't supported by every GPU. \nTAA can leave trails behind water while moving.");
+
+                egui::ComboBox::from_label("Present Mode")
+                    .selected_text(display.present_method.name())
+                    .show_ui(ui, |ui| {
+                        for method in PresentMethod::ALL
+                            .into_iter()
+                            .filter(|method| method.is_supported(&present_modes))
+                        {
+                            ui.selectable_value(&mut display.present_method, method, method.name());
+                        }
+                    })
+                    .response
+                    .on_hover_text("VSync caps the frame rate to the refresh rate of the display. \nAdaptive VSync doesn't wait for the next refresh when a frame is late, if the GPU supports it. \nMailbox and Immediate are only listed when the display supports them.");
+                ui.add(Slider::new(&mut display.fps_cap, 0..=360).text("FPS Cap"))
+                    .on_hover_text("The most frames per second, on top of the present mode. \nThe frame rate isn't limited at all at 0.");
+
+                let frame_time = diagnostics
+                    .get(FrameTimeDiagnosticsPlugin::FRAME_TIME)
+                    .and_then(|diagnostic| diagnostic.average());
+
+                // the frame time is averaged over the last couple of frames, so the difference
+                // takes a moment to settle after switching.
+                match (frame_time, baseline) {
+                    (Some(frame_time), Some(baseline)) => ui.label(format!(
+                        "Frame time: {frame_time:.2} ms ({:+.2} ms compared to {})",
+                        frame_time - baseline.frame_time,
+                        baseline.mode.name()
+                    )),
+                    (Some(frame_time), None) => ui.label(format!("Frame time: {frame_time:.2} ms")),
+                    _ => ui.label("Frame time: -"),
+                };
             });
 
             ui.allocate_space(ui.available_size());
         });
 }
+
+/// A labeled color picker, which only touches the color once it's actually changed.
+fn color_edit(ui: &mut egui::Ui, label: &str, color: &mut Color) {
+    let mut rgba = color.as_rgba_f32();
+
+    ui.horizontal(|ui| {
+        ui.label(label);
+
+        if ui.color_edit_button_rgba_unmultiplied(&mut rgba).changed() {
+            let [r, g, b, a] = rgba;
+
+            *color = Color::rgba(r, g, b, a);
+        }
+    });
+}
+
+/// Parses world coordinates in the form of `x y z`, the values may also be separated by commas.
+fn parse_coordinates(input: &str) -> Option<Vec3> {
+    let values = input
+        .split(|character: char| character == ',' || character.is_whitespace())
+        .filter(|value| !value.is_empty())
+        .map(|value| value.parse::<f32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    match values[..] {
+        [x, y, z] => Some(Vec3::new(x, y, z)),
+        _ => None,
+    }
+}
@@ -0,0 +1,50 @@
+use bevy::prelude::*;
+
+use bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+use egui::{Align2, ProgressBar, Vec2 as EguiVec2};
+
+use crate::chunk::ChunkStats;
+
+/// Shows how far along the initial load is, at the top of the screen.
+///
+/// The chunks within the discovery area are counted during every discovery scan, see
+/// `ChunkStats::settled`. Once every one of them is done loading the bar is gone for good; chunks
+/// that load in afterwards (such as when moving around) load in without it.
+pub fn loading_progress_ui(
+    mut context: Query<&mut EguiContext, With<PrimaryWindow>>,
+    stats: Res<ChunkStats>,
+    mut finished: Local<bool>,
+) {
+    if *finished {
+        return;
+    }
+
+    // nothing has been discovered until the first scan is done.
+    if stats.discovered > 0 && stats.settled >= stats.discovered {
+        *finished = true;
+        return;
+    }
+
+    let Ok(mut ctx) = context.get_single_mut() else {
+        return;
+    };
+
+    let progress = match stats.discovered {
+        0 => 0.0,
+        discovered => stats.settled as f32 / discovered as f32,
+    };
+
+    egui::Area::new("loading-progress")
+        .anchor(Align2::CENTER_TOP, EguiVec2::new(0.0, 5.0))
+        .show(ctx.get_mut(), |ui| {
+            ui.add(
+                ProgressBar::new(progress)
+                    .desired_width(300.0)
+                    .text(format!(
+                        "Loading chunks: {} / {}",
+                        stats.settled, stats.discovered
+                    )),
+            );
+        });
+}
@@ -0,0 +1,125 @@
+use std::io::{self, Write};
+
+use bevy::{
+    prelude::{Mesh, Vec3},
+    render::mesh::VertexAttributeValues,
+};
+
+/// Writes the given meshes into a single Wavefront OBJ file.
+///
+/// Every mesh is offset by the translation it's paired with, this is the world position of the
+/// chunk the mesh belongs to. Vertex colors are written as part of the vertex positions
+/// (`v x y z r g b`), which isn't part of the official OBJ specification but is supported by most
+/// tools, including Blender.
+///
+/// Meshes without positions are skipped. Meshes without indices are treated as a plain list of
+/// triangles, which is what our chunk meshes end up as after `Mesh::duplicate_vertices()`.
+///
+/// Returns the amount of meshes that were written.
+pub fn write_obj<'a>(
+    writer: &mut impl Write,
+    meshes: impl IntoIterator<Item = (&'a Mesh, Vec3)>,
+) -> io::Result<usize> {
+    // obj indices are global across the entire file (and start at 1), so the indices of every
+    // mesh have to be offset by the amount of vertices that were written before it.
+    let mut vertex_offset = 1;
+    let mut written = 0;
+
+    for (mesh, offset) in meshes {
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            continue;
+        };
+
+        let colors = match mesh.attribute(Mesh::ATTRIBUTE_COLOR) {
+            Some(VertexAttributeValues::Float32x4(colors)) => Some(colors),
+            _ => None,
+        };
+
+        let normals = match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+            Some(VertexAttributeValues::Float32x3(normals)) => Some(normals),
+            _ => None,
+        };
+
+        for (index, position) in positions.iter().enumerate() {
+            let Vec3 { x, y, z } = Vec3::from(*position) + offset;
+
+            match colors.and_then(|colors| colors.get(index)) {
+                Some([r, g, b, _]) => writeln!(writer, "v {x} {y} {z} {r} {g} {b}")?,
+                None => writeln!(writer, "v {x} {y} {z}")?,
+            }
+        }
+
+        if let Some(normals) = normals {
+            for [x, y, z] in normals {
+                writeln!(writer, "vn {x} {y} {z}")?;
+            }
+        }
+
+        let indices: Vec<usize> = match mesh.indices() {
+            Some(indices) => indices.iter().collect(),
+            None => (0..positions.len()).collect(),
+        };
+
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [
+                triangle[0] + vertex_offset,
+                triangle[1] + vertex_offset,
+                triangle[2] + vertex_offset,
+            ];
+
+            match normals {
+                Some(_) => writeln!(writer, "f {a}//{a} {b}//{b} {c}//{c}")?,
+                None => writeln!(writer, "f {a} {b} {c}")?,
+            }
+        }
+
+        vertex_offset += positions.len();
+        written += 1;
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::{
+        prelude::{Mesh, Vec3},
+        render::render_resource::PrimitiveTopology,
+    };
+
+    use super::write_obj;
+
+    fn triangle() -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+        );
+
+        mesh
+    }
+
+    #[test]
+    fn test_indices_are_offset() {
+        let (first, second) = (triangle(), triangle());
+        let mut output = Vec::new();
+
+        let written = write_obj(
+            &mut output,
+            [(&first, Vec3::ZERO), (&second, Vec3::new(32.0, 0.0, 0.0))],
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let faces: Vec<_> = output
+            .lines()
+            .filter(|line| line.starts_with("f "))
+            .collect();
+
+        assert_eq!(written, 2);
+        assert_eq!(faces, vec!["f 1 2 3", "f 4 5 6"]);
+        assert!(output.contains("v 33 0 0"));
+    }
+}
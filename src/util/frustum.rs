@@ -1,4 +1,7 @@
-use bevy::{math::Vec3A, prelude::IVec3, render::primitives::HalfSpace};
+use bevy::{
+    math::{BVec3A, Vec3A},
+    render::primitives::HalfSpace,
+};
 
 use crate::chunk::registry::Coordinates;
 
@@ -156,148 +159,61 @@ pub fn is_in_frustum_batch<const SIZE: usize>(
     results
 }
 
-/// Determines if a batch of points is inside a frustum defined by six half-spaces.
+/// Determines if any of the eight corners of a box is inside a frustum defined by six half-spaces.
 ///
-/// This function performs frustum culling on a batch of 3D points using a set of six
-/// half-space definitions that together define a frustum. The frustum is typically used
-/// in graphics and game development to determine if objects are visible within a specific
-/// camera's view volume. This function automatically uses get_frustum_point_amount() to infer the
-/// amount of points that will be used to determine if the object is within the frustum; thus
-/// unsized.
+/// The corners are computed and tested one at a time, so nothing is allocated, and the test stops
+/// at the first corner that's inside. Boxes that are larger than the frustum can still contain
+/// (part of) it without any of their corners being inside, so this is only meant for boxes that
+/// are small compared to the view, such as chunks.
 ///
 /// # Parameters
 ///
-/// - `points`: An iterator over items that can be converted into `Vec3A` (3D vectors).
-///    These are the points to be tested against the frustum.
-/// - `spaces`: An array of six `HalfSpace` instances representing the half-spaces that
-///    define the frustum. Each `HalfSpace` includes a normal vector and a distance from
-///    the origin. The normal vector points inward to the frustum.
-///
-/// # Returns
-///
-/// An array of boolean values, where each element indicates whether the corresponding
-/// point is inside the frustum (`true`) or outside the frustum (`false`).
-///
-/// # Examples
-///
-/// Iterating
-/// ```
-/// let points = vec![
-///     Vec3A::new(1.0, 2.0, -3.0),
-///     Vec3A::new(0.0, 0.0, -1.0),
-///     // ... more points ...
-/// ];
-///
-/// let frustum_planes = [
-///     HalfSpace::new(/* normal */, /* distance */),
-///     HalfSpace::new(/* normal */, /* distance */),
-///     // ... more half-spaces ...
-/// ];
-///
-/// let visibility = is_in_frustum_batch_unsized(points, frustum_planes);
-///
-/// for (index, is_visible) in visibility.iter().enumerate() {
-///     if *is_visible {
-///         println!("Point {index} is visible.");
-///     } else {
-///         println!("Point {index} is not visible.");
-///     }
-/// }
-/// ```
-///
-/// Mapping
-/// ```
-/// let points = vec![
-///     Vec3A::new(1.0, 2.0, -3.0),
-///     Vec3A::new(0.0, 0.0, -1.0),
-///     // ... more points ...
-/// ];
-///
-/// let frustum_planes = [
-///     HalfSpace::new(/* normal */, /* distance */),
-///     HalfSpace::new(/* normal */, /* distance */),
-///     // ... more half-spaces ...
-/// ];
-///
-/// let visibility = is_in_frustum_batch_unsized(points, frustum_planes);
-///
-/// let is_any_visible = visibility.iter()
-///     .filter(|visible| **visible)
-///     .next()
-///     .is_some();
-///
-/// println("{is_any_visible}")
-/// ```
-pub fn is_in_frustum_batch_unsized(
-    points: impl IntoIterator<Item = impl Into<Vec3A>>,
-    spaces: [HalfSpace; 6],
-) -> [bool; get_frustum_point_amount()] {
-    let mut results = [false; get_frustum_point_amount()];
+/// - `min`: The corner of the box with the smallest coordinates.
+/// - `max`: The corner of the box with the largest coordinates.
+/// - `spaces`: The half-spaces that define the frustum, see [`is_in_frustum()`].
+#[inline]
+pub fn is_box_in_frustum(min: Coordinates, max: Coordinates, spaces: &[HalfSpace; 6]) -> bool {
+    let (min, max) = (min.as_vec3a(), max.as_vec3a());
 
-    for (index, point) in points.into_iter().enumerate() {
-        let point = point.into();
-        let result = is_in_frustum(point, spaces);
+    (0..8).any(|corner: u8| {
+        let mask = BVec3A::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0);
 
-        results[index] = result;
-    }
-
-    results
+        is_in_frustum(Vec3A::select(mask, max, min), *spaces)
+    })
 }
 
-pub const fn get_frustum_point_amount() -> usize {
-    return 6;
-}
+#[cfg(test)]
+pub mod test {
+    use bevy::{prelude::Mat4, render::primitives::Frustum};
 
-/// Creates an array of frustum points based on the given position and dimensions.
-///
-/// This function calculates six frustum points that define the corners of a frustum.
-///
-/// # Arguments
-///
-/// * `pos` - The position of the frustum.
-/// * `dimensions` - The dimensions of the frustum (width, height, and depth).
-///
-/// # Returns
-///
-/// An array containing six `Vec3A` points that define the frustum corners.
-pub fn create_frustum_points(
-    IVec3 {
-        x: pos_x,
-        y: pos_y,
-        z: pos_z,
-    }: IVec3,
-    IVec3 {
-        x: width,
-        y: height,
-        z: depth,
-    }: IVec3,
-) -> [Vec3A; get_frustum_point_amount()] {
-    [
-        Vec3A::new(
-            (pos_x - width) as f32,
-            (pos_y - height) as f32,
-            (pos_z - depth) as f32,
-        ),
-        Vec3A::new(
-            (pos_x + width) as f32,
-            (pos_y + height) as f32,
-            (pos_z + depth) as f32,
-        ),
-        Vec3A::new(
-            (pos_x + width) as f32,
-            (pos_y - height) as f32,
-            (pos_z + depth) as f32,
-        ),
-        Vec3A::new(
-            (pos_x - width) as f32,
-            (pos_y + height) as f32,
-            (pos_z - depth) as f32,
-        ),
-        Vec3A::new(
-            (pos_x + width) as f32,
-            (pos_y + height) as f32,
-            (pos_z - depth) as f32,
-        ),
-        Vec3A::new(pos_x as f32, pos_y as f32, pos_z as f32),
-    ]
+    use super::is_box_in_frustum;
+    use crate::chunk::registry::Coordinates;
+
+    #[test]
+    fn test_box_corners() {
+        // looking down -z with a field of view of 90 degrees, so the view spans |x| <= -z.
+        let projection = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        let frustum = Frustum::from_view_projection(&projection);
+
+        let in_front = is_box_in_frustum(
+            Coordinates::new(-1, -1, -10),
+            Coordinates::new(1, 1, -5),
+            &frustum.half_spaces,
+        );
+        let behind = is_box_in_frustum(
+            Coordinates::new(-1, -1, 5),
+            Coordinates::new(1, 1, 10),
+            &frustum.half_spaces,
+        );
+
+        assert!(in_front);
+        assert!(!behind);
+
+        // only the corner at (max x, min y, min z) is inside, every corner has to be tested.
+        assert!(is_box_in_frustum(
+            Coordinates::new(-30, 5, -7),
+            Coordinates::new(5, 30, -3),
+            &frustum.half_spaces,
+        ));
+    }
 }
@@ -0,0 +1,117 @@
+use bevy::{
+    core_pipeline::{experimental::taa::TemporalAntiAliasBundle, fxaa::Fxaa},
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+};
+
+use crate::input::camera::PlayerController;
+
+/// The ways the edges of the voxels can be smoothed out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AntiAliasing {
+    Off,
+    #[default]
+    Fxaa,
+    // not every gpu supports 2 and 8 samples, 4 samples are always supported.
+    Msaa2,
+    Msaa4,
+    Msaa8,
+    // blends every frame with the previous ones, which can leave trails behind the transparent
+    // blocks (such as water) while moving.
+    Taa,
+}
+
+impl AntiAliasing {
+    pub const ALL: [AntiAliasing; 6] = [
+        AntiAliasing::Off,
+        AntiAliasing::Fxaa,
+        AntiAliasing::Msaa2,
+        AntiAliasing::Msaa4,
+        AntiAliasing::Msaa8,
+        AntiAliasing::Taa,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AntiAliasing::Off => "Off",
+            AntiAliasing::Fxaa => "FXAA",
+            AntiAliasing::Msaa2 => "MSAA 2x",
+            AntiAliasing::Msaa4 => "MSAA 4x",
+            AntiAliasing::Msaa8 => "MSAA 8x",
+            AntiAliasing::Taa => "TAA",
+        }
+    }
+
+    fn msaa(&self) -> Msaa {
+        match self {
+            AntiAliasing::Msaa2 => Msaa::Sample2,
+            AntiAliasing::Msaa4 => Msaa::Sample4,
+            AntiAliasing::Msaa8 => Msaa::Sample8,
+            // the post processing modes don't work together with multisampling.
+            AntiAliasing::Off | AntiAliasing::Fxaa | AntiAliasing::Taa => Msaa::Off,
+        }
+    }
+}
+
+/// The anti-aliasing of the camera, this is applied by `apply_anti_aliasing`.
+#[derive(Resource, Default, Clone, Debug, PartialEq)]
+pub struct AntiAliasingSettings {
+    pub mode: AntiAliasing,
+}
+
+/// The average frame time right before the anti-aliasing was last changed, which shows how much
+/// the current mode costs compared to the previous one.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct AntiAliasingBaseline {
+    pub mode: AntiAliasing,
+    // in milliseconds, see `FrameTimeDiagnosticsPlugin::FRAME_TIME`.
+    pub frame_time: f64,
+}
+
+/// Swaps out the anti-aliasing components of the camera and the `Msaa` resource whenever the
+/// `AntiAliasingSettings` change.
+pub fn apply_anti_aliasing(
+    mut commands: Commands,
+    settings: Res<AntiAliasingSettings>,
+    mut previous: Local<Option<AntiAliasing>>,
+    mut msaa: ResMut<Msaa>,
+    camera: Query<Entity, With<PlayerController>>,
+    diagnostics: Option<Res<DiagnosticsStore>>,
+) {
+    // swapping the components throws away the history of TAA and records a new baseline, so only
+    // once the mode itself differs; see `inspector_ui`.
+    if *previous == Some(settings.mode) {
+        return;
+    }
+
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+
+    // without the diagnostics plugins, there's simply no baseline to compare against.
+    let frame_time = diagnostics
+        .as_ref()
+        .and_then(|diagnostics| diagnostics.get(FrameTimeDiagnosticsPlugin::FRAME_TIME))
+        .and_then(|diagnostic| diagnostic.average());
+
+    if let (Some(mode), Some(frame_time)) = (*previous, frame_time) {
+        commands.insert_resource(AntiAliasingBaseline { mode, frame_time });
+    }
+
+    *previous = Some(settings.mode);
+    *msaa = settings.mode.msaa();
+
+    let mut camera = commands.entity(camera);
+
+    camera.remove::<(Fxaa, TemporalAntiAliasBundle)>();
+
+    match settings.mode {
+        AntiAliasing::Fxaa => {
+            camera.insert(Fxaa::default());
+        }
+        AntiAliasing::Taa => {
+            camera.insert(TemporalAntiAliasBundle::default());
+        }
+        _ => {}
+    }
+}
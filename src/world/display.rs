@@ -0,0 +1,299 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        renderer::{RenderAdapter, RenderInstance},
+        view::{ExtractedWindows, NonSendMarker},
+    },
+    window::{PresentMode, PrimaryWindow},
+};
+use parking_lot::RwLock;
+
+/// The ways a finished frame can be presented to the screen, see `PresentMode`.
+///
+/// The window can't be drawn to at all with a present mode the surface doesn't support, so
+/// `Mailbox` and `Immediate` are only offered once `SurfacePresentModes` says they're supported;
+/// the automatic ones fall back to `Fifo` when whatever they prefer isn't supported.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PresentMethod {
+    // presents every frame right away (`Immediate` or `Mailbox`) if the gpu can, otherwise the
+    // same as `Fifo`.
+    #[default]
+    AutoNoVsync,
+    // waits for the display to refresh (vsync), which is supported everywhere.
+    Fifo,
+    // the same as `Fifo`, but a frame that's late is presented right away (`FifoRelaxed`) if the
+    // gpu can, rather than waiting for the next refresh.
+    AutoVsync,
+    // only ever presents the newest frame at the next refresh, so there's no tearing but the
+    // frame rate isn't capped either.
+    Mailbox,
+    // presents every frame right away, which can tear.
+    Immediate,
+}
+
+impl PresentMethod {
+    pub const ALL: [PresentMethod; 5] = [
+        PresentMethod::AutoNoVsync,
+        PresentMethod::Fifo,
+        PresentMethod::AutoVsync,
+        PresentMethod::Mailbox,
+        PresentMethod::Immediate,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PresentMethod::AutoNoVsync => "No VSync",
+            PresentMethod::Fifo => "VSync",
+            PresentMethod::AutoVsync => "Adaptive VSync",
+            PresentMethod::Mailbox => "Mailbox",
+            PresentMethod::Immediate => "Immediate",
+        }
+    }
+
+    /// Whether the surface can be presented to with this method, the automatic ones and `Fifo`
+    /// work everywhere.
+    pub fn is_supported(&self, supported: &SurfacePresentModes) -> bool {
+        match self {
+            PresentMethod::Mailbox | PresentMethod::Immediate => {
+                supported.contains(self.present_mode())
+            }
+            _ => true,
+        }
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        match self {
+            PresentMethod::AutoNoVsync => PresentMode::AutoNoVsync,
+            PresentMethod::Fifo => PresentMode::Fifo,
+            PresentMethod::AutoVsync => PresentMode::AutoVsync,
+            PresentMethod::Mailbox => PresentMode::Mailbox,
+            PresentMethod::Immediate => PresentMode::Immediate,
+        }
+    }
+}
+
+/// The present modes the surface of the primary window supports, as reported by the gpu.
+///
+/// This is shared with the render world, which fills it in with `detect_present_modes` once the
+/// window has a surface. Until then nothing is reported, so only the methods that work
+/// everywhere are offered.
+#[derive(Resource, Clone, Default, Debug)]
+pub struct SurfacePresentModes(Arc<RwLock<Option<Vec<PresentMode>>>>);
+
+impl SurfacePresentModes {
+    pub fn is_detected(&self) -> bool {
+        self.0.read().is_some()
+    }
+
+    pub fn contains(&self, mode: PresentMode) -> bool {
+        self.0
+            .read()
+            .as_ref()
+            .is_some_and(|modes| modes.contains(&mode))
+    }
+
+    pub fn set(&self, modes: Vec<PresentMode>) {
+        *self.0.write() = Some(modes);
+    }
+}
+
+/// Asks the gpu which present modes the surface of the primary window supports, this runs in the
+/// render world until it has an answer.
+///
+/// Bevy keeps the surfaces it creates to itself, so this creates a surface of its own for the
+/// same window, only to read its capabilities; it's never configured.
+pub fn detect_present_modes(
+    // creating a surface has to happen on the main thread on some platforms, see
+    // `prepare_windows`.
+    _marker: NonSend<NonSendMarker>,
+    windows: Res<ExtractedWindows>,
+    instance: Res<RenderInstance>,
+    adapter: Res<RenderAdapter>,
+    supported: Res<SurfacePresentModes>,
+) {
+    if supported.is_detected() {
+        return;
+    }
+
+    let Some(window) = windows.primary.and_then(|primary| windows.get(&primary)) else {
+        return;
+    };
+
+    // safety: the window outlives the surface, which is dropped at the end of this scope.
+    let Ok(surface) = (unsafe { instance.create_surface(&window.handle.get_handle()) }) else {
+        return;
+    };
+
+    let modes = surface
+        .get_capabilities(&adapter)
+        .present_modes
+        .into_iter()
+        .filter_map(|mode| match mode {
+            wgpu::PresentMode::Fifo => Some(PresentMode::Fifo),
+            wgpu::PresentMode::FifoRelaxed => Some(PresentMode::FifoRelaxed),
+            wgpu::PresentMode::Mailbox => Some(PresentMode::Mailbox),
+            wgpu::PresentMode::Immediate => Some(PresentMode::Immediate),
+            // the automatic modes are never reported, they're picked from the ones above.
+            _ => None,
+        })
+        .collect();
+
+    supported.set(modes);
+}
+
+/// How (and how often) frames are presented, this is applied by `apply_present_method` and
+/// `limit_frame_rate`.
+#[derive(Resource, Default, Clone, Debug, PartialEq)]
+pub struct DisplaySettings {
+    pub present_method: PresentMethod,
+    // the most frames per second, on top of whatever the present method allows. the frame rate
+    // isn't limited at all at 0.
+    pub fps_cap: u32,
+}
+
+/// Updates the present mode of the primary window whenever the `DisplaySettings` change.
+///
+/// A method the surface doesn't support is presented with `PresentMethod::AutoNoVsync` instead,
+/// which is what `Mailbox` and `Immediate` are closest to.
+pub fn apply_present_method(
+    settings: Res<DisplaySettings>,
+    supported: Res<SurfacePresentModes>,
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let Ok(mut window) = window.get_single_mut() else {
+        return;
+    };
+
+    let method = match settings.present_method {
+        method if method.is_supported(&supported) => method,
+        _ => PresentMethod::AutoNoVsync,
+    };
+
+    // the surface gets configured again every time the window changes, so the window is only
+    // touched once the present mode differs; see `inspector_ui`.
+    let present_mode = method.present_mode();
+
+    if window.present_mode != present_mode {
+        window.present_mode = present_mode;
+    }
+}
+
+/// Sleeps at the end of every frame for as long as it takes to stay below
+/// `DisplaySettings::fps_cap`.
+///
+/// The sleep only ever makes a frame longer, so this doesn't make up for frames that were too
+/// slow; it only keeps the faster ones from going over the cap.
+pub fn limit_frame_rate(settings: Res<DisplaySettings>, mut previous: Local<Option<Instant>>) {
+    if let (Some(previous), 1..) = (*previous, settings.fps_cap) {
+        let target = Duration::from_secs_f64(1.0 / settings.fps_cap as f64);
+
+        if let Some(remaining) = target.checked_sub(previous.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    }
+
+    *previous = Some(Instant::now());
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn display_app(method: PresentMethod, supported: Vec<PresentMode>) -> App {
+        let mut app = App::new();
+        let present_modes = SurfacePresentModes::default();
+
+        present_modes.set(supported);
+
+        app.insert_resource(DisplaySettings {
+            present_method: method,
+            ..default()
+        })
+        .insert_resource(present_modes)
+        .add_systems(Update, apply_present_method);
+
+        app.world.spawn((
+            Window {
+                present_mode: PresentMode::Fifo,
+                ..default()
+            },
+            PrimaryWindow,
+        ));
+
+        app
+    }
+
+    fn present_mode(app: &mut App) -> PresentMode {
+        app.world
+            .query_filtered::<&Window, With<PrimaryWindow>>()
+            .single(&app.world)
+            .present_mode
+    }
+
+    #[test]
+    fn test_apply_present_method() {
+        let mut app = display_app(PresentMethod::AutoVsync, vec![PresentMode::Fifo]);
+        app.update();
+        assert_eq!(present_mode(&mut app), PresentMode::AutoVsync);
+
+        let mut app = display_app(
+            PresentMethod::Mailbox,
+            vec![PresentMode::Fifo, PresentMode::Mailbox],
+        );
+        app.update();
+        assert_eq!(present_mode(&mut app), PresentMode::Mailbox);
+
+        // the surface can't present with immediate, so it shouldn't be handed to the window.
+        let mut app = display_app(
+            PresentMethod::Immediate,
+            vec![PresentMode::Fifo, PresentMode::Mailbox],
+        );
+        app.update();
+        assert_eq!(present_mode(&mut app), PresentMode::AutoNoVsync);
+    }
+
+    #[test]
+    fn test_supported_before_detection() {
+        let supported = SurfacePresentModes::default();
+
+        for method in PresentMethod::ALL {
+            let always = !matches!(method, PresentMethod::Mailbox | PresentMethod::Immediate);
+            assert_eq!(method.is_supported(&supported), always, "{method:?}");
+        }
+    }
+
+    #[test]
+    fn test_limit_frame_rate() {
+        let mut app = App::new();
+
+        app.insert_resource(DisplaySettings {
+            fps_cap: 20,
+            ..default()
+        })
+        .add_systems(Update, limit_frame_rate);
+
+        // the first frame has nothing to compare against, so it's never slept.
+        app.update();
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            app.update();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(140));
+
+        // without a cap the frames shouldn't be slept at all.
+        app.world.resource_mut::<DisplaySettings>().fps_cap = 0;
+        app.update();
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            app.update();
+        }
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+}
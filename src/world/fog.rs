@@ -0,0 +1,69 @@
+use bevy::prelude::*;
+
+use crate::{
+    chunk::{registry::ChunkRegistry, DiscoverySettings},
+    input::camera::PlayerController,
+};
+
+/// The distance fog around the camera, which hides the edge of the loaded world.
+///
+/// The fog always ends right at the discovery radius, so the chunks fade out before they get
+/// unloaded (or before they pop in). By default the fog takes on the background color (the
+/// `ClearColor`), otherwise the fog would stand out against it at the horizon.
+#[derive(Resource, Clone)]
+pub struct FogConfig {
+    pub enabled: bool,
+    // the fraction of the discovery radius that's covered by fog, ranging from 0 (no fog at all)
+    // to 1 (the fog starts right at the camera).
+    pub density: f32,
+    // whether the fog uses the background color, rather than `color`.
+    pub match_background: bool,
+    pub color: Color,
+}
+
+impl Default for FogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            density: 0.3,
+            match_background: true,
+            // the default `ClearColor` of bevy.
+            color: Color::rgb(0.4, 0.4, 0.4),
+        }
+    }
+}
+
+/// Updates the fog of the camera whenever the `FogConfig`, the background color or the discovery
+/// radius changes.
+pub fn update_fog(
+    mut commands: Commands,
+    config: Res<FogConfig>,
+    discovery: Res<DiscoverySettings>,
+    clear_color: Res<ClearColor>,
+    camera: Query<Entity, With<PlayerController>>,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+
+    if !config.enabled {
+        commands.entity(camera).remove::<FogSettings>();
+        return;
+    }
+
+    // the chunks only get unloaded a chunk past the discovery radius, see `unload_distant_chunks`.
+    // the fog has to end before the edge of the chunks that are guaranteed to be loaded though.
+    let end = discovery.discovery_radius as f32 * ChunkRegistry::CHUNK_SIZE as f32;
+    let start = end * (1.0 - config.density.clamp(0.0, 1.0));
+
+    let color = match config.match_background {
+        true => clear_color.0,
+        false => config.color,
+    };
+
+    commands.entity(camera).insert(FogSettings {
+        color,
+        falloff: FogFalloff::Linear { start, end },
+        ..default()
+    });
+}
@@ -1,5 +1,23 @@
-use bevy::prelude::*;
+use bevy::{
+    core_pipeline::experimental::taa::TemporalAntiAliasPlugin,
+    prelude::*,
+    render::{view::WindowSystem, Render, RenderApp},
+};
 
+use crate::chunk::DiscoverySettings;
+
+use self::{
+    antialiasing::AntiAliasingSettings,
+    display::{DisplaySettings, SurfacePresentModes},
+    fog::FogConfig,
+    settings::SettingsEvent,
+    sky::LightingSettings,
+};
+
+pub mod antialiasing;
+pub mod display;
+pub mod fog;
+pub mod settings;
 pub mod sky;
 
 pub struct WorldPlugin;
@@ -8,5 +26,55 @@ impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, sky::setup_sky_lighting);
         app.add_systems(Update, sky::update_light_position);
+
+        app.init_resource::<LightingSettings>().add_systems(
+            Update,
+            sky::apply_lighting_settings.run_if(resource_changed::<LightingSettings>()),
+        );
+
+        app.add_plugins(TemporalAntiAliasPlugin)
+            .init_resource::<AntiAliasingSettings>()
+            .add_systems(
+                Update,
+                antialiasing::apply_anti_aliasing
+                    .run_if(resource_changed::<AntiAliasingSettings>()),
+            );
+
+        let present_modes = SurfacePresentModes::default();
+
+        // the render world is the only one that can see the surface, it fills this in for the ui.
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .insert_resource(present_modes.clone())
+                .add_systems(
+                    Render,
+                    display::detect_present_modes.after(WindowSystem::Prepare),
+                );
+        }
+
+        app.init_resource::<DisplaySettings>()
+            .insert_resource(present_modes)
+            .add_systems(
+                Update,
+                display::apply_present_method.run_if(resource_changed::<DisplaySettings>()),
+            )
+            // the frame is over by the time `Last` runs, so that's where the rest of it is slept.
+            .add_systems(Last, display::limit_frame_rate);
+
+        app.init_resource::<FogConfig>().add_systems(
+            Update,
+            fog::update_fog.run_if(
+                resource_changed::<FogConfig>()
+                    .or_else(resource_changed::<ClearColor>())
+                    .or_else(resource_changed::<DiscoverySettings>()),
+            ),
+        );
+
+        app.add_event::<SettingsEvent>()
+            .add_systems(Startup, settings::load_startup_settings)
+            .add_systems(
+                Update,
+                settings::handle_settings_events.run_if(on_event::<SettingsEvent>()),
+            );
     }
 }
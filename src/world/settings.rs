@@ -0,0 +1,222 @@
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chunk::{
+        events::discovery::ChunkDiscoveryEvent, noise_source::NoiseSource, registry::ChunkRegistry,
+        DiscoverySettings, GenerationSettings, MeshSettings,
+    },
+    input::camera::CameraSettings,
+};
+
+/// Where the settings are saved to by default, and loaded from on startup.
+pub const SETTINGS_PATH: &str = "settings.ron";
+
+/// Everything that's kept in a settings file, which makes for a preset of the world that can be
+/// shared with others.
+///
+/// Anything that's missing from the file (or from any of the settings within it) falls back to
+/// its default, so a file only has to contain the settings that differ from the defaults; see
+/// [`read_settings()`].
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldSettings {
+    pub generation: GenerationSettings,
+    pub discovery: DiscoverySettings,
+    pub mesh: MeshSettings,
+    pub camera: CameraSettings,
+    // the seed of the noise the terrain is generated with. the current seed is kept if this isn't
+    // set, so a preset can be applied to any world.
+    pub seed: Option<u32>,
+}
+
+/// Saves the current settings to (or loads them from) a settings file.
+#[derive(Event, Clone, Debug)]
+pub enum SettingsEvent {
+    Save(PathBuf),
+    Load(PathBuf),
+}
+
+/// Everything that can go wrong while reading or writing a settings file.
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    Serialize(ron::Error),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Io(error) => write!(f, "io error: {error}"),
+            SettingsError::Parse(error) => write!(f, "invalid settings: {error}"),
+            SettingsError::Serialize(error) => write!(f, "failed to serialize: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl From<io::Error> for SettingsError {
+    fn from(error: io::Error) -> Self {
+        SettingsError::Io(error)
+    }
+}
+
+/// Writes the settings as RON.
+pub fn write_settings(
+    settings: &WorldSettings,
+    writer: &mut impl Write,
+) -> Result<(), SettingsError> {
+    let text = ron::ser::to_string_pretty(settings, PrettyConfig::default())
+        .map_err(SettingsError::Serialize)?;
+
+    writer.write_all(text.as_bytes())?;
+
+    Ok(())
+}
+
+/// Reads settings that were written by [`write_settings()`], or written by hand.
+pub fn read_settings(reader: &mut impl Read) -> Result<WorldSettings, SettingsError> {
+    ron::de::from_reader(reader).map_err(SettingsError::Parse)
+}
+
+/// Loads the settings in [`SETTINGS_PATH`] on startup, if there are any.
+pub fn load_startup_settings(mut writer: EventWriter<SettingsEvent>) {
+    if Path::new(SETTINGS_PATH).exists() {
+        writer.send(SettingsEvent::Load(SETTINGS_PATH.into()));
+    }
+}
+
+/// Saves or loads the settings whenever a `SettingsEvent` comes in.
+///
+/// Loading the settings replaces every one of the settings resources, the world is regenerated
+/// and meshed again to match them. A file that can't be read is reported and ignored, leaving the
+/// current settings as they are.
+pub fn handle_settings_events(
+    mut reader: EventReader<SettingsEvent>,
+    (mut generation, mut discovery, mut mesh, mut camera): (
+        ResMut<GenerationSettings>,
+        ResMut<DiscoverySettings>,
+        ResMut<MeshSettings>,
+        ResMut<CameraSettings>,
+    ),
+    mut source: ResMut<NoiseSource>,
+    registry: Res<ChunkRegistry>,
+    mut discovery_writer: EventWriter<ChunkDiscoveryEvent>,
+) {
+    for event in reader.iter() {
+        match event {
+            SettingsEvent::Save(path) => {
+                let settings = WorldSettings {
+                    generation: generation.clone(),
+                    discovery: discovery.clone(),
+                    mesh: mesh.clone(),
+                    camera: camera.clone(),
+                    seed: Some(source.seed),
+                };
+
+                match write_settings_file(path, &settings) {
+                    Ok(()) => info!("saved the settings to {}", path.display()),
+                    Err(error) => {
+                        error!("failed to save the settings to {}: {error}", path.display())
+                    }
+                }
+            }
+            SettingsEvent::Load(path) => {
+                let settings = match File::open(path) {
+                    Ok(file) => read_settings(&mut BufReader::new(file)),
+                    Err(error) => Err(error.into()),
+                };
+
+                let settings = match settings {
+                    Ok(settings) => settings,
+                    Err(error) => {
+                        warn!("ignoring the settings at {}: {error}", path.display());
+                        continue;
+                    }
+                };
+
+                // the noise is rebuilt right away, rather than through `update_noise_source()`;
+                // this way the seed and the noise type change at the same time.
+                let seed = settings.seed.unwrap_or(source.seed);
+
+                if seed != source.seed || settings.generation.noise != source.noise_type() {
+                    *source = NoiseSource::new(settings.generation.noise, seed);
+                }
+
+                // a different seed or different generation settings regenerate the world by
+                // themselves, see `regenerate_outdated_chunks()`. the mesh settings don't, so
+                // every chunk is meshed again to be sure.
+                *generation = settings.generation;
+                *discovery = settings.discovery;
+                *mesh = settings.mesh;
+                *camera = settings.camera;
+
+                registry.for_each_chunk_mut(|chunk| chunk.set_dirty(true));
+                discovery_writer.send(ChunkDiscoveryEvent);
+
+                info!("loaded the settings from {}", path.display());
+            }
+        }
+    }
+}
+
+fn write_settings_file(path: &Path, settings: &WorldSettings) -> Result<(), SettingsError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    write_settings(settings, &mut File::create(path)?)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{read_settings, write_settings, WorldSettings};
+    use crate::chunk::{noise_source::NoiseType, DiscoveryShape, MeshTopology};
+
+    #[test]
+    fn test_roundtrip() {
+        let mut settings = WorldSettings {
+            seed: Some(1234),
+            ..Default::default()
+        };
+
+        settings.generation.noise = NoiseType::Billow;
+        settings.generation.height_bands.truncate(1);
+        settings.discovery.shape = DiscoveryShape::Sphere;
+        settings.mesh.topology = MeshTopology::Points;
+        settings.camera.speed = 0.5;
+
+        let mut bytes = Vec::new();
+        write_settings(&settings, &mut bytes).unwrap();
+
+        let loaded = read_settings(&mut bytes.as_slice()).unwrap();
+
+        assert!(loaded.generation == settings.generation);
+        assert_eq!(loaded.discovery.shape, DiscoveryShape::Sphere);
+        assert_eq!(loaded.mesh.topology, MeshTopology::Points);
+        assert_eq!(loaded.camera.speed, 0.5);
+        assert_eq!(loaded.seed, Some(1234));
+
+        // anything that's missing falls back to its default, including the fields of a setting.
+        let partial = "(generation: (sea_level: 20.0), mesh: (palette: true))";
+        let loaded = read_settings(&mut partial.as_bytes()).unwrap();
+
+        assert_eq!(loaded.generation.sea_level, 20.0);
+        assert_eq!(
+            loaded.generation.octaves,
+            WorldSettings::default().generation.octaves
+        );
+        assert!(loaded.mesh.palette);
+        assert_eq!(loaded.seed, None);
+    }
+}
@@ -5,21 +5,58 @@ use crate::input::camera::PlayerController;
 #[derive(Resource, Deref)]
 pub struct SkyLightEntity(Entity);
 
+/// The colors and brightness of the lights lighting the world, these are applied by
+/// `apply_lighting_settings`.
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct LightingSettings {
+    pub ambient_color: Color,
+    pub ambient_brightness: f32,
+    // disabling the directional light only turns it off, the light itself stays in place.
+    pub directional_enabled: bool,
+    pub directional_color: Color,
+    // in lux, see `DirectionalLight::illuminance`.
+    pub directional_illuminance: f32,
+}
+
+impl Default for LightingSettings {
+    fn default() -> Self {
+        Self {
+            ambient_color: Color::WHITE,
+            ambient_brightness: 0.7,
+            directional_enabled: true,
+            directional_color: Color::WHITE,
+            directional_illuminance: DirectionalLight::default().illuminance,
+        }
+    }
+}
+
 pub fn setup_sky_lighting(mut commands: Commands) {
     commands.spawn(DirectionalLightBundle {
         transform: Transform::IDENTITY.looking_to(Vec3::new(-1.0, -0.5, -1.0), Vec3::Y),
         directional_light: DirectionalLight {
-            color: Color::WHITE,
             shadows_enabled: true,
             ..Default::default()
         },
         ..Default::default()
     });
+}
 
-    commands.insert_resource(AmbientLight {
-        color: Color::WHITE,
-        brightness: 0.7,
-    });
+/// Applies the `LightingSettings` to the `AmbientLight` and every `DirectionalLight`.
+pub fn apply_lighting_settings(
+    settings: Res<LightingSettings>,
+    mut ambient: ResMut<AmbientLight>,
+    mut lights: Query<&mut DirectionalLight>,
+) {
+    ambient.color = settings.ambient_color;
+    ambient.brightness = settings.ambient_brightness;
+
+    for mut light in &mut lights {
+        light.color = settings.directional_color;
+        light.illuminance = match settings.directional_enabled {
+            true => settings.directional_illuminance,
+            false => 0.0,
+        };
+    }
 }
 
 pub fn update_light_position(
@@ -34,7 +71,11 @@ pub fn update_light_position(
         .map_or_else(|_| Default::default(), |player| player.translation);
 
     let mut binding = queries.p1();
-    let mut transform = binding.single_mut();
+
+    // there's no directional light until the startup systems have run.
+    let Ok(mut transform) = binding.get_single_mut() else {
+        return;
+    };
 
     transform.translation = translation;
 }